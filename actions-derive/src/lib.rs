@@ -0,0 +1,227 @@
+//! Derive macro for the inputs of GitHub Actions written in Rust.
+//!
+//! This is re-exported by the `rust-actions` crate with the `derive` feature, see its
+//! `inputs::ActionInputs` trait for the supported attributes.
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, Data, DeriveInput, Expr, Fields, GenericArgument, Lit, Meta, Type};
+
+/// Derives `ActionInputs`, parsing each field from an input.
+#[proc_macro_derive(ActionInputs, attributes(input))]
+pub fn derive_action_inputs(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    derive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// An input parsed into a field.
+struct Input {
+    field: syn::Ident,
+    /// The type parsed from the input, `T` for fields of type `Option<T>`.
+    parsed: Type,
+    optional: bool,
+    name: String,
+    description: String,
+    default: Option<String>,
+    validate: Option<syn::Path>,
+}
+
+impl Input {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let ident = field.ident.clone().expect("named field");
+        let (parsed, optional) = match option_item(&field.ty) {
+            Some(item) => (item.clone(), true),
+            None => (field.ty.clone(), false),
+        };
+        let mut input = Self {
+            name: ident.to_string().trim_start_matches("r#").replace('_', "-"),
+            field: ident,
+            parsed,
+            optional,
+            description: doc_comment(&field.attrs),
+            default: None,
+            validate: None,
+        };
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("input"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    input.name = meta.value()?.parse::<syn::LitStr>()?.value();
+                } else if meta.path.is_ident("description") {
+                    input.description = meta.value()?.parse::<syn::LitStr>()?.value();
+                } else if meta.path.is_ident("default") {
+                    let value = match meta.value()?.parse::<Lit>()? {
+                        Lit::Str(lit) => lit.value(),
+                        Lit::Int(lit) => lit.base10_digits().to_owned(),
+                        Lit::Float(lit) => lit.base10_digits().to_owned(),
+                        Lit::Bool(lit) => lit.value.to_string(),
+                        lit => return Err(syn::Error::new(lit.span(), "unsupported default")),
+                    };
+                    input.default = Some(value);
+                } else if meta.path.is_ident("validate") {
+                    input.validate = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unknown input attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(input)
+    }
+
+    fn required(&self) -> bool {
+        !self.optional && self.default.is_none()
+    }
+
+    /// Returns the expression parsing the input.
+    fn parse(&self) -> TokenStream {
+        let Self {
+            field,
+            parsed,
+            name,
+            ..
+        } = self;
+        let default = match &self.default {
+            Some(default) => quote!(::std::option::Option::Some(#default)),
+            None => quote!(::std::option::Option::None),
+        };
+        let validate = match &self.validate {
+            Some(validate) => quote!(#validate),
+            None => quote!(|_: &#parsed| ::std::result::Result::Ok(())),
+        };
+        let value = quote! {
+            ::rust_actions::inputs::__derive::field::<#parsed>(#name, #default, #validate)?
+        };
+        if self.optional {
+            quote!(#field: #value)
+        } else {
+            quote! {
+                #field: #value.ok_or_else(|| {
+                    ::rust_actions::inputs::InputError::Missing(::std::string::String::from(#name))
+                })?
+            }
+        }
+    }
+
+    /// Appends the input's entry of the `inputs` section to `yml`.
+    fn write_yml(&self, yml: &mut String) {
+        yml.push_str(&format!("  {}:\n", yaml_key(&self.name)));
+        yml.push_str(&format!("    description: {}\n", quoted(&self.description)));
+        yml.push_str(&format!("    required: {}\n", self.required()));
+        if let Some(default) = &self.default {
+            yml.push_str(&format!("    default: {}\n", quoted(default)));
+        }
+    }
+}
+
+fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "ActionInputs can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(
+            data.fields.span(),
+            "ActionInputs can only be derived for structs with named fields",
+        ));
+    };
+    let inputs = fields
+        .named
+        .iter()
+        .map(Input::from_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let mut yml = String::from(if inputs.is_empty() {
+        "inputs: {}\n"
+    } else {
+        "inputs:\n"
+    });
+    for input in &inputs {
+        input.write_yml(&mut yml);
+    }
+    let fields = inputs.iter().map(Input::parse);
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rust_actions::inputs::ActionInputs for #ident #ty_generics
+        #where_clause
+        {
+            fn from_inputs() -> ::std::result::Result<Self, ::rust_actions::inputs::InputError> {
+                ::std::result::Result::Ok(Self { #(#fields,)* })
+            }
+
+            fn action_yml() -> &'static str {
+                #yml
+            }
+        }
+    })
+}
+
+/// Returns `T` if `ty` is `Option<T>`.
+fn option_item(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(item) if args.args.len() == 1 => Some(item),
+        _ => None,
+    }
+}
+
+/// Joins the lines of the doc comments among `attrs`.
+fn doc_comment(attrs: &[syn::Attribute]) -> String {
+    let mut doc = String::new();
+    for attr in attrs {
+        let Meta::NameValue(meta) = &attr.meta else {
+            continue;
+        };
+        if !meta.path.is_ident("doc") {
+            continue;
+        }
+        if let Expr::Lit(syn::ExprLit {
+            lit: Lit::Str(lit), ..
+        }) = &meta.value
+        {
+            let line = lit.value();
+            let line = line.trim();
+            if !line.is_empty() {
+                if !doc.is_empty() {
+                    doc.push(' ');
+                }
+                doc.push_str(line);
+            }
+        }
+    }
+    doc
+}
+
+/// Returns `value` as single-quoted YAML scalar.
+fn quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Returns `name` as YAML mapping key, quoted unless it consists of safe characters only.
+fn yaml_key(name: &str) -> String {
+    let plain = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if plain {
+        name.to_owned()
+    } else {
+        quoted(name)
+    }
+}
@@ -0,0 +1,434 @@
+//! Workflow commands, written to stdout for the runner to interpret.
+//!
+//! Commands are lines of the form `::name key=value,...::message`. Messages and property values
+//! are escaped, so arbitrary text can't end a command early or inject another one. See the
+//! [workflow commands] documentation for the available commands.
+//!
+//! [workflow commands]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+use std::{
+    fmt,
+    io::{self, Write},
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::random;
+
+/// A workflow command with its properties and message.
+#[derive(Clone, Debug)]
+pub struct Command {
+    name: String,
+    properties: Vec<(&'static str, String)>,
+    message: String,
+}
+
+impl Command {
+    /// Creates a command without properties.
+    pub fn new(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            properties: vec![],
+            message: message.into(),
+        }
+    }
+
+    /// Adds a property, keeping the order in which properties are added.
+    pub fn property(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.properties.push((key, value.to_string()));
+        self
+    }
+
+    /// Writes the command to stdout as a single line.
+    pub fn issue(&self) {
+        let _ = self.write_to(&mut io::stdout().lock());
+    }
+
+    /// Writes the command to `writer`, followed by a newline.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", self)?;
+        writer.flush()
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "::{}", self.name)?;
+        for (i, (key, value)) in self.properties.iter().enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            write!(f, "{}{}={}", separator, key, escape_property(value))?;
+        }
+        write!(f, "::{}", escape_data(&self.message))
+    }
+}
+
+/// Severity of an [`Annotation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// Shown as error, without failing the step on its own.
+    Error,
+    /// Shown as warning.
+    Warning,
+    /// Shown as notice.
+    Notice,
+}
+
+impl Level {
+    fn command(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Notice => "notice",
+        }
+    }
+}
+
+/// An annotation, shown in the run's summary and, with a file location, next to the code of a
+/// pull request.
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    level: Level,
+    message: String,
+    title: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    end_line: Option<u32>,
+    column: Option<u32>,
+    end_column: Option<u32>,
+}
+
+impl Annotation {
+    /// Creates an annotation of the given severity.
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            title: None,
+            file: None,
+            line: None,
+            end_line: None,
+            column: None,
+            end_column: None,
+        }
+    }
+
+    /// Creates an error annotation.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Level::Error, message)
+    }
+
+    /// Creates a warning annotation.
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Level::Warning, message)
+    }
+
+    /// Creates a notice annotation.
+    pub fn notice(message: impl Into<String>) -> Self {
+        Self::new(Level::Notice, message)
+    }
+
+    /// Sets the title, shown instead of the default title of the severity.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Sets the file, relative to the repository root.
+    pub fn file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Sets the line within the file, starting at 1.
+    pub fn line(mut self, line: u32) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// Sets the last line of a range of lines.
+    pub fn end_line(mut self, end_line: u32) -> Self {
+        self.end_line = Some(end_line);
+        self
+    }
+
+    /// Sets the column within the line, starting at 1.
+    pub fn column(mut self, column: u32) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Sets the last column of a range within a line.
+    pub fn end_column(mut self, end_column: u32) -> Self {
+        self.end_column = Some(end_column);
+        self
+    }
+
+    /// Returns the workflow command creating this annotation.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(self.level.command(), self.message.clone());
+        let properties = [
+            ("title", self.title.clone()),
+            ("file", self.file.clone()),
+            ("line", self.line.map(|line| line.to_string())),
+            ("endLine", self.end_line.map(|line| line.to_string())),
+            ("col", self.column.map(|column| column.to_string())),
+            (
+                "endColumn",
+                self.end_column.map(|column| column.to_string()),
+            ),
+        ];
+        for (key, value) in properties {
+            if let Some(value) = value {
+                command = command.property(key, value);
+            }
+        }
+        command
+    }
+
+    /// Writes the annotation to stdout.
+    pub fn issue(&self) {
+        self.command().issue()
+    }
+}
+
+/// Writes an error annotation without location to stdout.
+pub fn error(message: impl Into<String>) {
+    Annotation::error(message).issue()
+}
+
+/// Writes a warning annotation without location to stdout.
+pub fn warning(message: impl Into<String>) {
+    Annotation::warning(message).issue()
+}
+
+/// Writes a notice annotation without location to stdout.
+pub fn notice(message: impl Into<String>) {
+    Annotation::notice(message).issue()
+}
+
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Writes an error annotation and marks the step as failed, like `core.setFailed`.
+///
+/// The step keeps running, its exit status is only set by returning [`exit_code`] from `main`.
+pub fn set_failed(message: impl Into<String>) {
+    error(message);
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+/// Returns whether the step was marked as failed, see [`set_failed`].
+pub fn is_failed() -> bool {
+    FAILED.load(Ordering::Relaxed)
+}
+
+/// Returns the exit status for the step, a failure if it was marked as failed.
+pub fn exit_code() -> ExitCode {
+    if is_failed() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Marks the step as failed and returns an error to return from `main`, see [`Failed`].
+pub fn fail(message: impl Into<String>) -> Failed {
+    let message = message.into();
+    set_failed(message.clone());
+    Failed(message)
+}
+
+/// The error of a failed step, returned from `main` to exit with a failure.
+///
+/// Any error converts into this with `?`, writing it as error annotation, so within
+/// `fn main() -> Result<(), Failed>` failing steps show their error like those of other actions.
+/// The message is also what `main` prints to stderr. Errors are converted including their
+/// sources, joined by `: `.
+pub struct Failed(String);
+
+impl Failed {
+    /// The message of the error annotation.
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<E: std::error::Error> From<E> for Failed {
+    fn from(err: E) -> Self {
+        let mut message = err.to_string();
+        let mut source = err.source();
+        while let Some(err) = source {
+            message.push_str(": ");
+            message.push_str(&err.to_string());
+            source = err.source();
+        }
+        fail(message)
+    }
+}
+
+impl fmt::Debug for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Returns whether step debug logging is enabled, by the `ACTIONS_STEP_DEBUG` secret or variable
+/// or by re-running a job with debug logging (`RUNNER_DEBUG`).
+pub fn is_debug() -> bool {
+    ["ACTIONS_STEP_DEBUG", "RUNNER_DEBUG"]
+        .iter()
+        .any(|name| matches!(std::env::var(name).as_deref(), Ok("true" | "1")))
+}
+
+/// Writes a debug message to stdout if step debug logging is enabled, see [`is_debug`].
+pub fn debug(message: impl Into<String>) {
+    if is_debug() {
+        Command::new("debug", message).issue()
+    }
+}
+
+/// Writes a formatted debug message to stdout if step debug logging is enabled.
+///
+/// The arguments are only formatted when the message is written, see
+/// [`commands::is_debug`](crate::commands::is_debug).
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if $crate::commands::is_debug() {
+            $crate::commands::Command::new("debug", ::std::format!($($arg)+)).issue()
+        }
+    };
+}
+
+/// Starts a collapsible group of log lines, ended when the returned guard is dropped.
+///
+/// The group also ends when the guard is dropped while unwinding from a panic, so the panic
+/// message isn't hidden inside the folded group. Groups can't be nested, starting a new group
+/// implicitly ends the current one.
+#[must_use = "the group ends when the guard is dropped"]
+pub fn group(name: impl Into<String>) -> Group {
+    Command::new("group", name).issue();
+    Group { _private: () }
+}
+
+/// Guard of a log group started with [`group`].
+#[derive(Debug)]
+pub struct Group {
+    _private: (),
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        Command::new("endgroup", "").issue();
+    }
+}
+
+/// Stops processing of workflow commands until the returned guard is dropped.
+///
+/// Use this around output that is not trusted, like the content of restored files, so it can't
+/// inject commands. The runner resumes processing when it sees the random token chosen here,
+/// which the untrusted output can't predict. Like [`group`], the guard also resumes processing
+/// when dropped during a panic.
+#[must_use = "command processing resumes when the guard is dropped"]
+pub fn stop_commands() -> StopCommands {
+    let token = random::token();
+    Command::new("stop-commands", token.as_str()).issue();
+    StopCommands { token }
+}
+
+/// Guard of stopped command processing, see [`stop_commands`].
+#[derive(Debug)]
+pub struct StopCommands {
+    token: String,
+}
+
+impl StopCommands {
+    /// The token that resumes command processing.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Drop for StopCommands {
+    fn drop(&mut self) {
+        Command::new(self.token.as_str(), "").issue();
+    }
+}
+
+/// Masks `value` in all further log output of the job.
+///
+/// Multiline values are additionally masked line by line, as the log is processed per line.
+/// Empty values are ignored, as they would mask nothing useful.
+pub fn add_mask(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    Command::new("add-mask", value).issue();
+    if value.contains(['\n', '\r']) {
+        for line in value.lines().map(|line| line.trim_end_matches('\r')) {
+            if !line.is_empty() {
+                Command::new("add-mask", line).issue();
+            }
+        }
+    }
+}
+
+/// A secret string that is masked in the log as soon as it is created.
+///
+/// Its `Debug` and `Display` implementations print `***` instead of the value, use
+/// [`expose`][Self::expose] to access it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MaskedString(String);
+
+impl MaskedString {
+    /// Masks `value` and wraps it.
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        add_mask(&value);
+        Self(value)
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the secret value, consuming the wrapper.
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for MaskedString {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl fmt::Debug for MaskedString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MaskedString(***)")
+    }
+}
+
+impl fmt::Display for MaskedString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+/// Escapes the message of a workflow command.
+pub fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+pub fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,167 @@
+//! The context of the current workflow run, from the runner's default environment variables.
+use std::path::PathBuf;
+
+/// A required environment variable that was missing or malformed.
+#[derive(Debug, thiserror::Error)]
+pub enum ContextError {
+    /// A variable that is not set, e.g. because this doesn't run within a workflow.
+    #[error("missing environment variable {0}")]
+    Missing(&'static str),
+    /// A variable that is set to an unexpected value.
+    #[error("invalid value {value:?} for environment variable {variable}")]
+    Invalid {
+        /// Name of the variable.
+        variable: &'static str,
+        /// Its value.
+        value: String,
+    },
+}
+
+/// The kind of a git ref, parsed from `GITHUB_REF`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefKind {
+    /// A branch, `refs/heads/{name}`.
+    Branch(String),
+    /// A tag, `refs/tags/{name}`.
+    Tag(String),
+    /// The merge or head ref of a pull request, `refs/pull/{number}/merge`.
+    PullRequest(u64),
+    /// Any other ref.
+    Other(String),
+}
+
+impl RefKind {
+    /// Parses a full ref name.
+    pub fn parse(git_ref: &str) -> Self {
+        if let Some(branch) = git_ref.strip_prefix("refs/heads/") {
+            return RefKind::Branch(branch.to_owned());
+        }
+        if let Some(tag) = git_ref.strip_prefix("refs/tags/") {
+            return RefKind::Tag(tag.to_owned());
+        }
+        if let Some(pull) = git_ref.strip_prefix("refs/pull/") {
+            if let Some(Ok(number)) = pull.split('/').next().map(str::parse) {
+                return RefKind::PullRequest(number);
+            }
+        }
+        RefKind::Other(git_ref.to_owned())
+    }
+}
+
+/// The context of the current workflow run, like the `github` context of workflow expressions.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Context {
+    /// Owner of the repository, from `GITHUB_REPOSITORY`.
+    pub owner: String,
+    /// Name of the repository without owner, from `GITHUB_REPOSITORY`.
+    pub repo: String,
+    /// The event that triggered the run, e.g. `push` (`GITHUB_EVENT_NAME`).
+    pub event_name: String,
+    /// Path of the file containing the event's webhook payload (`GITHUB_EVENT_PATH`).
+    pub event_path: Option<PathBuf>,
+    /// The commit the run is for (`GITHUB_SHA`).
+    pub sha: String,
+    /// The full ref the run is for, e.g. `refs/heads/main` (`GITHUB_REF`).
+    pub git_ref: Option<String>,
+    /// The parsed [`git_ref`][Self::git_ref].
+    pub ref_kind: Option<RefKind>,
+    /// Head branch of a pull request (`GITHUB_HEAD_REF`).
+    pub head_ref: Option<String>,
+    /// Base branch of a pull request (`GITHUB_BASE_REF`).
+    pub base_ref: Option<String>,
+    /// Name of the workflow (`GITHUB_WORKFLOW`).
+    pub workflow: String,
+    /// Id of the current job (`GITHUB_JOB`).
+    pub job: String,
+    /// Id of the run, unique within the repository (`GITHUB_RUN_ID`).
+    pub run_id: u64,
+    /// Number of the run, counting runs of the workflow (`GITHUB_RUN_NUMBER`).
+    pub run_number: u64,
+    /// Attempt of the run, starting at 1 (`GITHUB_RUN_ATTEMPT`).
+    pub run_attempt: u64,
+    /// User that triggered the run (`GITHUB_ACTOR`).
+    pub actor: String,
+    /// Directory of the checked out repository (`GITHUB_WORKSPACE`).
+    pub workspace: Option<PathBuf>,
+    /// URL of the GitHub server, e.g. `https://github.com` (`GITHUB_SERVER_URL`).
+    pub server_url: String,
+    /// URL of the REST API (`GITHUB_API_URL`).
+    pub api_url: String,
+    /// URL of the GraphQL API (`GITHUB_GRAPHQL_URL`).
+    pub graphql_url: String,
+}
+
+impl Context {
+    /// Reads the context from the environment.
+    pub fn from_env() -> Result<Self, ContextError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the context from variables returned by `var`, e.g. to test an action outside of a
+    /// workflow run.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ContextError> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        let required = |name: &'static str| var(name).ok_or(ContextError::Missing(name));
+        let number = |name: &'static str, default: Option<u64>| match var(name) {
+            Some(value) => value.parse().map_err(|_| ContextError::Invalid {
+                variable: name,
+                value,
+            }),
+            None => default.ok_or(ContextError::Missing(name)),
+        };
+
+        let repository = required("GITHUB_REPOSITORY")?;
+        let (owner, repo) = match repository.split_once('/') {
+            Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() => {
+                (owner.to_owned(), repo.to_owned())
+            }
+            _ => {
+                return Err(ContextError::Invalid {
+                    variable: "GITHUB_REPOSITORY",
+                    value: repository,
+                })
+            }
+        };
+        let git_ref = var("GITHUB_REF");
+
+        Ok(Self {
+            owner,
+            repo,
+            event_name: required("GITHUB_EVENT_NAME")?,
+            event_path: var("GITHUB_EVENT_PATH").map(PathBuf::from),
+            sha: required("GITHUB_SHA")?,
+            ref_kind: git_ref.as_deref().map(RefKind::parse),
+            git_ref,
+            head_ref: var("GITHUB_HEAD_REF"),
+            base_ref: var("GITHUB_BASE_REF"),
+            workflow: var("GITHUB_WORKFLOW").unwrap_or_default(),
+            job: var("GITHUB_JOB").unwrap_or_default(),
+            run_id: number("GITHUB_RUN_ID", None)?,
+            run_number: number("GITHUB_RUN_NUMBER", Some(1))?,
+            run_attempt: number("GITHUB_RUN_ATTEMPT", Some(1))?,
+            actor: var("GITHUB_ACTOR").unwrap_or_default(),
+            workspace: var("GITHUB_WORKSPACE").map(PathBuf::from),
+            server_url: var("GITHUB_SERVER_URL").unwrap_or_else(|| "https://github.com".to_owned()),
+            api_url: var("GITHUB_API_URL").unwrap_or_else(|| "https://api.github.com".to_owned()),
+            graphql_url: var("GITHUB_GRAPHQL_URL")
+                .unwrap_or_else(|| "https://api.github.com/graphql".to_owned()),
+        })
+    }
+
+    /// Returns the repository as `owner/repo`.
+    pub fn repository(&self) -> String {
+        format!("{}/{}", self.owner, self.repo)
+    }
+
+    /// Returns the URL of the run's page.
+    pub fn run_url(&self) -> String {
+        format!(
+            "{}/{}/{}/actions/runs/{}",
+            self.server_url.trim_end_matches('/'),
+            self.owner,
+            self.repo,
+            self.run_id
+        )
+    }
+}
@@ -0,0 +1,304 @@
+//! Webhook payloads of the events that trigger workflow runs.
+//!
+//! Only the most commonly needed fields are typed. The complete payload is always available as
+//! JSON value, see [`EventPayload::raw`].
+use std::{collections::BTreeMap, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use crate::context::Context;
+
+/// A payload that could not be read.
+#[derive(Debug, thiserror::Error)]
+pub enum EventError {
+    /// `GITHUB_EVENT_PATH` is not set, e.g. because this doesn't run within a workflow.
+    #[error("no event payload, GITHUB_EVENT_PATH is not set")]
+    NoPayload,
+    /// The payload file could not be read.
+    #[error("could not read event payload: {0}")]
+    Io(#[from] io::Error),
+    /// The payload does not match the event.
+    #[error("malformed payload of {event} event: {source}")]
+    Malformed {
+        /// Name of the event.
+        event: String,
+        /// The JSON error.
+        source: serde_json::Error,
+    },
+}
+
+/// The payload of the event that triggered the run, typed for common events.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// A `push` event.
+    Push(Box<PushEvent>),
+    /// A `pull_request` or `pull_request_target` event.
+    PullRequest(Box<PullRequestEvent>),
+    /// A `workflow_dispatch` event, i.e. a manually started run.
+    WorkflowDispatch(Box<WorkflowDispatchEvent>),
+    /// A `schedule` event.
+    Schedule(ScheduleEvent),
+    /// Any other event, see [`EventPayload::raw`].
+    Other,
+}
+
+/// The parsed payload of an event together with its JSON value.
+#[derive(Clone, Debug)]
+pub struct EventPayload {
+    /// Name of the event, e.g. `push`.
+    pub name: String,
+    /// The typed payload.
+    pub event: Event,
+    /// The complete payload.
+    pub raw: Value,
+}
+
+impl EventPayload {
+    /// Reads the payload of the event that triggered the current run.
+    pub fn from_context(context: &Context) -> Result<Self, EventError> {
+        let path = context.event_path.as_ref().ok_or(EventError::NoPayload)?;
+        Self::load(&context.event_name, path)
+    }
+
+    /// Reads the payload of the event `name` from a file.
+    pub fn load(name: &str, path: impl AsRef<Path>) -> Result<Self, EventError> {
+        let json = std::fs::read(path)?;
+        let raw = serde_json::from_slice(&json).map_err(|source| EventError::Malformed {
+            event: name.to_owned(),
+            source,
+        })?;
+        Self::parse(name, raw)
+    }
+
+    /// Parses the payload of the event `name`.
+    pub fn parse(name: &str, raw: Value) -> Result<Self, EventError> {
+        fn typed<T: DeserializeOwned>(name: &str, raw: &Value) -> Result<T, EventError> {
+            T::deserialize(raw).map_err(|source| EventError::Malformed {
+                event: name.to_owned(),
+                source,
+            })
+        }
+        let event = match name {
+            "push" => Event::Push(typed(name, &raw)?),
+            "pull_request" | "pull_request_target" => Event::PullRequest(typed(name, &raw)?),
+            "workflow_dispatch" => Event::WorkflowDispatch(typed(name, &raw)?),
+            "schedule" => Event::Schedule(typed(name, &raw)?),
+            _ => Event::Other,
+        };
+        Ok(Self {
+            name: name.to_owned(),
+            event,
+            raw,
+        })
+    }
+}
+
+/// A GitHub user or organization.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct User {
+    /// The login name.
+    pub login: String,
+    /// The numeric id.
+    pub id: u64,
+}
+
+/// A repository.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Repository {
+    /// The numeric id.
+    pub id: u64,
+    /// Name without owner.
+    pub name: String,
+    /// Name as `owner/repo`.
+    pub full_name: String,
+    /// Owner of the repository.
+    pub owner: User,
+    /// Whether the repository is private.
+    #[serde(default)]
+    pub private: bool,
+    /// The default branch, e.g. `main`.
+    #[serde(default)]
+    pub default_branch: Option<String>,
+    /// URL of the repository's page.
+    #[serde(default)]
+    pub html_url: Option<String>,
+}
+
+/// Author or committer of a pushed commit.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct GitUser {
+    /// The name in the git metadata.
+    pub name: String,
+    /// The email address in the git metadata.
+    pub email: String,
+    /// The GitHub user, if the email address belongs to one.
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// A commit of a push.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Commit {
+    /// The commit's hash.
+    pub id: String,
+    /// The commit message.
+    pub message: String,
+    /// The commit's timestamp.
+    pub timestamp: String,
+    /// The author.
+    pub author: GitUser,
+}
+
+/// Payload of a `push` event.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct PushEvent {
+    /// The full ref that was pushed, e.g. `refs/heads/main`.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// Commit the ref pointed to before the push, all zeros for new refs.
+    pub before: String,
+    /// Commit the ref points to after the push, all zeros for deleted refs.
+    pub after: String,
+    /// Whether the push created the ref.
+    #[serde(default)]
+    pub created: bool,
+    /// Whether the push deleted the ref.
+    #[serde(default)]
+    pub deleted: bool,
+    /// Whether the push was forced.
+    #[serde(default)]
+    pub forced: bool,
+    /// The pushed commits, at most 20.
+    #[serde(default)]
+    pub commits: Vec<Commit>,
+    /// The most recent pushed commit.
+    #[serde(default)]
+    pub head_commit: Option<Commit>,
+    /// The repository.
+    pub repository: Repository,
+    /// The user that pushed.
+    #[serde(default)]
+    pub sender: Option<User>,
+}
+
+/// A label of a pull request.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Label {
+    /// The label's name.
+    pub name: String,
+}
+
+/// Head or base of a pull request.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct PullRequestRef {
+    /// The branch name.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// The commit the branch pointed to.
+    pub sha: String,
+    /// The branch as `owner:branch`.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The repository, unset if a fork was deleted.
+    #[serde(default)]
+    pub repo: Option<Repository>,
+}
+
+/// A pull request.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct PullRequest {
+    /// The number of the pull request.
+    pub number: u64,
+    /// The title.
+    pub title: String,
+    /// The description.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// `open` or `closed`.
+    pub state: String,
+    /// Whether the pull request is a draft.
+    #[serde(default)]
+    pub draft: bool,
+    /// Whether the pull request was merged.
+    #[serde(default)]
+    pub merged: bool,
+    /// The user that opened the pull request.
+    pub user: User,
+    /// The branch to merge.
+    pub head: PullRequestRef,
+    /// The branch to merge into.
+    pub base: PullRequestRef,
+    /// The labels.
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    /// URL of the pull request's page.
+    #[serde(default)]
+    pub html_url: Option<String>,
+}
+
+impl PullRequest {
+    /// Returns whether the head branch is in a different repository than the base branch.
+    ///
+    /// Workflows run for pull requests from forks have no access to secrets.
+    pub fn is_fork(&self) -> bool {
+        match (&self.head.repo, &self.base.repo) {
+            (Some(head), Some(base)) => head.id != base.id,
+            _ => true,
+        }
+    }
+}
+
+/// Payload of a `pull_request` or `pull_request_target` event.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct PullRequestEvent {
+    /// The activity, e.g. `opened` or `synchronize`.
+    pub action: String,
+    /// The number of the pull request.
+    pub number: u64,
+    /// The pull request.
+    pub pull_request: PullRequest,
+    /// The repository.
+    pub repository: Repository,
+    /// The user that caused the event.
+    #[serde(default)]
+    pub sender: Option<User>,
+}
+
+/// Payload of a `workflow_dispatch` event.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct WorkflowDispatchEvent {
+    /// The full ref the workflow was started for.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// Path of the workflow file.
+    #[serde(default)]
+    pub workflow: Option<String>,
+    /// The inputs given when starting the workflow, strings or booleans.
+    #[serde(default)]
+    pub inputs: BTreeMap<String, Value>,
+    /// The repository.
+    pub repository: Repository,
+    /// The user that started the workflow.
+    #[serde(default)]
+    pub sender: Option<User>,
+}
+
+/// Payload of a `schedule` event.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct ScheduleEvent {
+    /// The cron expression of the schedule that triggered the run.
+    pub schedule: String,
+}
@@ -0,0 +1,141 @@
+//! Environment files, the runner's replacement for the deprecated `set-output`, `set-env`,
+//! `add-path` and `save-state` commands.
+//!
+//! Each is a file named by an environment variable that steps append lines to. Values spanning
+//! multiple lines use a heredoc with a random delimiter, so they can't be confused with
+//! following entries.
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{commands::Command, random};
+
+/// Sets the output `name` of the current step, for later steps and dependent jobs.
+///
+/// Outside of the runner, i.e. when `GITHUB_OUTPUT` is not set, this falls back to the
+/// deprecated `set-output` command like the official toolkit does.
+pub fn set_output(name: &str, value: &str) -> io::Result<()> {
+    if !append_key_value("GITHUB_OUTPUT", name, value)? {
+        Command::new("set-output", value)
+            .property("name", name)
+            .issue();
+    }
+    Ok(())
+}
+
+/// Sets the environment variable `name` for all later steps of the job, and for this process.
+///
+/// Names have to consist of ASCII letters, digits and underscores, not starting with a digit.
+/// The runner refuses to set `NODE_OPTIONS` this way, so that is rejected too. Outside of the
+/// runner, i.e. when `GITHUB_ENV` is not set, this falls back to the deprecated `set-env`
+/// command like the official toolkit does.
+pub fn export_env(name: &str, value: &str) -> io::Result<()> {
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && !name.eq_ignore_ascii_case("NODE_OPTIONS");
+    if !valid || name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid environment variable name {:?}", name),
+        ));
+    }
+
+    if !append_key_value("GITHUB_ENV", name, value)? {
+        Command::new("set-env", value)
+            .property("name", name)
+            .issue();
+    }
+    std::env::set_var(name, value);
+    Ok(())
+}
+
+/// Prepends `dir` to the `PATH` of all later steps of the job, and of this process.
+///
+/// Outside of the runner, i.e. when `GITHUB_PATH` is not set, this falls back to the deprecated
+/// `add-path` command like the official toolkit does.
+pub fn add_path(dir: impl AsRef<Path>) -> io::Result<()> {
+    let dir = dir.as_ref();
+    let line = dir.to_str().filter(|line| !line.contains(['\n', '\r']));
+    let Some(line) = line else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("path {:?} can't be added to PATH", dir),
+        ));
+    };
+
+    let mut paths = vec![dir.to_owned()];
+    if let Some(current) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&current));
+    }
+    let joined = std::env::join_paths(paths)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    match std::env::var_os("GITHUB_PATH") {
+        Some(path) => {
+            let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        None => Command::new("add-path", line).issue(),
+    }
+    std::env::set_var("PATH", joined);
+    Ok(())
+}
+
+/// Saves state for the post step of this action, see [`get_state`].
+///
+/// Outside of the runner, i.e. when `GITHUB_STATE` is not set, this falls back to the deprecated
+/// `save-state` command like the official toolkit does.
+pub fn save_state(name: &str, value: &str) -> io::Result<()> {
+    if !append_key_value("GITHUB_STATE", name, value)? {
+        Command::new("save-state", value)
+            .property("name", name)
+            .issue();
+    }
+    Ok(())
+}
+
+/// Returns state saved by an earlier step of this action with [`save_state`].
+///
+/// The runner passes state as `STATE_{name}` environment variables, e.g. so a post step can save
+/// a cache restored by the main step under the key it computed.
+pub fn get_state(name: &str) -> Option<String> {
+    std::env::var(format!("STATE_{}", name)).ok()
+}
+
+/// Appends a `name=value` entry to the environment file named by `variable`.
+///
+/// Returns `false` without writing anything if `variable` is not set, after validating `name`.
+pub(crate) fn append_key_value(variable: &str, name: &str, value: &str) -> io::Result<bool> {
+    let entry = key_value(name, value)?;
+    let Some(path) = std::env::var_os(variable) else {
+        return Ok(false);
+    };
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(entry.as_bytes())?;
+    Ok(true)
+}
+
+/// Formats an entry, using a heredoc for values that aren't a single line.
+fn key_value(name: &str, value: &str) -> io::Result<String> {
+    if name.is_empty() || name.contains(['=', '<', '\n', '\r']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid name {:?} for an environment file entry", name),
+        ));
+    }
+    if !value.contains(['\n', '\r']) {
+        return Ok(format!("{}={}\n", name, value));
+    }
+    let delimiter = loop {
+        let delimiter = format!("ghadelimiter_{}", random::token());
+        if !value.contains(&delimiter) {
+            break delimiter;
+        }
+    };
+    Ok(format!(
+        "{}<<{}\n{}\n{}\n",
+        name, delimiter, value, delimiter
+    ))
+}
@@ -0,0 +1,168 @@
+//! Inputs of an action, as configured by the `with` section of a workflow step.
+//!
+//! The runner passes inputs as `INPUT_*` environment variables. Names are mapped like the
+//! official toolkit does, upper-casing them and replacing spaces with underscores, and values are
+//! trimmed. Inputs that are unset or empty count as not supplied, which is also what the runner
+//! passes for optional inputs without a default.
+//!
+//! With the `derive` feature, [`ActionInputs`] can be derived for a struct with one field per
+//! input.
+use std::path::PathBuf;
+
+#[cfg(feature = "derive")]
+pub use rust_actions_derive::ActionInputs;
+
+/// An input that was missing or could not be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum InputError {
+    /// A required input was not supplied.
+    #[error("input required and not supplied: {0}")]
+    Missing(String),
+    /// An input with a value that could not be parsed.
+    #[error("invalid value {value:?} for input {name}: {reason}")]
+    Invalid {
+        /// Name of the input.
+        name: String,
+        /// The supplied value.
+        value: String,
+        /// Why the value could not be parsed.
+        reason: String,
+    },
+}
+
+/// Types that can be parsed from the value of an input.
+pub trait FromInput: Sized {
+    /// Parses a trimmed, non-empty value, returning the reason it is invalid on failure.
+    fn from_input(value: &str) -> Result<Self, String>;
+}
+
+impl FromInput for String {
+    fn from_input(value: &str) -> Result<Self, String> {
+        Ok(value.to_owned())
+    }
+}
+
+impl FromInput for PathBuf {
+    fn from_input(value: &str) -> Result<Self, String> {
+        Ok(value.into())
+    }
+}
+
+/// Accepts booleans of the YAML 1.2 core schema, like `getBooleanInput`.
+impl FromInput for bool {
+    fn from_input(value: &str) -> Result<Self, String> {
+        match value {
+            "true" | "True" | "TRUE" => Ok(true),
+            "false" | "False" | "FALSE" => Ok(false),
+            _ => Err("expected `true` or `false`".to_owned()),
+        }
+    }
+}
+
+macro_rules! from_str_inputs {
+    ($($ty:ty),*) => {
+        $(
+            impl FromInput for $ty {
+                fn from_input(value: &str) -> Result<Self, String> {
+                    value.parse().map_err(|err| format!("{}", err))
+                }
+            }
+        )*
+    };
+}
+
+from_str_inputs!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+/// Parses one item per line, ignoring empty lines, like `getMultilineInput`.
+impl<T: FromInput> FromInput for Vec<T> {
+    fn from_input(value: &str) -> Result<Self, String> {
+        value
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(T::from_input)
+            .collect()
+    }
+}
+
+/// Returns the name of the environment variable holding the input `name`.
+pub fn variable_name(name: &str) -> String {
+    format!("INPUT_{}", name.replace(' ', "_").to_uppercase())
+}
+
+/// Returns the trimmed value of the input `name`, or `None` if it was not supplied.
+pub fn get_raw(name: &str) -> Option<String> {
+    let value = std::env::var(variable_name(name)).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Parses the input `name`, returning `None` if it was not supplied.
+pub fn get<T: FromInput>(name: &str) -> Result<Option<T>, InputError> {
+    get_raw(name)
+        .map(|value| {
+            T::from_input(&value).map_err(|reason| InputError::Invalid {
+                name: name.to_owned(),
+                value,
+                reason,
+            })
+        })
+        .transpose()
+}
+
+/// Parses the required input `name`.
+pub fn required<T: FromInput>(name: &str) -> Result<T, InputError> {
+    get(name)?.ok_or_else(|| InputError::Missing(name.to_owned()))
+}
+
+/// Parses the input `name`, returning `default` if it was not supplied.
+pub fn get_or<T: FromInput>(name: &str, default: T) -> Result<T, InputError> {
+    Ok(get(name)?.unwrap_or(default))
+}
+
+/// All inputs of an action, parsed at once.
+///
+/// With the `derive` feature, this can be derived for structs with named fields, each an input
+/// whose type implements [`FromInput`]. Fields of type `Option<T>` are optional, all others are
+/// required unless they have a default. Inputs are named after their fields, with underscores
+/// replaced by dashes, and their descriptions are taken from the fields' doc comments. Fields
+/// accept these attributes:
+///
+/// * `#[input(rename = "name")]` sets the name of the input.
+/// * `#[input(default = "value")]` sets the value used when the input is not supplied, parsed
+///   like a supplied one.
+/// * `#[input(validate = path)]` checks parsed values with a function taking a reference to the
+///   value and returning `Result<(), String>`, the error being the reason the value is invalid.
+/// * `#[input(description = "text")]` sets the description instead of the doc comment.
+pub trait ActionInputs: Sized {
+    /// Parses the inputs, failing on the first that is missing or invalid.
+    fn from_inputs() -> Result<Self, InputError>;
+
+    /// Returns the `inputs` section of the action's metadata file, `action.yml`.
+    fn action_yml() -> &'static str;
+}
+
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __derive {
+    use super::{get_raw, FromInput, InputError};
+
+    /// Parses an input for derived [`ActionInputs`](super::ActionInputs) implementations.
+    pub fn field<T: FromInput>(
+        name: &str,
+        default: Option<&str>,
+        validate: impl FnOnce(&T) -> Result<(), String>,
+    ) -> Result<Option<T>, InputError> {
+        let Some(value) = get_raw(name).or_else(|| default.map(str::to_owned)) else {
+            return Ok(None);
+        };
+        let invalid = |reason| InputError::Invalid {
+            name: name.to_owned(),
+            value: value.clone(),
+            reason,
+        };
+        let parsed = T::from_input(&value).map_err(invalid)?;
+        validate(&parsed).map_err(invalid)?;
+        Ok(Some(parsed))
+    }
+}
@@ -0,0 +1,163 @@
+//! A [`tracing`] layer writing events as workflow commands, available with the `tracing`
+//! feature.
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write as _},
+    sync::Mutex,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+use crate::commands::{self, Annotation, Command};
+
+/// A layer making instrumented code look native in the log of a workflow run.
+///
+/// Errors and warnings become annotations, info events are printed as plain lines and debug and
+/// trace events are written as `debug` commands, which the runner only shows when step debug
+/// logging is enabled. Fields named `file`, `line`, `col` and `title` set the corresponding
+/// properties of annotations, all other fields are appended to the message.
+///
+/// Top-level spans become log groups while they exist. As groups can't be nested, spans created
+/// while a group is open don't start another one.
+#[derive(Debug)]
+pub struct WorkflowLayer {
+    debug: bool,
+    groups: bool,
+    group: Mutex<Option<span::Id>>,
+}
+
+impl WorkflowLayer {
+    /// Creates a layer, writing debug events if step debug logging is enabled.
+    pub fn new() -> Self {
+        Self {
+            debug: commands::is_debug(),
+            groups: true,
+            group: Mutex::new(None),
+        }
+    }
+
+    /// Sets whether debug and trace events are written.
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Sets whether top-level spans become log groups, enabled by default.
+    pub fn with_groups(mut self, groups: bool) -> Self {
+        self.groups = groups;
+        self
+    }
+}
+
+impl Default for WorkflowLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for WorkflowLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !self.groups {
+            return;
+        }
+        let root = attrs.is_root() || (attrs.is_contextual() && ctx.current_span().id().is_none());
+        let mut group = self.group.lock().unwrap();
+        if root && group.is_none() {
+            Command::new("group", attrs.metadata().name()).issue();
+            *group = Some(id.clone());
+        }
+    }
+
+    fn on_close(&self, id: span::Id, _ctx: Context<'_, S>) {
+        let mut group = self.group.lock().unwrap();
+        if group.as_ref() == Some(&id) {
+            Command::new("endgroup", "").issue();
+            *group = None;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::INFO && !self.debug {
+            return;
+        }
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields.message();
+
+        let mut annotation = match level {
+            Level::ERROR => Annotation::error(message),
+            Level::WARN => Annotation::warning(message),
+            Level::INFO => {
+                let _ = writeln!(io::stdout().lock(), "{}", message);
+                return;
+            }
+            _ => return Command::new("debug", message).issue(),
+        };
+        if let Some(title) = fields.title {
+            annotation = annotation.title(title);
+        }
+        if let Some(file) = fields.file {
+            annotation = annotation.file(file);
+        }
+        if let Some(line) = fields.line {
+            annotation = annotation.line(line);
+        }
+        if let Some(column) = fields.column {
+            annotation = annotation.column(column);
+        }
+        annotation.issue();
+    }
+}
+
+/// Fields of an event, split into the message, annotation properties and everything else.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+    title: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+}
+
+impl Fields {
+    fn message(&self) -> String {
+        match (self.message.is_empty(), self.rest.is_empty()) {
+            (_, true) => self.message.clone(),
+            (true, false) => self.rest.clone(),
+            (false, false) => format!("{} {}", self.message, self.rest),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.message = value,
+            "title" => self.title = Some(value),
+            "file" => self.file = Some(value),
+            "line" => self.line = value.parse().ok(),
+            "col" => self.column = value.parse().ok(),
+            name => {
+                if !self.rest.is_empty() {
+                    self.rest.push(' ');
+                }
+                let _ = write!(self.rest, "{}={}", name, value);
+            }
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        Fields::record_str(self, field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        Fields::record_str(self, field, format!("{:?}", value));
+    }
+}
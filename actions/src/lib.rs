@@ -1,3 +1,16 @@
+pub mod commands;
+pub mod context;
+pub mod event;
+pub mod files;
+pub mod inputs;
+#[cfg(feature = "tracing")]
+pub mod layer;
+pub mod matchers;
+pub mod oidc;
+mod random;
+pub mod runner;
+pub mod summary;
+
 pub fn main() {
     println!("Hello, world!");
 }
@@ -0,0 +1,213 @@
+//! Problem matchers, which turn matching lines of a step's output into annotations.
+//!
+//! A matcher is registered with the `add-matcher` command, naming a JSON file that describes
+//! regular expressions and which of their groups hold the file, line, message and so on. See the
+//! runner's [problem matcher] documentation for the format.
+//!
+//! [problem matcher]: https://github.com/actions/toolkit/blob/main/docs/problem-matchers.md
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::commands::Command;
+
+/// Registers the problem matchers in the JSON file at `path`.
+pub fn add_matcher(path: impl AsRef<Path>) {
+    Command::new("add-matcher", path.as_ref().to_string_lossy()).issue();
+}
+
+/// Unregisters the problem matcher with the given owner.
+pub fn remove_matcher(owner: &str) {
+    Command::new("remove-matcher", "")
+        .property("owner", owner)
+        .issue();
+}
+
+/// Severity assigned to matched problems.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Error annotations.
+    Error,
+    /// Warning annotations.
+    Warning,
+    /// Notice annotations.
+    Notice,
+}
+
+/// A regular expression matching one line of a problem, with the groups holding its parts.
+///
+/// Group numbers start at 1.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pattern {
+    regexp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_path: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<u32>,
+    #[serde(rename = "loop", skip_serializing_if = "std::ops::Not::not")]
+    repeat: bool,
+}
+
+impl Pattern {
+    /// Creates a pattern with the given regular expression, in JavaScript syntax.
+    pub fn new(regexp: impl Into<String>) -> Self {
+        Self {
+            regexp: regexp.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the group holding the file.
+    pub fn file(mut self, group: u32) -> Self {
+        self.file = Some(group);
+        self
+    }
+
+    /// Sets the group holding a path the file is relative to.
+    pub fn from_path(mut self, group: u32) -> Self {
+        self.from_path = Some(group);
+        self
+    }
+
+    /// Sets the group holding the line.
+    pub fn line(mut self, group: u32) -> Self {
+        self.line = Some(group);
+        self
+    }
+
+    /// Sets the group holding the last line.
+    pub fn end_line(mut self, group: u32) -> Self {
+        self.end_line = Some(group);
+        self
+    }
+
+    /// Sets the group holding the column.
+    pub fn column(mut self, group: u32) -> Self {
+        self.column = Some(group);
+        self
+    }
+
+    /// Sets the group holding the last column.
+    pub fn end_column(mut self, group: u32) -> Self {
+        self.end_column = Some(group);
+        self
+    }
+
+    /// Sets the group holding the severity, `error`, `warning` or `notice`.
+    pub fn severity(mut self, group: u32) -> Self {
+        self.severity = Some(group);
+        self
+    }
+
+    /// Sets the group holding an error code.
+    pub fn code(mut self, group: u32) -> Self {
+        self.code = Some(group);
+        self
+    }
+
+    /// Sets the group holding the message.
+    pub fn message(mut self, group: u32) -> Self {
+        self.message = Some(group);
+        self
+    }
+
+    /// Makes the last pattern of a matcher match repeatedly, one problem per matching line.
+    pub fn repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+}
+
+/// A problem matcher, a sequence of patterns matching consecutive lines.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProblemMatcher {
+    owner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<Severity>,
+    pattern: Vec<Pattern>,
+}
+
+impl ProblemMatcher {
+    /// Creates a matcher without patterns, identified by `owner`.
+    pub fn new(owner: impl Into<String>) -> Self {
+        Self {
+            owner: owner.into(),
+            severity: None,
+            pattern: vec![],
+        }
+    }
+
+    /// Sets the severity of problems without a severity group, `error` by default.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    /// Appends a pattern.
+    pub fn pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern.push(pattern);
+        self
+    }
+
+    /// The owner identifying this matcher.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MatcherFile<'a> {
+    problem_matcher: &'a [ProblemMatcher],
+}
+
+/// Returns the JSON describing the given matchers.
+pub fn to_json(matchers: &[ProblemMatcher]) -> String {
+    serde_json::to_string_pretty(&MatcherFile {
+        problem_matcher: matchers,
+    })
+    .expect("problem matchers serialize")
+}
+
+/// Writes the given matchers to `path`.
+pub fn write(path: impl AsRef<Path>, matchers: &[ProblemMatcher]) -> io::Result<()> {
+    std::fs::write(path, to_json(matchers))
+}
+
+/// Writes the given matchers to a file in the runner's temporary directory and registers them,
+/// returning the file's path.
+///
+/// The file is named after the owner of the first matcher, so registering again replaces it.
+pub fn register(matchers: &[ProblemMatcher]) -> io::Result<PathBuf> {
+    let dir = std::env::var_os("RUNNER_TEMP").map_or_else(std::env::temp_dir, PathBuf::from);
+    let owner = matchers
+        .first()
+        .map_or("matchers", |matcher| &matcher.owner);
+    let name: String = owner
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let path = dir.join(format!("{}-problem-matcher.json", name));
+    write(&path, matchers)?;
+    add_matcher(&path);
+    Ok(path)
+}
@@ -0,0 +1,296 @@
+//! OpenID Connect tokens identifying the workflow run, e.g. to authenticate to cloud providers.
+//!
+//! Tokens are only available to jobs with the `id-token: write` permission. The claims are
+//! decoded so they can be checked against what the caller expects before the token is passed
+//! on, see [`Expectations`]. Their signature is not verified here; the token comes directly from
+//! the runner's token service, and whoever it is handed to verifies it.
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::commands::MaskedString;
+
+/// An ID token that could not be obtained or did not meet expectations.
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    /// The runner provides no token, as the job lacks the `id-token: write` permission.
+    #[error(
+        "no ID token available, ACTIONS_ID_TOKEN_REQUEST_URL and ACTIONS_ID_TOKEN_REQUEST_TOKEN \
+         are only set for jobs with the `id-token: write` permission"
+    )]
+    NotAvailable,
+    /// The request to the token service failed.
+    #[error("requesting an ID token failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// A token that isn't a JWT with the expected claims.
+    #[error("malformed ID token: {0}")]
+    Malformed(String),
+    /// A token that has expired.
+    #[error("the ID token has expired")]
+    Expired,
+    /// A claim that doesn't have the expected value.
+    #[error("ID token claim {claim} is {actual:?}, expected {expected:?}")]
+    Mismatch {
+        /// Name of the claim.
+        claim: &'static str,
+        /// The expected value.
+        expected: String,
+        /// The value in the token.
+        actual: String,
+    },
+}
+
+/// Audience of a token, a single one or a list.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    /// A single audience.
+    One(String),
+    /// Multiple audiences.
+    Many(Vec<String>),
+}
+
+impl Audience {
+    /// Returns whether `audience` is among the token's audiences.
+    pub fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::One(one) => one == audience,
+            Audience::Many(many) => many.iter().any(|one| one == audience),
+        }
+    }
+}
+
+/// The claims of an ID token issued for a workflow run.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Claims {
+    /// The issuer, `https://token.actions.githubusercontent.com` for github.com.
+    pub iss: String,
+    /// The subject, e.g. `repo:owner/repo:ref:refs/heads/main`.
+    pub sub: String,
+    /// The audience, by default the URL of the repository owner.
+    pub aud: Audience,
+    /// Expiry, in seconds since the Unix epoch.
+    pub exp: u64,
+    /// Time of issue, in seconds since the Unix epoch.
+    pub iat: u64,
+    /// The repository as `owner/repo`.
+    pub repository: String,
+    /// Owner of the repository.
+    pub repository_owner: String,
+    /// The full ref the run is for.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// `branch` or `tag`.
+    #[serde(default)]
+    pub ref_type: Option<String>,
+    /// The commit the run is for.
+    pub sha: String,
+    /// Name of the workflow.
+    pub workflow: String,
+    /// Ref of the workflow file, as `owner/repo/path@ref`, also for reusable workflows.
+    #[serde(default)]
+    pub job_workflow_ref: Option<String>,
+    /// The deployment environment of the job, if any.
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// The event that triggered the run.
+    pub event_name: String,
+    /// The id of the run.
+    pub run_id: String,
+    /// The user that triggered the run.
+    pub actor: String,
+}
+
+impl Claims {
+    /// Returns the time the token expires.
+    pub fn expires(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.exp)
+    }
+}
+
+/// Values that claims of a token have to match, see [`IdToken::validate`].
+#[derive(Clone, Debug, Default)]
+pub struct Expectations {
+    audience: Option<String>,
+    repository: Option<String>,
+    git_ref: Option<String>,
+    workflow: Option<String>,
+    environment: Option<String>,
+}
+
+impl Expectations {
+    /// Creates expectations that any unexpired token meets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires `audience` to be among the token's audiences.
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Requires the repository, as `owner/repo`.
+    pub fn repository(mut self, repository: impl Into<String>) -> Self {
+        self.repository = Some(repository.into());
+        self
+    }
+
+    /// Requires the full ref, e.g. `refs/heads/main`.
+    pub fn git_ref(mut self, git_ref: impl Into<String>) -> Self {
+        self.git_ref = Some(git_ref.into());
+        self
+    }
+
+    /// Requires the workflow's name.
+    pub fn workflow(mut self, workflow: impl Into<String>) -> Self {
+        self.workflow = Some(workflow.into());
+        self
+    }
+
+    /// Requires the job's deployment environment.
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+}
+
+/// An ID token together with its decoded claims.
+#[derive(Clone)]
+pub struct IdToken {
+    token: MaskedString,
+    claims: Claims,
+    raw_claims: Value,
+}
+
+impl fmt::Debug for IdToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdToken")
+            .field("claims", &self.claims)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    value: String,
+}
+
+impl IdToken {
+    /// Requests a token for the given audience from the runner's token service.
+    ///
+    /// The token is masked in the log.
+    pub async fn request(audience: Option<&str>) -> Result<Self, OidcError> {
+        let (Ok(url), Ok(request_token)) = (
+            std::env::var("ACTIONS_ID_TOKEN_REQUEST_URL"),
+            std::env::var("ACTIONS_ID_TOKEN_REQUEST_TOKEN"),
+        ) else {
+            return Err(OidcError::NotAvailable);
+        };
+        let mut url =
+            reqwest::Url::parse(&url).map_err(|err| OidcError::Malformed(err.to_string()))?;
+        if let Some(audience) = audience {
+            url.query_pairs_mut().append_pair("audience", audience);
+        }
+
+        let response: TokenResponse = reqwest::Client::new()
+            .get(url)
+            .bearer_auth(request_token)
+            .header(reqwest::header::ACCEPT, "application/json; api-version=2.0")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Self::decode(MaskedString::new(response.value))
+    }
+
+    /// Decodes the claims of a token, without verifying its signature.
+    pub fn decode(token: MaskedString) -> Result<Self, OidcError> {
+        let payload = token
+            .expose()
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| OidcError::Malformed("not a JWT".to_owned()))?;
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|err| OidcError::Malformed(err.to_string()))?;
+        let raw_claims: Value = serde_json::from_slice(&payload)
+            .map_err(|err| OidcError::Malformed(err.to_string()))?;
+        let claims = Claims::deserialize(&raw_claims)
+            .map_err(|err| OidcError::Malformed(err.to_string()))?;
+        Ok(Self {
+            token,
+            claims,
+            raw_claims,
+        })
+    }
+
+    /// The encoded token, to pass on.
+    pub fn token(&self) -> &MaskedString {
+        &self.token
+    }
+
+    /// The decoded claims.
+    pub fn claims(&self) -> &Claims {
+        &self.claims
+    }
+
+    /// All claims, including those not typed by [`Claims`].
+    pub fn raw_claims(&self) -> &Value {
+        &self.raw_claims
+    }
+
+    /// Checks that the token has not expired and its claims meet `expected`.
+    pub fn validate(&self, expected: &Expectations) -> Result<(), OidcError> {
+        if self.claims.expires() <= SystemTime::now() {
+            return Err(OidcError::Expired);
+        }
+
+        if let Some(audience) = &expected.audience {
+            if !self.claims.aud.contains(audience) {
+                return Err(OidcError::Mismatch {
+                    claim: "aud",
+                    expected: audience.clone(),
+                    actual: match &self.claims.aud {
+                        Audience::One(one) => one.clone(),
+                        Audience::Many(many) => many.join(", "),
+                    },
+                });
+            }
+        }
+
+        let checks = [
+            (
+                "repository",
+                &expected.repository,
+                Some(&self.claims.repository),
+            ),
+            ("ref", &expected.git_ref, Some(&self.claims.git_ref)),
+            ("workflow", &expected.workflow, Some(&self.claims.workflow)),
+            (
+                "environment",
+                &expected.environment,
+                self.claims.environment.as_ref(),
+            ),
+        ];
+        for (claim, expected, actual) in checks {
+            if let Some(expected) = expected {
+                if actual != Some(expected) {
+                    return Err(OidcError::Mismatch {
+                        claim,
+                        expected: expected.clone(),
+                        actual: actual.cloned().unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
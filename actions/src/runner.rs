@@ -0,0 +1,142 @@
+//! The runner executing the current job, from the `RUNNER_*` environment variables.
+use std::path::PathBuf;
+
+/// Operating system of a runner (`RUNNER_OS`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Os {
+    /// Linux.
+    Linux,
+    /// Windows.
+    Windows,
+    /// macOS.
+    MacOs,
+    /// Any other value.
+    Other(String),
+}
+
+impl Os {
+    fn parse(os: &str) -> Self {
+        match os {
+            "Linux" => Os::Linux,
+            "Windows" => Os::Windows,
+            "macOS" => Os::MacOs,
+            _ => Os::Other(os.to_owned()),
+        }
+    }
+}
+
+/// Architecture of a runner (`RUNNER_ARCH`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Arch {
+    /// 32-bit x86.
+    X86,
+    /// 64-bit x86.
+    X64,
+    /// 32-bit ARM.
+    Arm,
+    /// 64-bit ARM.
+    Arm64,
+    /// Any other value.
+    Other(String),
+}
+
+impl Arch {
+    fn parse(arch: &str) -> Self {
+        match arch {
+            "X86" => Arch::X86,
+            "X64" => Arch::X64,
+            "ARM" => Arch::Arm,
+            "ARM64" => Arch::Arm64,
+            _ => Arch::Other(arch.to_owned()),
+        }
+    }
+}
+
+/// Who provides a runner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hosting {
+    /// A runner hosted by GitHub, a fresh virtual machine for each job.
+    GitHub,
+    /// A self-hosted runner, which can keep state like caches between jobs.
+    SelfHosted,
+}
+
+/// The runner executing the current job.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Runner {
+    /// The operating system.
+    pub os: Os,
+    /// The architecture.
+    pub arch: Arch,
+    /// The runner's name (`RUNNER_NAME`).
+    pub name: String,
+    /// Who provides the runner.
+    pub hosting: Hosting,
+    /// Directory for temporary files, emptied for each job (`RUNNER_TEMP`).
+    pub temp: PathBuf,
+    /// Directory of preinstalled tools (`RUNNER_TOOL_CACHE`).
+    pub tool_cache: Option<PathBuf>,
+    /// Whether debug logging is enabled (`RUNNER_DEBUG`).
+    pub debug: bool,
+}
+
+impl Runner {
+    /// Detects the runner from the environment.
+    ///
+    /// Outside of a runner, the operating system and architecture are those this was compiled
+    /// for and the temporary directory is the system's.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Detects the runner from variables returned by `var`.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+
+        let os = match var("RUNNER_OS") {
+            Some(os) => Os::parse(&os),
+            None => match std::env::consts::OS {
+                "linux" => Os::Linux,
+                "windows" => Os::Windows,
+                "macos" => Os::MacOs,
+                os => Os::Other(os.to_owned()),
+            },
+        };
+        let arch = match var("RUNNER_ARCH") {
+            Some(arch) => Arch::parse(&arch),
+            None => match std::env::consts::ARCH {
+                "x86" => Arch::X86,
+                "x86_64" => Arch::X64,
+                "arm" => Arch::Arm,
+                "aarch64" => Arch::Arm64,
+                arch => Arch::Other(arch.to_owned()),
+            },
+        };
+        let name = var("RUNNER_NAME").unwrap_or_default();
+        let hosting = match var("RUNNER_ENVIRONMENT").as_deref() {
+            Some("github-hosted") => Hosting::GitHub,
+            Some(_) => Hosting::SelfHosted,
+            // Older runners don't set RUNNER_ENVIRONMENT, but GitHub's images set ImageOS.
+            None if var("ImageOS").is_some() || name.starts_with("GitHub Actions") => {
+                Hosting::GitHub
+            }
+            None => Hosting::SelfHosted,
+        };
+
+        Self {
+            os,
+            arch,
+            name,
+            hosting,
+            temp: var("RUNNER_TEMP").map_or_else(std::env::temp_dir, PathBuf::from),
+            tool_cache: var("RUNNER_TOOL_CACHE").map(PathBuf::from),
+            debug: var("RUNNER_DEBUG").as_deref() == Some("1"),
+        }
+    }
+
+    /// Returns whether the runner is hosted by GitHub.
+    pub fn is_github_hosted(&self) -> bool {
+        self.hosting == Hosting::GitHub
+    }
+}
@@ -0,0 +1,221 @@
+//! Job summaries, markdown shown on the summary page of a workflow run.
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{self, Write as _},
+};
+
+/// Size limit of a step's summary, larger summaries are not shown at all.
+pub const MAX_SIZE: usize = 1024 * 1024;
+
+const TRUNCATED: &str = "\n\n*Summary truncated, it exceeded the size limit.*\n";
+
+/// Builds the summary of the current step.
+///
+/// Content is collected in memory and appended to the file named by `GITHUB_STEP_SUMMARY` using
+/// [`write`][Self::write]. Text passed to the builder methods is escaped, use
+/// [`raw`][Self::raw] to add markdown or HTML directly.
+#[derive(Clone, Debug, Default)]
+pub struct StepSummary {
+    buffer: String,
+}
+
+impl StepSummary {
+    /// Creates an empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds markdown or HTML without escaping.
+    pub fn raw(&mut self, content: &str) -> &mut Self {
+        self.buffer.push_str(content);
+        self
+    }
+
+    /// Adds a heading of the given level, clamped to 1 to 6.
+    pub fn heading(&mut self, level: u8, text: &str) -> &mut Self {
+        let level = level.clamp(1, 6);
+        self.block(&format!("<h{0}>{1}</h{0}>", level, escape(text)))
+    }
+
+    /// Adds a paragraph of text.
+    pub fn paragraph(&mut self, text: &str) -> &mut Self {
+        self.block(&format!("<p>{}</p>", escape(text)))
+    }
+
+    /// Adds a link.
+    pub fn link(&mut self, text: &str, url: &str) -> &mut Self {
+        let _ = write!(
+            self.buffer,
+            "<a href=\"{}\">{}</a>",
+            escape(url),
+            escape(text)
+        );
+        self
+    }
+
+    /// Adds a list, numbered if `ordered`.
+    pub fn list<I: AsRef<str>>(
+        &mut self,
+        items: impl IntoIterator<Item = I>,
+        ordered: bool,
+    ) -> &mut Self {
+        let tag = if ordered { "ol" } else { "ul" };
+        let mut html = format!("<{}>", tag);
+        for item in items {
+            let _ = write!(html, "<li>{}</li>", escape(item.as_ref()));
+        }
+        let _ = write!(html, "</{}>", tag);
+        self.block(&html)
+    }
+
+    /// Adds a table with a header row.
+    pub fn table<R, C>(&mut self, header: &[&str], rows: impl IntoIterator<Item = R>) -> &mut Self
+    where
+        R: IntoIterator<Item = C>,
+        C: AsRef<str>,
+    {
+        let mut html = String::from("<table><tr>");
+        for cell in header {
+            let _ = write!(html, "<th>{}</th>", escape(cell));
+        }
+        html.push_str("</tr>");
+        for row in rows {
+            html.push_str("<tr>");
+            for cell in row {
+                let _ = write!(html, "<td>{}</td>", escape(cell.as_ref()));
+            }
+            html.push_str("</tr>");
+        }
+        html.push_str("</table>");
+        self.block(&html)
+    }
+
+    /// Adds a collapsed section with the given label, containing markdown or HTML.
+    pub fn details(&mut self, label: &str, content: &str) -> &mut Self {
+        self.block(&format!(
+            "<details><summary>{}</summary>\n\n{}\n\n</details>",
+            escape(label),
+            content
+        ))
+    }
+
+    /// Adds a code block, highlighted as `language` if given.
+    pub fn code_block(&mut self, code: &str, language: Option<&str>) -> &mut Self {
+        // The fence has to be longer than any run of backticks within the code.
+        let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+        let newline = if code.ends_with('\n') { "" } else { "\n" };
+        self.block(&format!(
+            "{}{}\n{}{}{}",
+            fence,
+            language.unwrap_or(""),
+            code,
+            newline,
+            fence
+        ))
+    }
+
+    /// Adds a horizontal rule.
+    pub fn separator(&mut self) -> &mut Self {
+        self.block("<hr>")
+    }
+
+    /// Adds a block element on its own lines.
+    fn block(&mut self, html: &str) -> &mut Self {
+        if !self.buffer.is_empty() && !self.buffer.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        self.buffer.push_str(html);
+        self.buffer.push('\n');
+        self
+    }
+
+    /// Returns the collected content.
+    pub fn as_str(&self) -> &str {
+        &self.buffer
+    }
+
+    /// Returns whether nothing was added.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Removes all collected content.
+    pub fn clear(&mut self) -> &mut Self {
+        self.buffer.clear();
+        self
+    }
+
+    /// Appends the collected content to the step's summary and clears it.
+    ///
+    /// Content that would make the summary exceed [`MAX_SIZE`] is cut off at a line boundary
+    /// and replaced by a note, as the runner drops oversized summaries entirely. Returns `false`
+    /// without writing anything when `GITHUB_STEP_SUMMARY` is not set, i.e. outside of a
+    /// workflow run.
+    pub fn write(&mut self) -> io::Result<bool> {
+        self.write_mode(false)
+    }
+
+    /// Replaces the step's summary with the collected content and clears it, see
+    /// [`write`][Self::write].
+    pub fn overwrite(&mut self) -> io::Result<bool> {
+        self.write_mode(true)
+    }
+
+    fn write_mode(&mut self, overwrite: bool) -> io::Result<bool> {
+        let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") else {
+            return Ok(false);
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(!overwrite)
+            .truncate(overwrite)
+            .open(path)?;
+        let existing = if overwrite {
+            0
+        } else {
+            file.metadata()?.len() as usize
+        };
+
+        let available = MAX_SIZE.saturating_sub(existing);
+        if self.buffer.len() <= available {
+            file.write_all(self.buffer.as_bytes())?;
+        } else if available > TRUNCATED.len() {
+            let content = truncate(&self.buffer, available - TRUNCATED.len());
+            file.write_all(content.as_bytes())?;
+            file.write_all(TRUNCATED.as_bytes())?;
+        }
+        self.buffer.clear();
+        Ok(true)
+    }
+}
+
+/// Returns the longest prefix of `text` of at most `len` bytes ending at a line boundary.
+fn truncate(text: &str, len: usize) -> &str {
+    let mut end = len.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text[..end].rfind('\n') {
+        Some(newline) => &text[..=newline],
+        None => &text[..end],
+    }
+}
+
+/// Escapes text for use in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,61 @@
+//! Workflow annotations for degraded cache operations, available with the `annotations` feature.
+//!
+//! Failures that the client recovers from, like retried requests or reservations that could not
+//! be released, are only logged at debug level by default. Setting an [`Annotator`] using
+//! [`Cache::with_annotations`][crate::Cache::with_annotations] surfaces them as warnings, e.g. in
+//! the checks UI of a pull request using [`WorkflowCommands`].
+
+/// Receives warnings about failures the client recovered from.
+pub trait Annotator: Send + Sync {
+    /// Reports a warning.
+    fn warning(&self, message: &str);
+}
+
+impl<F: Fn(&str) + Send + Sync> Annotator for F {
+    fn warning(&self, message: &str) {
+        self(message)
+    }
+}
+
+/// Writes `::warning` workflow commands to stdout, creating annotations for the current run.
+#[derive(Clone, Debug)]
+pub struct WorkflowCommands {
+    title: String,
+}
+
+impl WorkflowCommands {
+    /// Creates annotations with the given title.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+        }
+    }
+}
+
+impl Default for WorkflowCommands {
+    fn default() -> Self {
+        Self::new("Cache")
+    }
+}
+
+impl Annotator for WorkflowCommands {
+    fn warning(&self, message: &str) {
+        println!(
+            "::warning title={}::{}",
+            escape_property(&self.title),
+            escape_data(message)
+        );
+    }
+}
+
+/// Escapes the message of a workflow command.
+pub(crate) fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+pub(crate) fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
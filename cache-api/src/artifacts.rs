@@ -0,0 +1,917 @@
+//! Client for the artifact service.
+//!
+//! Artifacts are zip archives attached to a workflow run, as created by the official
+//! [`upload-artifact`] action. Like [`Cache`][crate::Cache], [`ArtifactClient`] uses the runtime
+//! token only available to actions. It implements version 4 of the protocol, where the service
+//! hands out signed URLs for uploading to and downloading from blob storage.
+//!
+//! GitHub Enterprise Server and older runners only provide the legacy API, where an artifact is a
+//! container of individual files. [`ArtifactClient::new`] selects it automatically there. It
+//! supports uploading, listing and downloading into a directory, other operations fail with
+//! [`Error::UnsupportedByLegacyArtifacts`].
+//!
+//! Uploads record the SHA-256 digest of the zip archive, which downloads verify, failing with
+//! [`Error::DigestMismatch`] for corrupted content. The legacy API has no digests.
+//!
+//! [`upload-artifact`]: https://github.com/actions/upload-artifact
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use reqwest::{Client, Response};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    digest, error::error_for_response, glob, key, management::parse_timestamp, Direction, Error,
+    NoRetry, ProgressReporter, Result, RetryPolicy,
+};
+
+mod blocks;
+mod legacy;
+mod zip;
+
+pub use blocks::PendingUpload;
+
+/// Twirp service implementing the artifact API.
+const SERVICE: &str = "twirp/github.actions.results.api.v1.ArtifactService";
+
+/// Client for the artifact service of the current workflow run.
+pub struct ArtifactClient {
+    client: Client,
+    token: String,
+    protocol: Protocol,
+    block_size: usize,
+    concurrency: usize,
+    file_concurrency: usize,
+    retry_policy: Box<dyn RetryPolicy>,
+    retention_days: Option<u32>,
+    progress: Option<Arc<dyn ProgressReporter>>,
+    verify_digests: bool,
+}
+
+enum Protocol {
+    /// Version 4, using the results service.
+    Results {
+        url: String,
+        run_id: String,
+        job_id: String,
+    },
+    Legacy(legacy::Endpoint),
+}
+
+/// An artifact stored by [`ArtifactClient::upload_bytes`] and related methods.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct UploadedArtifact {
+    /// The artifact's id.
+    pub id: u64,
+    /// The size of the uploaded zip archive.
+    pub size: u64,
+    /// The SHA-256 digest of the uploaded zip archive, hex encoded.
+    ///
+    /// Not available with the legacy API, which stores individual files instead.
+    pub sha256: Option<String>,
+}
+
+/// An artifact of a workflow run.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Artifact {
+    /// The artifact's id.
+    pub id: u64,
+    /// The artifact's name, unique within the workflow run.
+    pub name: String,
+    /// The size of the artifact's zip archive.
+    pub size: u64,
+    /// Time the artifact was created, as RFC 3339 timestamp.
+    pub created_at: Option<String>,
+    /// Time the artifact expires, as RFC 3339 timestamp.
+    ///
+    /// Only reported by the REST API, see
+    /// [`CacheManagement::run_artifacts`][crate::management::CacheManagement::run_artifacts].
+    pub expires_at: Option<String>,
+    /// The SHA-256 digest of the artifact's zip archive, hex encoded.
+    ///
+    /// Only recorded for artifacts uploaded with version 4 of the protocol.
+    pub sha256: Option<String>,
+}
+
+impl Artifact {
+    /// Returns the parsed time the artifact was created.
+    pub fn created(&self) -> Option<SystemTime> {
+        parse_timestamp(self.created_at.as_deref()?)
+    }
+
+    /// Returns the parsed time the artifact expires.
+    pub fn expires(&self) -> Option<SystemTime> {
+        parse_timestamp(self.expires_at.as_deref()?)
+    }
+}
+
+#[derive(Serialize)]
+struct CreateArtifactRequest<'a> {
+    workflow_run_backend_id: &'a str,
+    workflow_job_run_backend_id: &'a str,
+    name: &'a str,
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CreateArtifactResponse {
+    ok: bool,
+    #[serde(alias = "signedUploadUrl")]
+    signed_upload_url: String,
+}
+
+#[derive(Serialize)]
+struct FinalizeArtifactRequest<'a> {
+    workflow_run_backend_id: &'a str,
+    workflow_job_run_backend_id: &'a str,
+    name: &'a str,
+    /// 64-bit integers are encoded as strings in protobuf JSON.
+    size: String,
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct GetSignedArtifactUrlRequest<'a> {
+    workflow_run_backend_id: &'a str,
+    workflow_job_run_backend_id: &'a str,
+    name: &'a str,
+}
+
+#[derive(Deserialize)]
+struct GetSignedArtifactUrlResponse {
+    #[serde(alias = "signedUrl")]
+    signed_url: String,
+}
+
+#[derive(Serialize)]
+struct ListArtifactsRequest<'a> {
+    workflow_run_backend_id: &'a str,
+    workflow_job_run_backend_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name_filter: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct ListArtifactsResponse {
+    #[serde(default)]
+    artifacts: Vec<ListedArtifact>,
+}
+
+#[derive(Deserialize)]
+struct ListedArtifact {
+    #[serde(alias = "databaseId", deserialize_with = "de_u64")]
+    database_id: u64,
+    name: String,
+    #[serde(deserialize_with = "de_u64")]
+    size: u64,
+    #[serde(default, alias = "createdAt")]
+    created_at: Option<String>,
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DeleteArtifactRequest<'a> {
+    workflow_run_backend_id: &'a str,
+    workflow_job_run_backend_id: &'a str,
+    name: &'a str,
+}
+
+#[derive(Deserialize)]
+struct DeleteArtifactResponse {
+    ok: bool,
+    #[serde(alias = "artifactId", deserialize_with = "de_u64")]
+    artifact_id: u64,
+}
+
+#[derive(Deserialize)]
+struct FinalizeArtifactResponse {
+    ok: bool,
+    #[serde(alias = "artifactId", deserialize_with = "de_u64")]
+    artifact_id: u64,
+}
+
+/// Returns the hex encoded SHA-256 digest from a digest like `sha256:<hex>`, as recorded by the
+/// artifact service.
+pub(crate) fn sha256_of_digest(digest: Option<String>) -> Option<String> {
+    let digest = digest?;
+    let hex = digest.strip_prefix("sha256:")?;
+    Some(hex.to_ascii_lowercase())
+}
+
+/// Deserializes a 64-bit integer encoded either as number or, like protobuf JSON does, as string.
+fn de_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Int {
+        Number(u64),
+        String(String),
+    }
+    match Int::deserialize(deserializer)? {
+        Int::Number(value) => Ok(value),
+        Int::String(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+impl ArtifactClient {
+    /// Creates a client for the current workflow run.
+    ///
+    /// This uses the `ACTIONS_RUNTIME_TOKEN` and `ACTIONS_RESULTS_URL` environment variables,
+    /// which are only available to actions. The passed `user_agent` should identify the program
+    /// using this library.
+    ///
+    /// On GitHub Enterprise Server, detected from `GITHUB_SERVER_URL`, and on runners without a
+    /// results service, the legacy API is used instead, see
+    /// [`with_legacy_endpoint`][Self::with_legacy_endpoint].
+    pub fn new(user_agent: &str) -> Result<Self> {
+        let token = std::env::var("ACTIONS_RUNTIME_TOKEN").map_err(|_| Error::NoRuntimeToken)?;
+        match std::env::var("ACTIONS_RESULTS_URL") {
+            Ok(results_url) if !is_enterprise_server() => {
+                Self::with_endpoint(user_agent, &results_url, &token)
+            }
+            results_url => {
+                let (Ok(runtime_url), Ok(run_id)) = (
+                    std::env::var("ACTIONS_RUNTIME_URL"),
+                    std::env::var("GITHUB_RUN_ID"),
+                ) else {
+                    return Err(match results_url {
+                        Ok(_) => Error::NoRuntimeUrl,
+                        Err(_) => Error::NoResultsUrl,
+                    });
+                };
+                Self::with_legacy_endpoint(user_agent, &runtime_url, &token, &run_id)
+            }
+        }
+    }
+
+    /// Creates a client for the given results service URL and runtime token.
+    ///
+    /// The workflow run and job are identified by the scopes of the runtime token. Use
+    /// [`with_endpoint_for_job`][Self::with_endpoint_for_job] for tokens without these scopes,
+    /// like those of mock servers.
+    pub fn with_endpoint(user_agent: &str, results_url: &str, token: &str) -> Result<Self> {
+        let (run_id, job_id) = backend_ids(token)?;
+        Self::with_endpoint_for_job(user_agent, results_url, token, &run_id, &job_id)
+    }
+
+    /// Creates a client for the given results service URL, runtime token and backend ids of the
+    /// workflow run and job.
+    pub fn with_endpoint_for_job(
+        user_agent: &str,
+        results_url: &str,
+        token: &str,
+        run_id: &str,
+        job_id: &str,
+    ) -> Result<Self> {
+        let protocol = Protocol::Results {
+            url: results_url.trim_end_matches('/').to_owned(),
+            run_id: run_id.to_owned(),
+            job_id: job_id.to_owned(),
+        };
+        Self::with_protocol(user_agent, token, protocol)
+    }
+
+    /// Creates a client using the legacy API of the runtime service at `runtime_url`, for the
+    /// workflow run with the given id.
+    ///
+    /// This is the API of GitHub Enterprise Server, where `runtime_url` is given by the
+    /// `ACTIONS_RUNTIME_URL` environment variable.
+    pub fn with_legacy_endpoint(
+        user_agent: &str,
+        runtime_url: &str,
+        token: &str,
+        run_id: &str,
+    ) -> Result<Self> {
+        let protocol = Protocol::Legacy(legacy::Endpoint::new(runtime_url, run_id));
+        Self::with_protocol(user_agent, token, protocol)
+    }
+
+    fn with_protocol(user_agent: &str, token: &str, protocol: Protocol) -> Result<Self> {
+        let client = Client::builder().user_agent(user_agent).build()?;
+        Ok(Self {
+            client,
+            token: token.to_owned(),
+            protocol,
+            block_size: 8 << 20,
+            concurrency: 4,
+            file_concurrency: 4,
+            retry_policy: Box::new(NoRetry),
+            retention_days: None,
+            progress: None,
+            verify_digests: true,
+        })
+    }
+
+    /// Sets the number of days after which uploaded artifacts expire.
+    ///
+    /// Without this, the repository's default retention applies, usually 90 days. Like the
+    /// official action, longer retention than the maximum configured for the repository, given
+    /// by the `GITHUB_RETENTION_DAYS` environment variable, is reduced to that maximum.
+    pub fn with_retention_days(mut self, days: u32) -> Self {
+        let max = std::env::var("GITHUB_RETENTION_DAYS")
+            .ok()
+            .and_then(|max| max.parse().ok());
+        self.retention_days = Some(match max {
+            Some(max) => days.min(max),
+            None => days,
+        });
+        self
+    }
+
+    /// Sets the size of the blocks of block uploads, 8 MiB by default.
+    ///
+    /// See [`upload_file_blocks`][Self::upload_file_blocks].
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Sets the number of blocks uploaded at once, 4 by default.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the number of files read and compressed, or with the legacy API uploaded, at once by
+    /// [`upload_files`][Self::upload_files], 4 by default.
+    pub fn with_file_concurrency(mut self, concurrency: usize) -> Self {
+        self.file_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Reports the progress of [`upload_files`][Self::upload_files] to the given reporter.
+    ///
+    /// The transfer is reported under the artifact's name, advancing by the size of each file
+    /// once it is processed, so the total is the combined size of all files.
+    pub fn with_progress(mut self, progress: impl ProgressReporter + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Calls the progress reporter if one is set.
+    fn report(&self, progress: impl FnOnce(&dyn ProgressReporter)) {
+        if let Some(reporter) = &self.progress {
+            progress(&**reporter);
+        }
+    }
+
+    /// Sets whether downloads verify the digest recorded by the upload, enabled by default.
+    ///
+    /// Verification needs an additional request to look up the digest.
+    pub fn with_digest_verification(mut self, verify: bool) -> Self {
+        self.verify_digests = verify;
+        self
+    }
+
+    /// Sets the policy deciding whether and when failed requests are retried.
+    ///
+    /// By default, failed requests are not retried.
+    pub fn with_retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Box::new(retry_policy);
+        self
+    }
+
+    /// Uploads a zip archive as artifact of the given name.
+    ///
+    /// The content has to be a zip archive for the artifact to be downloadable by the official
+    /// actions and the web interface. Names have to be unique within a workflow run. With the
+    /// legacy API, the archive is stored as the artifact's single file `{name}.zip`.
+    pub async fn upload_bytes(&self, name: &str, zip: Bytes) -> Result<UploadedArtifact> {
+        if let Protocol::Legacy(endpoint) = &self.protocol {
+            let file = (format!("{}.zip", name), legacy::FileSource::Bytes(zip));
+            return self.legacy_upload(endpoint, name, vec![file]).await;
+        }
+        let sha256 = digest::sha256_hex(&zip);
+        let size = zip.len() as u64;
+
+        let upload_url = self.create(name).await?;
+        self.upload_blob(&upload_url, zip).await?;
+        let id = self.finalize(name, size, &sha256).await?;
+
+        Ok(UploadedArtifact {
+            id,
+            size,
+            sha256: Some(sha256),
+        })
+    }
+
+    /// Uploads an existing zip archive as artifact of the given name.
+    pub async fn upload_zip_file(
+        &self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<UploadedArtifact> {
+        if let Protocol::Legacy(endpoint) = &self.protocol {
+            let path = path.as_ref().to_owned();
+            let file = (format!("{}.zip", name), legacy::FileSource::Path(path));
+            return self.legacy_upload(endpoint, name, vec![file]).await;
+        }
+        let zip = tokio::fs::read(path).await?;
+        self.upload_bytes(name, zip.into()).await
+    }
+
+    /// Uploads files as artifact of the given name, storing them relative to `root`.
+    ///
+    /// The files are compressed into a zip archive in memory before uploading, with the legacy
+    /// API they are uploaded individually instead. Up to the configured number of files are
+    /// processed at once, see [`with_file_concurrency`][Self::with_file_concurrency]. Files
+    /// outside of `root` are stored under their file name.
+    pub async fn upload_files(
+        &self,
+        name: &str,
+        root: impl AsRef<Path>,
+        files: &[PathBuf],
+    ) -> Result<UploadedArtifact> {
+        let root = root.as_ref();
+        let paths: Vec<PathBuf> = files.iter().map(|file| root.join(file)).collect();
+        let total = {
+            let paths = paths.clone();
+            tokio::task::spawn_blocking(move || {
+                paths.iter().try_fold(0, |total, path| {
+                    Ok::<_, std::io::Error>(total + std::fs::metadata(path)?.len())
+                })
+            })
+            .await
+            .map_err(std::io::Error::other)??
+        };
+
+        self.report(|progress| progress.start(Direction::Upload, name, Some(total)));
+        let result = match &self.protocol {
+            Protocol::Legacy(endpoint) => {
+                let files = legacy::file_sources(root, files);
+                self.legacy_upload(endpoint, name, files).await
+            }
+            Protocol::Results { .. } => match self.zip_files(root, paths).await {
+                Ok(zip) => self.upload_bytes(name, zip.into()).await,
+                Err(err) => Err(err),
+            },
+        };
+        self.report(|progress| progress.finish(Direction::Upload));
+        result
+    }
+
+    /// Uploads all files below `root` matching the given glob patterns as artifact of the given
+    /// name, see [`upload_files`][Self::upload_files].
+    ///
+    /// Patterns follow the semantics of `hashFiles()`, see
+    /// [`hash_files_in`][crate::key::hash_files_in]. Files matched by several patterns are
+    /// uploaded once.
+    pub async fn upload_paths(
+        &self,
+        name: &str,
+        root: impl AsRef<Path>,
+        patterns: &[&str],
+    ) -> Result<UploadedArtifact> {
+        let root = root.as_ref().to_owned();
+        let patterns: Vec<_> = patterns.iter().map(|p| glob::Pattern::new(p)).collect();
+        let files = {
+            let root = root.clone();
+            tokio::task::spawn_blocking(move || glob::find_files(&root, &patterns))
+                .await
+                .map_err(std::io::Error::other)??
+        };
+        self.upload_files(name, root, &files).await
+    }
+
+    /// Lists the artifacts of the current workflow run, including those of other jobs.
+    pub async fn list_artifacts(&self) -> Result<Vec<Artifact>> {
+        if let Protocol::Legacy(endpoint) = &self.protocol {
+            return self.legacy_containers(endpoint).await;
+        }
+        self.list(None).await
+    }
+
+    async fn list(&self, name: Option<&str>) -> Result<Vec<Artifact>> {
+        let (run_id, job_id) = self.backend_ids("listing")?;
+        let response: ListArtifactsResponse = self
+            .call(
+                "ListArtifacts",
+                &ListArtifactsRequest {
+                    workflow_run_backend_id: run_id,
+                    workflow_job_run_backend_id: job_id,
+                    name_filter: name,
+                },
+            )
+            .await?;
+        Ok(response
+            .artifacts
+            .into_iter()
+            .map(|artifact| Artifact {
+                id: artifact.database_id,
+                name: artifact.name,
+                size: artifact.size,
+                created_at: artifact.created_at,
+                expires_at: None,
+                sha256: sha256_of_digest(artifact.digest),
+            })
+            .collect())
+    }
+
+    /// Returns a short-lived URL for downloading the zip archive of the named artifact.
+    ///
+    /// Fails with [`Error::NotFound`] if the workflow run has no artifact of that name.
+    pub async fn download_url(&self, name: &str) -> Result<String> {
+        let (run_id, job_id) = self.backend_ids("downloading zip archives")?;
+        let response: GetSignedArtifactUrlResponse = self
+            .call(
+                "GetSignedArtifactURL",
+                &GetSignedArtifactUrlRequest {
+                    workflow_run_backend_id: run_id,
+                    workflow_job_run_backend_id: job_id,
+                    name,
+                },
+            )
+            .await?;
+        Ok(response.signed_url)
+    }
+
+    /// Downloads the zip archive of the named artifact into memory.
+    pub async fn download_bytes(&self, name: &str) -> Result<Bytes> {
+        let mut data = vec![];
+        self.download_to_writer(name, &mut data).await?;
+        Ok(data.into())
+    }
+
+    /// Streams the zip archive of the named artifact into `writer`, returning its size.
+    ///
+    /// The content is verified against the digest recorded by the upload, if any, after it was
+    /// written. On an [`Error::DigestMismatch`] the written content has to be discarded.
+    pub async fn download_to_writer<W>(&self, name: &str, mut writer: W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let expected = if self.verify_digests {
+            self.recorded_sha256(name).await?
+        } else {
+            None
+        };
+        let url = self.download_url(name).await?;
+        let request = self.client.get(url).build()?;
+        let mut response = self.execute(request).await?;
+        let mut hasher = digest::Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            writer.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        writer.flush().await?;
+
+        let actual = digest::hex(&hasher.finish());
+        match expected {
+            Some(expected) if expected != actual => Err(Error::DigestMismatch {
+                name: name.to_owned(),
+                expected,
+                actual,
+            }),
+            _ => Ok(size),
+        }
+    }
+
+    /// Downloads the zip archive of the named artifact to a file, returning its size.
+    ///
+    /// The file is created or truncated, and removed again if its content is corrupted.
+    pub async fn download_to_file(&self, name: &str, path: impl AsRef<Path>) -> Result<u64> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::create(path).await?;
+        let result = self.download_to_writer(name, file).await;
+        if let Err(Error::DigestMismatch { .. }) = result {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        result
+    }
+
+    /// Returns the digest recorded for the named artifact, if any.
+    async fn recorded_sha256(&self, name: &str) -> Result<Option<String>> {
+        let artifacts = self.list(Some(name)).await?;
+        Ok(artifacts
+            .into_iter()
+            .find(|artifact| artifact.name == name)
+            .and_then(|artifact| artifact.sha256))
+    }
+
+    /// Deletes the named artifact of the current workflow run, returning its id.
+    ///
+    /// Artifacts of other runs can be deleted by id using
+    /// [`CacheManagement::delete_artifact`][crate::management::CacheManagement::delete_artifact].
+    pub async fn delete_artifact(&self, name: &str) -> Result<u64> {
+        let (run_id, job_id) = self.backend_ids("deleting artifacts")?;
+        let response: DeleteArtifactResponse = self
+            .call(
+                "DeleteArtifact",
+                &DeleteArtifactRequest {
+                    workflow_run_backend_id: run_id,
+                    workflow_job_run_backend_id: job_id,
+                    name,
+                },
+            )
+            .await?;
+        if !response.ok {
+            return Err(Error::ArtifactRejected {
+                name: name.to_owned(),
+                operation: "delete",
+            });
+        }
+        Ok(response.artifact_id)
+    }
+
+    /// Downloads the named artifact and extracts it into `dir`, returning the extracted files.
+    ///
+    /// This matches the official `download-artifact` action: `dir` is created if necessary and
+    /// existing files are overwritten. The archive is streamed to a temporary file first. Entries
+    /// with absolute paths or paths leaving `dir` are rejected with an error.
+    pub async fn download_to_dir(&self, name: &str, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref().to_owned();
+        tokio::fs::create_dir_all(&dir).await?;
+        if let Protocol::Legacy(endpoint) = &self.protocol {
+            return self.legacy_download_to_dir(endpoint, name, &dir).await;
+        }
+
+        let archive = TempFile::new("zip");
+        self.download_to_file(name, &archive.0).await?;
+        let path = archive.0.clone();
+        let files = tokio::task::spawn_blocking(move || zip::extract(&path, &dir))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok(files)
+    }
+
+    async fn create(&self, name: &str) -> Result<String> {
+        let (run_id, job_id) = self.backend_ids("block uploads")?;
+        let response: CreateArtifactResponse = self
+            .call(
+                "CreateArtifact",
+                &CreateArtifactRequest {
+                    workflow_run_backend_id: run_id,
+                    workflow_job_run_backend_id: job_id,
+                    name,
+                    version: 4,
+                    expires_at: self.retention_days.map(|days| {
+                        format_timestamp(
+                            SystemTime::now() + Duration::from_secs(86400 * days as u64),
+                        )
+                    }),
+                },
+            )
+            .await?;
+        if !response.ok {
+            return Err(Error::ArtifactRejected {
+                name: name.to_owned(),
+                operation: "create",
+            });
+        }
+        Ok(response.signed_upload_url)
+    }
+
+    async fn upload_blob(&self, upload_url: &str, data: Bytes) -> Result<()> {
+        let request = self
+            .client
+            .put(upload_url)
+            .header("x-ms-blob-type", "BlockBlob")
+            .header(reqwest::header::CONTENT_TYPE, "application/zip")
+            .body(data)
+            .build()?;
+        self.execute(request).await?;
+        Ok(())
+    }
+
+    async fn finalize(&self, name: &str, size: u64, sha256: &str) -> Result<u64> {
+        let (run_id, job_id) = self.backend_ids("block uploads")?;
+        let response: FinalizeArtifactResponse = self
+            .call(
+                "FinalizeArtifact",
+                &FinalizeArtifactRequest {
+                    workflow_run_backend_id: run_id,
+                    workflow_job_run_backend_id: job_id,
+                    name,
+                    size: size.to_string(),
+                    hash: format!("sha256:{}", sha256),
+                },
+            )
+            .await?;
+        if !response.ok {
+            return Err(Error::ArtifactRejected {
+                name: name.to_owned(),
+                operation: "finalize",
+            });
+        }
+        Ok(response.artifact_id)
+    }
+
+    /// Creates a zip archive of the given files, compressing them in parallel.
+    async fn zip_files(&self, root: &Path, paths: Vec<PathBuf>) -> Result<Vec<u8>> {
+        let mut entries = futures_util::stream::iter(paths.into_iter().map(|path| {
+            let name = archive_name(root, &path);
+            tokio::task::spawn_blocking(move || {
+                let metadata = std::fs::metadata(&path)?;
+                let data = std::fs::read(&path)?;
+                zip::Compressed::new(zip::Entry {
+                    name: &name,
+                    data: &data,
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    mode: file_mode(&metadata),
+                })
+            })
+        }))
+        // Keeps the order of entries, so archives are reproducible.
+        .buffered(self.file_concurrency);
+
+        let mut writer = zip::ZipWriter::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(std::io::Error::other)??;
+            let size = entry.size();
+            writer.add(entry)?;
+            self.report(|progress| progress.advance(Direction::Upload, size));
+        }
+        Ok(writer.finish()?)
+    }
+
+    /// Returns the backend ids of the workflow run and job, failing for the legacy API which
+    /// lacks `operation`.
+    fn backend_ids(&self, operation: &'static str) -> Result<(&str, &str)> {
+        match &self.protocol {
+            Protocol::Results { run_id, job_id, .. } => Ok((run_id, job_id)),
+            Protocol::Legacy(_) => Err(Error::UnsupportedByLegacyArtifacts(operation)),
+        }
+    }
+
+    /// Calls a method of the artifact service.
+    async fn call<T: DeserializeOwned>(&self, method: &str, body: &impl Serialize) -> Result<T> {
+        let Protocol::Results { url, .. } = &self.protocol else {
+            unreachable!("checked by backend_ids")
+        };
+        let request = self
+            .client
+            .post(format!("{}/{}/{}", url, SERVICE, method))
+            .bearer_auth(&self.token)
+            .json(body)
+            .build()?;
+        Ok(self.execute(request).await?.json().await?)
+    }
+
+    async fn execute(&self, mut request: reqwest::Request) -> Result<Response> {
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            let retry_request = request.try_clone();
+            attempts += 1;
+
+            let url = request.url().clone();
+            let result = async {
+                let response = self.client.execute(request).await?;
+                error_for_response(response, url, None, SystemTime::now()).await
+            }
+            .await;
+
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(error) => error.redacted(&self.token),
+            };
+            let delay = self
+                .retry_policy
+                .retry_delay(attempts, start.elapsed(), &error);
+            match (retry_request, delay) {
+                (Some(retry_request), Some(delay)) => {
+                    tracing::debug!(%error, ?delay, attempts, "retrying artifact request");
+                    tokio::time::sleep(delay).await;
+                    request = retry_request;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+/// Returns whether the workflow runs on GitHub Enterprise Server, as opposed to github.com or a
+/// GHE.com data residency instance.
+fn is_enterprise_server() -> bool {
+    let Some(server_url) = std::env::var("GITHUB_SERVER_URL")
+        .ok()
+        .and_then(|url| reqwest::Url::parse(&url).ok())
+    else {
+        return false;
+    };
+    match server_url.host_str() {
+        Some(host) => {
+            let host = host.to_ascii_lowercase();
+            !(host == "github.com" || host.ends_with(".ghe.com") || host == "localhost")
+        }
+        None => false,
+    }
+}
+
+/// Formats a time as RFC 3339 timestamp in UTC, like `2022-09-21T09:57:04Z`.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = key::civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// A file in the temporary directory that is removed on drop.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::var_os("RUNNER_TEMP").map_or_else(std::env::temp_dir, PathBuf::from);
+        Self(dir.join(format!(
+            "artifact-{}-{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            extension
+        )))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Extracts the backend ids of the workflow run and job from the runtime token's scopes.
+fn backend_ids(token: &str) -> Result<(String, String)> {
+    let invalid = |reason: &str| Error::InvalidRuntimeToken(reason.to_owned());
+
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| invalid("not a JWT"))?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| invalid("malformed JWT payload"))?;
+
+    #[derive(Deserialize)]
+    struct Claims {
+        #[serde(default)]
+        scp: String,
+    }
+    let claims: Claims =
+        serde_json::from_slice(&payload).map_err(|_| invalid("malformed JWT payload"))?;
+
+    claims
+        .scp
+        .split(' ')
+        .find_map(|scope| {
+            let ids = scope.strip_prefix("Actions.Results:")?;
+            let (run_id, job_id) = ids.split_once(':')?;
+            Some((run_id.to_owned(), job_id.to_owned()))
+        })
+        .ok_or_else(|| invalid("no Actions.Results scope"))
+}
+
+/// Returns the `/` separated path of a file within the archive.
+fn archive_name(root: &Path, path: &Path) -> String {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => path.file_name().map_or(path, Path::new),
+    };
+    relative
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions())
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o100444
+    } else {
+        0o100644
+    }
+}
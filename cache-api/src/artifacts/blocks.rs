@@ -0,0 +1,152 @@
+//! Uploads to blob storage in separately staged blocks.
+use std::{collections::HashMap, fmt, path::Path};
+
+use bytes::Bytes;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::io::AsyncReadExt;
+
+use super::{ArtifactClient, UploadedArtifact};
+use crate::{block_blob, digest, redact, Error, Result};
+
+/// An artifact created with [`ArtifactClient::start_upload`] but not yet finalized.
+///
+/// Uploading content with [`ArtifactClient::upload_file_blocks`] can be repeated after a failure,
+/// skipping blocks that were already uploaded.
+#[derive(Clone)]
+pub struct PendingUpload {
+    name: String,
+    upload_url: String,
+}
+
+impl PendingUpload {
+    /// The artifact's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for PendingUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let upload_url = reqwest::Url::parse(&self.upload_url)
+            .map(|url| redact::redact_url(&url).to_string())
+            .unwrap_or_else(|_| redact::REDACTED.to_owned());
+        f.debug_struct("PendingUpload")
+            .field("name", &self.name)
+            .field("upload_url", &upload_url)
+            .finish()
+    }
+}
+
+impl ArtifactClient {
+    /// Uploads a large zip archive as artifact of the given name, see
+    /// [`upload_file_blocks`][Self::upload_file_blocks].
+    pub async fn upload_large_file(
+        &self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<UploadedArtifact> {
+        if let super::Protocol::Legacy(endpoint) = &self.protocol {
+            let path = path.as_ref().to_owned();
+            let file = (
+                format!("{}.zip", name),
+                super::legacy::FileSource::Path(path),
+            );
+            return self.legacy_upload(endpoint, name, vec![file]).await;
+        }
+        let upload = self.start_upload(name).await?;
+        self.upload_file_blocks(&upload, path).await
+    }
+
+    /// Creates an artifact to upload content to.
+    pub async fn start_upload(&self, name: &str) -> Result<PendingUpload> {
+        Ok(PendingUpload {
+            name: name.to_owned(),
+            upload_url: self.create(name).await?,
+        })
+    }
+
+    /// Uploads a zip archive to a created artifact and finalizes it.
+    ///
+    /// The file is read sequentially and uploaded in blocks, with up to the configured number of
+    /// blocks in flight, so memory use is bounded independent of the file size. Failed block
+    /// uploads are retried according to the retry policy. When called again for the same
+    /// `upload`, blocks that were already uploaded with the same size are skipped.
+    pub async fn upload_file_blocks(
+        &self,
+        upload: &PendingUpload,
+        path: impl AsRef<Path>,
+    ) -> Result<UploadedArtifact> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let file_size = file.metadata().await?.len();
+        let block_size = block_blob::block_size(self.block_size, file_size);
+
+        let staged = self.uncommitted_blocks(&upload.upload_url).await?;
+
+        let mut hasher = digest::Sha256::new();
+        let mut block_ids = vec![];
+        let mut size = 0;
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            let mut block = Vec::with_capacity(block_size as usize);
+            (&mut file).take(block_size).read_to_end(&mut block).await?;
+            if block.is_empty() {
+                break;
+            }
+            hasher.update(&block);
+            size += block.len() as u64;
+
+            let id = block_blob::block_id(block_ids.len());
+            block_ids.push(id.clone());
+            if staged.get(&id) == Some(&(block.len() as u64)) {
+                continue;
+            }
+
+            if in_flight.len() >= self.concurrency {
+                if let Some(result) = in_flight.next().await {
+                    result?;
+                }
+            }
+            in_flight.push(self.put_block(&upload.upload_url, id, block.into()));
+        }
+        while let Some(result) = in_flight.next().await {
+            result?;
+        }
+
+        self.put_block_list(&upload.upload_url, &block_ids).await?;
+
+        let sha256 = digest::hex(&hasher.finish());
+        let id = self.finalize(&upload.name, size, &sha256).await?;
+        Ok(UploadedArtifact {
+            id,
+            size,
+            sha256: Some(sha256),
+        })
+    }
+
+    async fn put_block(&self, upload_url: &str, id: String, data: Bytes) -> Result<()> {
+        let request = block_blob::put_block(&self.client, upload_url, &id, data).build()?;
+        self.execute(request).await?;
+        Ok(())
+    }
+
+    async fn put_block_list(&self, upload_url: &str, block_ids: &[String]) -> Result<()> {
+        let request = block_blob::put_block_list(&self.client, upload_url, block_ids)
+            .header("x-ms-blob-content-type", "application/zip")
+            .build()?;
+        self.execute(request).await?;
+        Ok(())
+    }
+
+    /// Returns the sizes of the blocks staged but not yet committed, by block id.
+    async fn uncommitted_blocks(&self, upload_url: &str) -> Result<HashMap<String, u64>> {
+        let request = block_blob::get_uncommitted_blocks(&self.client, upload_url).build()?;
+        let body = match self.execute(request).await {
+            Ok(response) => response.text().await?,
+            // Nothing was staged yet for new blobs.
+            Err(Error::NotFound(_)) => return Ok(HashMap::new()),
+            Err(err) => return Err(err),
+        };
+        Ok(block_blob::parse_block_list(&body))
+    }
+}
@@ -0,0 +1,314 @@
+//! Legacy artifact API of GitHub Enterprise Server and older runners.
+//!
+//! Here artifacts are containers of individual files, uploaded to and downloaded from the
+//! `_apis/pipelines` endpoints of the runtime service, instead of zip archives in blob storage.
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt};
+use reqwest::{header, Method, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{archive_name, Artifact, ArtifactClient, UploadedArtifact};
+use crate::{Direction, Error, Result, StatusError};
+
+const API_VERSION: &str = "api-version=6.0-preview";
+
+/// Maximal size of a single upload request, larger files are uploaded in ranges.
+const CHUNK_SIZE: u64 = 8 << 20;
+
+/// Location of the legacy API of a workflow run.
+pub(super) struct Endpoint {
+    runtime_url: String,
+    run_id: String,
+}
+
+impl Endpoint {
+    pub(super) fn new(runtime_url: &str, run_id: &str) -> Self {
+        Self {
+            runtime_url: format!("{}/", runtime_url.trim_end_matches('/')),
+            run_id: run_id.to_owned(),
+        }
+    }
+
+    fn artifacts_url(&self) -> String {
+        format!(
+            "{}_apis/pipelines/workflows/{}/artifacts?{}",
+            self.runtime_url, self.run_id, API_VERSION
+        )
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct CreateContainer<'a> {
+    r#type: &'static str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retention_days: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Container {
+    container_id: u64,
+    #[serde(default)]
+    size: u64,
+    name: String,
+    file_container_resource_url: String,
+}
+
+#[derive(Deserialize)]
+struct List<T> {
+    value: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerItem {
+    path: String,
+    item_type: String,
+    #[serde(default)]
+    content_location: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Finalize {
+    size: u64,
+}
+
+impl ArtifactClient {
+    fn legacy_request(&self, method: Method, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.legacy_request_accepting(method, url, "application/json")
+    }
+
+    fn legacy_request_accepting(
+        &self,
+        method: Method,
+        url: impl reqwest::IntoUrl,
+        accept: &str,
+    ) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .bearer_auth(&self.token)
+            .header(header::ACCEPT, format!("{};{}", accept, API_VERSION))
+    }
+
+    /// Uploads files, each given as path within the artifact and content, then finalizes it.
+    ///
+    /// Up to the configured number of files are uploaded at once, reporting progress per file.
+    pub(super) async fn legacy_upload(
+        &self,
+        endpoint: &Endpoint,
+        name: &str,
+        files: Vec<(String, FileSource)>,
+    ) -> Result<UploadedArtifact> {
+        let container: Container = self
+            .execute(
+                self.legacy_request(Method::POST, endpoint.artifacts_url())
+                    .json(&CreateContainer {
+                        r#type: "actions_storage",
+                        name,
+                        retention_days: self.retention_days,
+                    })
+                    .build()?,
+            )
+            .await?
+            .json()
+            .await?;
+
+        let resource_url = &container.file_container_resource_url;
+        let size = futures_util::stream::iter(files.into_iter().map(|(path, source)| async move {
+            let item_path = format!("{}/{}", name, path);
+            let size = self
+                .legacy_upload_file(resource_url, &item_path, source)
+                .await?;
+            self.report(|progress| progress.advance(Direction::Upload, size));
+            Ok::<_, Error>(size)
+        }))
+        .buffer_unordered(self.file_concurrency)
+        .try_fold(0, |total, size| async move { Ok(total + size) })
+        .await?;
+
+        self.execute(
+            self.legacy_request(Method::PATCH, endpoint.artifacts_url())
+                .query(&[("artifactName", name)])
+                .json(&Finalize { size })
+                .build()?,
+        )
+        .await?;
+
+        Ok(UploadedArtifact {
+            id: container.container_id,
+            size,
+            sha256: None,
+        })
+    }
+
+    /// Uploads a single file in ranges of at most [`CHUNK_SIZE`], returning its size.
+    async fn legacy_upload_file(
+        &self,
+        resource_url: &str,
+        item_path: &str,
+        source: FileSource,
+    ) -> Result<u64> {
+        let (total, mut file, mut data) = match source {
+            FileSource::Bytes(data) => (data.len() as u64, None, Some(data)),
+            FileSource::Path(path) => {
+                let file = tokio::fs::File::open(path).await?;
+                (file.metadata().await?.len(), Some(file), None)
+            }
+        };
+
+        let mut offset = 0;
+        loop {
+            let chunk: Bytes = match (&mut file, &mut data) {
+                (Some(file), _) => {
+                    let mut chunk = vec![];
+                    file.take(CHUNK_SIZE).read_to_end(&mut chunk).await?;
+                    chunk.into()
+                }
+                (None, Some(data)) => data.split_to(data.len().min(CHUNK_SIZE as usize)),
+                (None, None) => unreachable!(),
+            };
+            let end = offset + chunk.len() as u64;
+            // Like the official client, this sends `bytes 0--1/0` for empty files.
+            let range = format!("bytes {}-{}/{}", offset, end as i64 - 1, total);
+            self.execute(
+                self.legacy_request(Method::PUT, resource_url)
+                    .query(&[("itemPath", item_path)])
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .header(header::CONTENT_RANGE, range)
+                    .body(chunk)
+                    .build()?,
+            )
+            .await?;
+
+            offset = end;
+            if offset >= total {
+                return Ok(total);
+            }
+        }
+    }
+
+    pub(super) async fn legacy_containers(&self, endpoint: &Endpoint) -> Result<Vec<Artifact>> {
+        let list: List<Container> = self
+            .execute(
+                self.legacy_request(Method::GET, endpoint.artifacts_url())
+                    .build()?,
+            )
+            .await?
+            .json()
+            .await?;
+        Ok(list
+            .value
+            .into_iter()
+            .map(|container| Artifact {
+                id: container.container_id,
+                name: container.name,
+                size: container.size,
+                created_at: None,
+                expires_at: None,
+                sha256: None,
+            })
+            .collect())
+    }
+
+    /// Downloads all files of the named artifact into `dir`.
+    pub(super) async fn legacy_download_to_dir(
+        &self,
+        endpoint: &Endpoint,
+        name: &str,
+        dir: &Path,
+    ) -> Result<Vec<PathBuf>> {
+        let containers: List<Container> = self
+            .execute(
+                self.legacy_request(Method::GET, endpoint.artifacts_url())
+                    .build()?,
+            )
+            .await?
+            .json()
+            .await?;
+        let container = containers
+            .value
+            .into_iter()
+            .find(|container| container.name == name)
+            .ok_or_else(|| {
+                Error::NotFound(Box::new(StatusError {
+                    status: StatusCode::NOT_FOUND,
+                    url: Url::parse(&endpoint.artifacts_url()).expect("valid runtime URL"),
+                    body: format!("no artifact named {:?}", name),
+                    service_error: None,
+                }))
+            })?;
+
+        let items: List<ContainerItem> = self
+            .execute(
+                self.legacy_request(Method::GET, &container.file_container_resource_url)
+                    .query(&[("itemPath", name)])
+                    .build()?,
+            )
+            .await?
+            .json()
+            .await?;
+
+        let mut files = vec![];
+        for item in items.value {
+            // Items are listed with the artifact's name as first component, which includes the
+            // artifact's folder itself.
+            let Some(relative) = item
+                .path
+                .strip_prefix(name)
+                .and_then(|path| path.strip_prefix('/'))
+            else {
+                continue;
+            };
+            let path = super::zip::destination(dir, relative)?;
+            if item.item_type == "folder" {
+                tokio::fs::create_dir_all(&path).await?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            let mut response = self
+                .execute(
+                    self.legacy_request_accepting(
+                        Method::GET,
+                        &item.content_location,
+                        "application/octet-stream",
+                    )
+                    .build()?,
+                )
+                .await?;
+            super::zip::remove_symlink(&path)?;
+            let mut file = tokio::fs::File::create(&path).await?;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            files.push(path);
+        }
+        Ok(files)
+    }
+}
+
+/// Content of a file to upload with the legacy API.
+pub(super) enum FileSource {
+    Bytes(Bytes),
+    Path(PathBuf),
+}
+
+/// Lists files to upload relative to `root`, like [`ArtifactClient::upload_files`].
+pub(super) fn file_sources(root: &Path, files: &[PathBuf]) -> Vec<(String, FileSource)> {
+    files
+        .iter()
+        .map(|file| {
+            let path = root.join(file);
+            (archive_name(root, &path), FileSource::Path(path))
+        })
+        .collect()
+}
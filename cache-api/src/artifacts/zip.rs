@@ -0,0 +1,633 @@
+//! Minimal zip archive writer and extractor, as artifacts are stored as zip archives.
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use miniz_oxide::{
+    inflate::stream::{inflate, InflateState},
+    DataFormat, MZError, MZFlush, MZStatus,
+};
+
+use crate::key;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+/// Version 2.0, needed for deflate and directories.
+const VERSION: u16 = 20;
+/// Created on Unix, so the external attributes hold the file mode.
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION;
+/// File names are UTF-8 encoded.
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC-32 as used by zip archives.
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Self {
+        Self(!0)
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC_TABLE[((self.0 ^ u32::from(byte)) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// An entry to add to an archive.
+pub(crate) struct Entry<'a> {
+    /// Path within the archive, using `/` as separator.
+    pub(crate) name: &'a str,
+    pub(crate) data: &'a [u8],
+    pub(crate) modified: SystemTime,
+    /// Unix file mode including the file type bits.
+    pub(crate) mode: u32,
+}
+
+struct Written {
+    name: String,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    mode: u32,
+    offset: u32,
+}
+
+/// Writes a zip archive into memory.
+///
+/// Zip64 is not supported, so archives and entries are limited to 4 GiB.
+#[derive(Default)]
+pub(crate) struct ZipWriter {
+    out: Vec<u8>,
+    entries: Vec<Written>,
+}
+
+/// An entry compressed ahead of adding it to a [`ZipWriter`], so that entries can be compressed
+/// in parallel.
+pub(crate) struct Compressed {
+    written: Written,
+    data: Vec<u8>,
+}
+
+impl Compressed {
+    /// Compresses an entry unless that doesn't reduce its size.
+    pub(crate) fn new(entry: Entry) -> io::Result<Self> {
+        let mut crc = Crc32::new();
+        crc.update(entry.data);
+
+        let deflated = miniz_oxide::deflate::compress_to_vec(entry.data, 6);
+        let (method, data) = if deflated.len() < entry.data.len() {
+            (METHOD_DEFLATE, deflated)
+        } else {
+            (METHOD_STORED, entry.data.to_owned())
+        };
+
+        let (time, date) = dos_time(entry.modified);
+        Ok(Self {
+            written: Written {
+                name: entry.name.to_owned(),
+                method,
+                time,
+                date,
+                crc: crc.finish(),
+                compressed: to_u32(data.len())?,
+                size: to_u32(entry.data.len())?,
+                mode: entry.mode,
+                offset: 0,
+            },
+            data,
+        })
+    }
+
+    /// The uncompressed size of the entry.
+    pub(crate) fn size(&self) -> u64 {
+        self.written.size.into()
+    }
+}
+
+impl ZipWriter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a compressed entry.
+    pub(crate) fn add(&mut self, entry: Compressed) -> io::Result<()> {
+        let Compressed { mut written, data } = entry;
+        written.offset = to_u32(self.out.len())?;
+
+        self.u32(LOCAL_HEADER);
+        self.u16(VERSION);
+        self.common(&written);
+        self.u16(0);
+        self.out.extend_from_slice(written.name.as_bytes());
+        self.out.extend_from_slice(&data);
+
+        self.entries.push(written);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the archive.
+    pub(crate) fn finish(mut self) -> io::Result<Vec<u8>> {
+        let start = to_u32(self.out.len())?;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.u32(CENTRAL_HEADER);
+            self.u16(VERSION_MADE_BY);
+            self.u16(VERSION);
+            self.common(entry);
+            // Extra field, comment, disk number and internal attributes.
+            self.u16(0);
+            self.u16(0);
+            self.u16(0);
+            self.u16(0);
+            self.u32(entry.mode << 16);
+            self.u32(entry.offset);
+            self.out.extend_from_slice(entry.name.as_bytes());
+        }
+        let size = to_u32(self.out.len())? - start;
+        let count = u16::try_from(entries.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many zip entries"))?;
+
+        self.u32(END_OF_CENTRAL_DIRECTORY);
+        self.u16(0);
+        self.u16(0);
+        self.u16(count);
+        self.u16(count);
+        self.u32(size);
+        self.u32(start);
+        self.u16(0);
+        Ok(self.out)
+    }
+
+    /// Writes the header fields shared by local and central headers, up to the name length.
+    fn common(&mut self, entry: &Written) {
+        self.u16(FLAG_UTF8);
+        self.u16(entry.method);
+        self.u16(entry.time);
+        self.u16(entry.date);
+        self.u32(entry.crc);
+        self.u32(entry.compressed);
+        self.u32(entry.size);
+        self.u16(entry.name.len() as u16);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn to_u32(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "zip archives larger than 4 GiB are not supported",
+        )
+    })
+}
+
+/// Converts a time to the MS-DOS time and date used by zip archives, in UTC.
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = key::civil_from_days((secs / 86400) as i64);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let secs_of_day = secs % 86400;
+    let time =
+        ((secs_of_day / 3600) << 11) | (((secs_of_day / 60) % 60) << 5) | (secs_of_day % 60 / 2);
+    let date = ((year.min(2107) - 1980) << 9) as u32 | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+/// An entry listed in the central directory of an archive.
+struct CentralEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+    /// Unix file mode, if the archive was created on Unix.
+    mode: Option<u32>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn le16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn le32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+/// Extracts all entries of the archive at `archive` into `dir`, returning the extracted files.
+///
+/// Entries whose path is absolute, leaves `dir` via `..` or is below a symlink within `dir` are
+/// rejected, so archives cannot write outside of `dir`. Existing files are overwritten, symlinks
+/// at the path of a file are replaced. Checksums of all entries are verified.
+pub(crate) fn extract(archive: &Path, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut file = File::open(archive)?;
+    let entries = central_directory(&mut file)?;
+    // Check all paths first, so nothing is extracted from malicious archives.
+    let paths = entries
+        .iter()
+        .map(|entry| destination(dir, &entry.name))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut extracted = vec![];
+    for (entry, path) in entries.into_iter().zip(paths) {
+        if entry.name.ends_with('/') {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut header = [0; 30];
+        file.read_exact(&mut header)?;
+        if le32(&header, 0) != LOCAL_HEADER {
+            return Err(invalid(format!("missing local header of {:?}", entry.name)));
+        }
+        let skip = u64::from(le16(&header, 26)) + u64::from(le16(&header, 28));
+        file.seek(SeekFrom::Current(skip as i64))?;
+
+        remove_symlink(&path)?;
+        let mut out = io::BufWriter::new(File::create(&path)?);
+        let mut data = (&mut file).take(entry.compressed);
+        let mut crc = Crc32::new();
+        let size = match entry.method {
+            METHOD_STORED => copy(&mut data, &mut out, &mut crc)?,
+            METHOD_DEFLATE => inflate_into(&mut data, &mut out, &mut crc)?,
+            method => {
+                return Err(invalid(format!(
+                    "unsupported compression method {} of {:?}",
+                    method, entry.name
+                )))
+            }
+        };
+        out.flush()?;
+        if size != entry.size || crc.finish() != entry.crc {
+            return Err(invalid(format!("corrupted zip entry {:?}", entry.name)));
+        }
+        drop(out);
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.mode.filter(|mode| mode & 0o111 != 0) {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        #[cfg(not(unix))]
+        let _ = entry.mode;
+
+        extracted.push(path);
+    }
+    Ok(extracted)
+}
+
+/// Turns an entry name into a relative path that stays within the extraction directory.
+fn safe_path(name: &str) -> io::Result<PathBuf> {
+    // Some archivers use backslashes, which must not be able to smuggle in parent directories.
+    crate::paths::safe_relative_path(Path::new(&name.replace('\\', "/")))
+        .ok_or_else(|| invalid(format!("refusing to extract zip entry {:?}", name)))
+}
+
+/// Returns where to extract the entry `name` into `dir`.
+///
+/// Fails for names leaving `dir` and for names below a symlink already present in `dir`, which
+/// creating the file would follow.
+pub(super) fn destination(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let relative = safe_path(name)?;
+    if let Some(parent) = crate::paths::symlinked_parent(dir, &relative) {
+        return Err(invalid(format!(
+            "refusing to extract zip entry {:?} below the symlink {}",
+            name,
+            parent.display()
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Removes a symlink at `path`, so that creating a file there doesn't write through it.
+pub(super) fn remove_symlink(path: &Path) -> io::Result<()> {
+    if path
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_symlink())
+    {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn copy(input: &mut impl Read, out: &mut impl Write, crc: &mut Crc32) -> io::Result<u64> {
+    let mut buf = vec![0; 1 << 16];
+    let mut size = 0;
+    loop {
+        let len = input.read(&mut buf)?;
+        if len == 0 {
+            return Ok(size);
+        }
+        crc.update(&buf[..len]);
+        out.write_all(&buf[..len])?;
+        size += len as u64;
+    }
+}
+
+fn inflate_into(input: &mut impl Read, out: &mut impl Write, crc: &mut Crc32) -> io::Result<u64> {
+    let mut state = InflateState::new_boxed(DataFormat::Raw);
+    let mut in_buf = vec![0; 1 << 16];
+    let mut out_buf = vec![0; 1 << 16];
+    let (mut start, mut end) = (0, 0);
+    let mut eof = false;
+    let mut size = 0;
+    loop {
+        if start == end && !eof {
+            start = 0;
+            end = input.read(&mut in_buf)?;
+            eof = end == 0;
+        }
+        let flush = if eof { MZFlush::Finish } else { MZFlush::None };
+        let result = inflate(&mut state, &in_buf[start..end], &mut out_buf, flush);
+        start += result.bytes_consumed;
+        let written = &out_buf[..result.bytes_written];
+        crc.update(written);
+        out.write_all(written)?;
+        size += written.len() as u64;
+        match result.status {
+            Ok(MZStatus::StreamEnd) => return Ok(size),
+            Ok(_) => {}
+            // No progress without further input.
+            Err(MZError::Buf) if !eof => {}
+            Err(MZError::Buf) => return Err(invalid("truncated deflate data")),
+            Err(err) => return Err(invalid(format!("invalid deflate data: {:?}", err))),
+        }
+    }
+}
+
+/// Reads the central directory, supporting Zip64 archives.
+fn central_directory(file: &mut File) -> io::Result<Vec<CentralEntry>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    // The end record is 22 bytes followed by a comment of at most 64 KiB.
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| le32(&tail, at) == END_OF_CENTRAL_DIRECTORY)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let mut count = u64::from(le16(&tail, end + 10));
+    let mut cd_size = u64::from(le32(&tail, end + 12));
+    let mut cd_offset = u64::from(le32(&tail, end + 16));
+
+    if end >= 20 && le32(&tail, end - 20) == ZIP64_LOCATOR {
+        let zip64_end = le64(&tail, end - 20 + 8);
+        file.seek(SeekFrom::Start(zip64_end))?;
+        let mut record = [0; 56];
+        file.read_exact(&mut record)?;
+        if le32(&record, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY {
+            return Err(invalid("invalid zip64 end of central directory"));
+        }
+        count = le64(&record, 32);
+        cd_size = le64(&record, 40);
+        cd_offset = le64(&record, 48);
+    }
+
+    file.seek(SeekFrom::Start(cd_offset))?;
+    let mut cd = vec![];
+    file.take(cd_size).read_to_end(&mut cd)?;
+
+    let mut entries = vec![];
+    let mut at = 0;
+    for _ in 0..count {
+        if at + 46 > cd.len() || le32(&cd, at) != CENTRAL_HEADER {
+            return Err(invalid("invalid central directory"));
+        }
+        let made_by = le16(&cd, at + 4) >> 8;
+        let name_len = usize::from(le16(&cd, at + 28));
+        let extra_len = usize::from(le16(&cd, at + 30));
+        let comment_len = usize::from(le16(&cd, at + 32));
+        let next = at + 46 + name_len + extra_len + comment_len;
+        if next > cd.len() {
+            return Err(invalid("invalid central directory"));
+        }
+
+        let mut entry = CentralEntry {
+            name: String::from_utf8_lossy(&cd[at + 46..at + 46 + name_len]).into_owned(),
+            method: le16(&cd, at + 10),
+            crc: le32(&cd, at + 16),
+            compressed: u64::from(le32(&cd, at + 20)),
+            size: u64::from(le32(&cd, at + 24)),
+            offset: u64::from(le32(&cd, at + 42)),
+            mode: (made_by == 3).then(|| le32(&cd, at + 38) >> 16),
+        };
+
+        // Fields that don't fit are stored in the zip64 extra field, in this order.
+        let mut extra = &cd[at + 46 + name_len..at + 46 + name_len + extra_len];
+        while extra.len() >= 4 {
+            let (id, len) = (le16(extra, 0), usize::from(le16(extra, 2)));
+            let data = &extra[4..(4 + len).min(extra.len())];
+            if id == ZIP64_EXTRA {
+                let mut fields = data.chunks_exact(8).map(|field| le64(field, 0));
+                for value in [&mut entry.size, &mut entry.compressed, &mut entry.offset] {
+                    if *value == u64::from(u32::MAX) {
+                        *value = fields
+                            .next()
+                            .ok_or_else(|| invalid("invalid zip64 field"))?;
+                    }
+                }
+            }
+            extra = &extra[(4 + len).min(extra.len())..];
+        }
+
+        entries.push(entry);
+        at = next;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zip-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes an archive with the given entries to `path`.
+    fn write_archive(path: &Path, entries: &[(&str, &[u8], u32)]) {
+        let mut writer = ZipWriter::new();
+        for &(name, data, mode) in entries {
+            let entry = Entry {
+                name,
+                data,
+                modified: SystemTime::now(),
+                mode,
+            };
+            writer.add(Compressed::new(entry).unwrap()).unwrap();
+        }
+        std::fs::write(path, writer.finish().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn extracts_written_archive() {
+        let dir = temp_dir("round-trip");
+        let archive = dir.join("archive.zip");
+        let compressible = vec![b'a'; 4096];
+        let incompressible: Vec<u8> = (0..256u32).map(|i| (i * 167 % 256) as u8).collect();
+        write_archive(
+            &archive,
+            &[
+                ("a.txt", &compressible, 0o100644),
+                ("sub/dir/b.bin", &incompressible, 0o100755),
+                ("empty", b"", 0o100644),
+            ],
+        );
+
+        let out = dir.join("out");
+        let files = extract(&archive, &out).unwrap();
+        assert_eq!(
+            files,
+            [
+                out.join("a.txt"),
+                out.join("sub/dir/b.bin"),
+                out.join("empty")
+            ]
+        );
+        assert_eq!(std::fs::read(out.join("a.txt")).unwrap(), compressible);
+        assert_eq!(
+            std::fs::read(out.join("sub/dir/b.bin")).unwrap(),
+            incompressible
+        );
+        assert_eq!(std::fs::read(out.join("empty")).unwrap(), b"");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &str| {
+                std::fs::metadata(out.join(path))
+                    .unwrap()
+                    .permissions()
+                    .mode()
+            };
+            assert_eq!(mode("sub/dir/b.bin") & 0o111, 0o111);
+            assert_eq!(mode("a.txt") & 0o111, 0);
+        }
+    }
+
+    #[test]
+    fn rejects_names_leaving_the_directory() {
+        let dir = temp_dir("escape");
+        let absolute = dir.join("absolute.txt");
+        let out = dir.join("out");
+        for name in [
+            "../escaped.txt",
+            absolute.to_str().unwrap(),
+            "..\\escaped.txt",
+            "a\\..\\..\\escaped.txt",
+            "",
+        ] {
+            let archive = dir.join("archive.zip");
+            write_archive(
+                &archive,
+                &[("ok.txt", b"ok", 0o100644), (name, b"evil", 0o100644)],
+            );
+            let err = extract(&archive, &out).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", name);
+        }
+        // Paths are checked before extracting anything.
+        assert!(!out.join("ok.txt").exists());
+        assert!(!dir.join("escaped.txt").exists());
+        assert!(!absolute.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_names_below_symlinks() {
+        let dir = temp_dir("symlink");
+        let (out, elsewhere) = (dir.join("out"), dir.join("elsewhere"));
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, out.join("link")).unwrap();
+
+        let archive = dir.join("archive.zip");
+        write_archive(&archive, &[("link/x", b"evil", 0o100644)]);
+        let err = extract(&archive, &out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!elsewhere.join("x").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_symlinks_at_file_paths() {
+        let dir = temp_dir("replace-symlink");
+        let (out, target) = (dir.join("out"), dir.join("target"));
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(&target, "untouched").unwrap();
+        std::os::unix::fs::symlink(&target, out.join("file")).unwrap();
+
+        let archive = dir.join("archive.zip");
+        write_archive(&archive, &[("file", b"extracted", 0o100644)]);
+        extract(&archive, &out).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"untouched");
+        assert_eq!(std::fs::read(out.join("file")).unwrap(), b"extracted");
+        assert!(!out.join("file").symlink_metadata().unwrap().is_symlink());
+    }
+}
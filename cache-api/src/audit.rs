@@ -0,0 +1,100 @@
+//! Structured audit log of cache operations.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{stats::Tracker, Error};
+
+/// Appends one JSON record per line to a file.
+pub(crate) struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends a record, logging failures instead of failing the cache operation.
+    pub(crate) fn append(&self, record: &Record) {
+        let mut line = serde_json::to_vec(record).expect("audit records are serializable");
+        line.push(b'\n');
+        // A single write keeps lines intact when multiple processes append to the same file.
+        if let Err(err) = self.file.lock().unwrap().write_all(&line) {
+            tracing::debug!(%err, "failed to write audit log record");
+        }
+    }
+}
+
+/// A single audit log record.
+#[derive(Serialize)]
+pub(crate) struct Record<'a> {
+    /// Seconds since the Unix epoch at which the operation started.
+    timestamp: f64,
+    operation: &'static str,
+    key_space: &'a str,
+    /// Requested keys or key prefixes.
+    keys: &'a [&'a str],
+    /// Matched or stored key.
+    key: Option<&'a str>,
+    /// One of `hit`, `miss`, `stored`, `skipped` or `error`.
+    result: &'static str,
+    bytes: Option<u64>,
+    duration_ms: u64,
+    attempts: u32,
+    error: Option<String>,
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn new(
+        operation: &'static str,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+        start: Instant,
+        tracker: &Tracker,
+    ) -> Self {
+        let elapsed = start.elapsed();
+        let timestamp = SystemTime::now()
+            .checked_sub(elapsed)
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0.0, |time| time.as_secs_f64());
+        Self {
+            timestamp,
+            operation,
+            key_space,
+            keys,
+            key: None,
+            result: "error",
+            bytes: None,
+            duration_ms: elapsed.as_millis() as u64,
+            attempts: tracker.attempts(),
+            error: None,
+        }
+    }
+
+    pub(crate) fn result(mut self, result: &'static str, key: Option<&'a str>) -> Self {
+        self.result = result;
+        self.key = key;
+        self
+    }
+
+    pub(crate) fn bytes(mut self, bytes: u64) -> Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    pub(crate) fn error(mut self, error: &Error) -> Self {
+        self.result = "error";
+        self.error = Some(error.to_string());
+        self
+    }
+}
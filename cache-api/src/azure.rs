@@ -0,0 +1,769 @@
+//! Caching in a user-provided Azure Blob Storage container.
+//!
+//! Unlike GitHub's cache, the container is not limited in size, entries are not evicted unless
+//! a lifecycle policy is configured, and all runs, no matter their branch, see the same entries.
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use bytes::Bytes;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use reqwest::{Client, Request, RequestBuilder, Response, Url};
+use serde::Deserialize;
+use tokio::{io::AsyncReadExt, sync::Mutex};
+
+use crate::{
+    backend::BoxFuture, block_blob, digest, error::error_for_response, CacheBackend, CacheHit,
+    Error, MatchKind, NoRetry, Result, RetryPolicy,
+};
+
+/// Version of the Blob service REST API used for requests.
+const API_VERSION: &str = "2021-08-06";
+
+/// Endpoint of the Azure Instance Metadata Service issuing managed identity tokens.
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Time before expiry at which managed identity tokens are refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+enum Credentials {
+    SharedKey {
+        account: String,
+        key: Vec<u8>,
+    },
+    /// A query string appended to every request.
+    Sas(String),
+    ManagedIdentity {
+        client_id: Option<String>,
+        cached: Mutex<Option<(String, SystemTime)>>,
+    },
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_on: String,
+}
+
+/// A [`CacheBackend`] storing entries as blobs of an Azure Blob Storage container.
+///
+/// Entries are stored as blobs named `{prefix}{key_space}/{key}`, and are never overwritten.
+/// Large files are uploaded in blocks, with several blocks in flight. Lookups list the blobs
+/// starting with each key prefix and pick an exact match or the most recently modified blob.
+/// The scope of hits is the container's name.
+pub struct AzureBlobCache {
+    client: Client,
+    container_url: Url,
+    container: String,
+    credentials: Credentials,
+    prefix: String,
+    block_size: usize,
+    concurrency: usize,
+    retry_policy: Box<dyn RetryPolicy>,
+}
+
+impl AzureBlobCache {
+    fn new(container_url: Url, credentials: Credentials) -> Result<Self> {
+        let container = container_url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                Error::InvalidCredentials(format!("no container in URL {}", container_url))
+            })?
+            .to_owned();
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(concat!(
+                    "rust-actions-cache-api/",
+                    env!("CARGO_PKG_VERSION")
+                ))
+                .build()?,
+            container_url,
+            container,
+            credentials,
+            prefix: String::new(),
+            block_size: 8 << 20,
+            concurrency: 4,
+            retry_policy: Box::new(NoRetry),
+        })
+    }
+
+    /// Creates a backend for the named container of the account given by a connection string.
+    ///
+    /// The connection string is the one shown in the Azure portal, with either an
+    /// `AccountKey` or a `SharedAccessSignature`. Custom endpoints, e.g. of an emulator, are
+    /// given with `BlobEndpoint`.
+    pub fn from_connection_string(connection_string: &str, container: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::InvalidCredentials(format!("{} in connection string", reason));
+        let fields: BTreeMap<&str, &str> = connection_string
+            .split(';')
+            .filter(|field| !field.trim().is_empty())
+            .map(|field| {
+                field
+                    .split_once('=')
+                    .ok_or_else(|| invalid("malformed field"))
+            })
+            .collect::<Result<_>>()?;
+
+        let account = fields.get("AccountName").copied();
+        let endpoint = match (fields.get("BlobEndpoint"), account) {
+            (Some(endpoint), _) => endpoint.trim_end_matches('/').to_owned(),
+            (None, Some(account)) => format!(
+                "{}://{}.blob.{}",
+                fields.get("DefaultEndpointsProtocol").unwrap_or(&"https"),
+                account,
+                fields.get("EndpointSuffix").unwrap_or(&"core.windows.net"),
+            ),
+            (None, None) => return Err(invalid("no AccountName or BlobEndpoint")),
+        };
+        let container_url = Url::parse(&format!("{}/{}", endpoint, container))
+            .map_err(|err| invalid(&format!("invalid endpoint ({})", err)))?;
+
+        let credentials = match (
+            fields.get("SharedAccessSignature"),
+            fields.get("AccountKey"),
+        ) {
+            (Some(sas), _) => Credentials::Sas(sas.trim_start_matches('?').to_owned()),
+            (None, Some(key)) => Credentials::SharedKey {
+                account: account.ok_or_else(|| invalid("no AccountName"))?.to_owned(),
+                key: base64::engine::general_purpose::STANDARD
+                    .decode(key)
+                    .map_err(|_| invalid("invalid AccountKey"))?,
+            },
+            (None, None) => return Err(invalid("no AccountKey or SharedAccessSignature")),
+        };
+        Self::new(container_url, credentials)
+    }
+
+    /// Creates a backend for the container at `container_url`, authenticating with the managed
+    /// identity of the Azure VM the runner is hosted on.
+    ///
+    /// The identity needs a role granting read and write access to blobs, like "Storage Blob
+    /// Data Contributor". If the VM has several identities, select one using `client_id`.
+    pub fn with_managed_identity(container_url: &str, client_id: Option<&str>) -> Result<Self> {
+        let container_url = Url::parse(container_url.trim_end_matches('/'))
+            .map_err(|err| Error::InvalidCredentials(format!("invalid container URL: {}", err)))?;
+        Self::new(
+            container_url,
+            Credentials::ManagedIdentity {
+                client_id: client_id.map(str::to_owned),
+                cached: Mutex::new(None),
+            },
+        )
+    }
+
+    /// Sets a prefix prepended to the names of blobs, e.g. `"cache/"`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets the size of blocks for uploading files, 8 MiB by default.
+    ///
+    /// Smaller files are uploaded with a single request.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Sets the number of blocks uploaded at once, 4 by default.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the policy for retrying failed requests, by default requests are not retried.
+    pub fn with_retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Box::new(retry_policy);
+        self
+    }
+
+    /// Returns the URL of the container, without credentials.
+    pub fn container_url(&self) -> &Url {
+        &self.container_url
+    }
+
+    fn blob_name(&self, key_space: &str, key: &str) -> String {
+        format!("{}{}/{}", self.prefix, key_space, key)
+    }
+
+    fn with_sas(&self, mut url: Url) -> Url {
+        if let Credentials::Sas(sas) = &self.credentials {
+            let query = match url.query() {
+                Some(query) => format!("{}&{}", query, sas),
+                None => sas.clone(),
+            };
+            url.set_query(Some(&query));
+        }
+        url
+    }
+
+    fn blob_url(&self, name: &str) -> String {
+        let mut url = self.container_url.clone();
+        url.path_segments_mut()
+            .expect("container URLs have a path")
+            .extend(name.split('/'));
+        self.with_sas(url).into()
+    }
+
+    fn container_list_url(&self) -> String {
+        self.with_sas(self.container_url.clone()).into()
+    }
+
+    /// Sends a request, adding authentication and retrying according to the retry policy.
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let mut request = request.header("x-ms-version", API_VERSION).build()?;
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            let retry_request = request.try_clone();
+            attempts += 1;
+
+            let url = request.url().clone();
+            let result = async {
+                self.authorize(&mut request).await?;
+                let response = self.client.execute(request).await?;
+                error_for_response(response, url, None, SystemTime::now()).await
+            }
+            .await;
+
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(error) => error.redacted(""),
+            };
+            let delay = self
+                .retry_policy
+                .retry_delay(attempts, start.elapsed(), &error);
+            match (retry_request, delay) {
+                (Some(retry_request), Some(delay)) => {
+                    tracing::debug!(%error, ?delay, attempts, "retrying blob storage request");
+                    tokio::time::sleep(delay).await;
+                    request = retry_request;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    async fn authorize(&self, request: &mut Request) -> Result<()> {
+        let now = httpdate::fmt_http_date(SystemTime::now());
+        request.headers_mut().insert(
+            "x-ms-date",
+            now.parse().expect("dates are valid header values"),
+        );
+        let authorization = match &self.credentials {
+            Credentials::Sas(_) => return Ok(()),
+            Credentials::SharedKey { account, key } => {
+                format!(
+                    "SharedKey {}:{}",
+                    account,
+                    shared_key_signature(account, key, request)
+                )
+            }
+            Credentials::ManagedIdentity { client_id, cached } => {
+                format!(
+                    "Bearer {}",
+                    self.managed_identity_token(client_id, cached).await?
+                )
+            }
+        };
+        request.headers_mut().insert(
+            reqwest::header::AUTHORIZATION,
+            authorization
+                .parse()
+                .map_err(|_| Error::InvalidCredentials("invalid access token".to_owned()))?,
+        );
+        Ok(())
+    }
+
+    async fn managed_identity_token(
+        &self,
+        client_id: &Option<String>,
+        cached: &Mutex<Option<(String, SystemTime)>>,
+    ) -> Result<String> {
+        let mut cached = cached.lock().await;
+        if let Some((token, expires_at)) = &*cached {
+            if SystemTime::now() + REFRESH_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let mut query = vec![
+            ("api-version", "2018-02-01"),
+            ("resource", "https://storage.azure.com/"),
+        ];
+        if let Some(client_id) = client_id {
+            query.push(("client_id", client_id));
+        }
+        let request = self
+            .client
+            .get(IMDS_TOKEN_URL)
+            .query(&query)
+            .header("Metadata", "true")
+            .build()?;
+        let url = request.url().clone();
+        let response = self.client.execute(request).await?;
+        let response: TokenResponse = error_for_response(response, url, None, SystemTime::now())
+            .await?
+            .json()
+            .await?;
+        let expires_on = response
+            .expires_on
+            .parse()
+            .map_err(|_| Error::InvalidCredentials("invalid token expiry".to_owned()))?;
+        let expires_at = UNIX_EPOCH + Duration::from_secs(expires_on);
+        *cached = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    /// Returns the names and modification times of the blobs starting with `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<(String, SystemTime)>> {
+        let mut blobs = vec![];
+        let mut marker: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("restype", "container"),
+                ("comp", "list"),
+                ("prefix", prefix),
+            ];
+            if let Some(marker) = &marker {
+                query.push(("marker", marker.as_str()));
+            }
+            let request = self.client.get(self.container_list_url()).query(&query);
+            let body = self.send(request).await?.text().await?;
+
+            for blob in body.split("<Blob>").skip(1) {
+                let Some(name) = block_blob::element(blob, "Name") else {
+                    continue;
+                };
+                let modified = block_blob::element(blob, "Last-Modified")
+                    .and_then(|date| httpdate::parse_http_date(date).ok())
+                    .unwrap_or(UNIX_EPOCH);
+                blobs.push((unescape_xml(name), modified));
+            }
+            match block_blob::element(&body, "NextMarker") {
+                Some(next) if !next.is_empty() => marker = Some(next.to_owned()),
+                _ => return Ok(blobs),
+            }
+        }
+    }
+
+    async fn find(&self, key_space: &str, keys: &[&str]) -> Result<Option<CacheHit>> {
+        let space_prefix = self.blob_name(key_space, "");
+        for (index, key) in keys.iter().enumerate() {
+            let name = self.blob_name(key_space, key);
+            let blobs = self.list(&name).await?;
+            let found = blobs
+                .iter()
+                .find(|(blob, _)| *blob == name)
+                .or_else(|| blobs.iter().max_by_key(|(_, modified)| *modified));
+            if let Some((blob, _)) = found {
+                let match_kind = if index == 0 && *blob == name {
+                    MatchKind::Exact
+                } else {
+                    MatchKind::Prefix
+                };
+                return Ok(Some(CacheHit {
+                    key: blob[space_prefix.len()..].to_owned(),
+                    scope: self.container.clone(),
+                    match_kind,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Refuses to overwrite existing blobs, reporting them as [`Error::Conflict`].
+    fn create_only(request: RequestBuilder) -> RequestBuilder {
+        request.header(reqwest::header::IF_NONE_MATCH, "*")
+    }
+
+    async fn put_blob(&self, name: &str, data: Bytes) -> Result<()> {
+        let request = self
+            .client
+            .put(self.blob_url(name))
+            .header("x-ms-blob-type", "BlockBlob")
+            .body(data);
+        conflict_on_precondition(self.send(Self::create_only(request)).await)?;
+        Ok(())
+    }
+
+    async fn put_blob_blocks(
+        &self,
+        name: &str,
+        file: &mut tokio::fs::File,
+        size: u64,
+    ) -> Result<()> {
+        let url = self.blob_url(name);
+        let block_size = block_blob::block_size(self.block_size, size);
+        // Unique to this upload, so concurrent uploads of the same blob don't mix their blocks.
+        let upload = digest::sha256_hex(
+            format!("{}\n{}\n{:?}", name, std::process::id(), SystemTime::now()).as_bytes(),
+        );
+
+        let mut block_ids = vec![];
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            let mut block = Vec::with_capacity(block_size as usize);
+            (&mut *file)
+                .take(block_size)
+                .read_to_end(&mut block)
+                .await?;
+            if block.is_empty() {
+                break;
+            }
+            let id = base64::engine::general_purpose::STANDARD.encode(format!(
+                "{}-{:08}",
+                &upload[..16],
+                block_ids.len()
+            ));
+            block_ids.push(id.clone());
+
+            if in_flight.len() >= self.concurrency {
+                if let Some(result) = in_flight.next().await {
+                    result?;
+                }
+            }
+            let request = block_blob::put_block(&self.client, &url, &id, block.into());
+            in_flight.push(self.send(request));
+        }
+        while let Some(result) = in_flight.next().await {
+            result?;
+        }
+
+        let request = block_blob::put_block_list(&self.client, &url, &block_ids);
+        conflict_on_precondition(self.send(Self::create_only(request)).await)?;
+        Ok(())
+    }
+}
+
+impl CacheBackend for AzureBlobCache {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(self.find(key_space, key_prefixes))
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let Some(hit) = self.find(key_space, keys).await? else {
+                return Ok(None);
+            };
+            let request = self
+                .client
+                .get(self.blob_url(&self.blob_name(key_space, &hit.key)));
+            match self.send(request).await {
+                Ok(response) => Ok(Some((hit, response.bytes().await?))),
+                // Deleted since it was listed.
+                Err(Error::NotFound(_)) => Ok(None),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.put_blob(&self.blob_name(key_space, key), data).await })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let name = self.blob_name(key_space, key);
+            let mut file = tokio::fs::File::open(path).await?;
+            let size = file.metadata().await?.len();
+            if size <= self.block_size as u64 {
+                let mut data = Vec::with_capacity(size as usize);
+                file.read_to_end(&mut data).await?;
+                self.put_blob(&name, data.into()).await
+            } else {
+                self.put_blob_blocks(&name, &mut file, size).await
+            }
+        })
+    }
+}
+
+/// Reports failed preconditions, i.e. a blob that already exists, as [`Error::Conflict`].
+fn conflict_on_precondition(result: Result<Response>) -> Result<Response> {
+    match result {
+        Err(Error::Status(err)) if err.status == reqwest::StatusCode::PRECONDITION_FAILED => {
+            Err(Error::Conflict(err))
+        }
+        result => result,
+    }
+}
+
+/// Returns the signature of a request for Shared Key authorization.
+fn shared_key_signature(account: &str, key: &[u8], request: &Request) -> String {
+    base64::engine::general_purpose::STANDARD.encode(digest::hmac_sha256(
+        key,
+        string_to_sign(account, request).as_bytes(),
+    ))
+}
+
+/// Returns the string signed for Shared Key authorization of `request`.
+fn string_to_sign(account: &str, request: &Request) -> String {
+    let headers = request.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+    };
+    let length = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map_or(0, <[u8]>::len);
+
+    let mut string_to_sign = request.method().as_str().to_owned();
+    for value in [
+        header("content-encoding"),
+        header("content-language"),
+        &if length == 0 {
+            String::new()
+        } else {
+            length.to_string()
+        },
+        header("content-md5"),
+        header("content-type"),
+        // The date is given with `x-ms-date`.
+        "",
+        header("if-modified-since"),
+        header("if-match"),
+        header("if-none-match"),
+        header("if-unmodified-since"),
+        header("range"),
+    ] {
+        string_to_sign.push('\n');
+        string_to_sign.push_str(value);
+    }
+    string_to_sign.push('\n');
+
+    let ms_headers: BTreeMap<&str, &str> = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| (name.as_str(), value.to_str().unwrap_or("").trim()))
+        .collect();
+    for (name, value) in ms_headers {
+        string_to_sign.push_str(&format!("{}:{}\n", name, value));
+    }
+
+    string_to_sign.push_str(&format!("/{}{}", account, request.url().path()));
+    let mut params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, value) in request.url().query_pairs() {
+        params
+            .entry(name.to_lowercase())
+            .or_default()
+            .push(value.into_owned());
+    }
+    for (name, mut values) in params {
+        values.sort();
+        string_to_sign.push_str(&format!("\n{}:{}", name, values.join(",")));
+    }
+    string_to_sign
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The well-known key of the storage emulator's account.
+    const EMULATOR_KEY: &str =
+        "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+
+    const DATE: &str = "Sun, 11 Oct 2009 21:49:13 GMT";
+
+    fn emulator_key() -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD
+            .decode(EMULATOR_KEY)
+            .unwrap()
+    }
+
+    /// Based on the example of the Azure Storage documentation for authorizing with Shared Key.
+    #[test]
+    fn signs_documented_example() {
+        let request = Client::new()
+            .get(
+                "https://myaccount.blob.core.windows.net/mycontainer\
+                 ?restype=container&comp=metadata&timeout=20",
+            )
+            .header("x-ms-date", DATE)
+            .header("x-ms-version", "2009-09-19")
+            .build()
+            .unwrap();
+        assert_eq!(
+            string_to_sign("myaccount", &request),
+            "GET\n\n\n\n\n\n\n\n\n\n\n\n\
+             x-ms-date:Sun, 11 Oct 2009 21:49:13 GMT\n\
+             x-ms-version:2009-09-19\n\
+             /myaccount/mycontainer\n\
+             comp:metadata\n\
+             restype:container\n\
+             timeout:20"
+        );
+        assert_eq!(
+            shared_key_signature("myaccount", &emulator_key(), &request),
+            "m649E40iEJ3QQyCg9/WI2Fa9zS+RB/2rEBcLJb0CKs0="
+        );
+    }
+
+    #[test]
+    fn signs_headers_and_content_length() {
+        let request = Client::new()
+            .put("https://myaccount.blob.core.windows.net/mycontainer/key%20space/key")
+            .header("content-type", "application/octet-stream")
+            .header("if-none-match", "*")
+            .header("x-ms-version", "2009-09-19")
+            .header("x-ms-date", DATE)
+            .header("x-ms-blob-type", "BlockBlob")
+            .body("hello")
+            .build()
+            .unwrap();
+        assert_eq!(
+            string_to_sign("myaccount", &request),
+            "PUT\n\n\n5\n\napplication/octet-stream\n\n\n\n*\n\n\n\
+             x-ms-blob-type:BlockBlob\n\
+             x-ms-date:Sun, 11 Oct 2009 21:49:13 GMT\n\
+             x-ms-version:2009-09-19\n\
+             /myaccount/mycontainer/key%20space/key"
+        );
+        assert_eq!(
+            shared_key_signature("myaccount", &emulator_key(), &request),
+            "xeIb/Bk33fj1arSR+wFumWWsIdkA4KBue8sbF0Lrj3s="
+        );
+    }
+
+    #[test]
+    fn signs_empty_bodies_without_content_length() {
+        let request = Client::new()
+            .put("https://myaccount.blob.core.windows.net/mycontainer/blob")
+            .body("")
+            .build()
+            .unwrap();
+        assert!(string_to_sign("myaccount", &request).starts_with("PUT\n\n\n\n"));
+    }
+
+    #[test]
+    fn sorts_and_joins_query_parameters() {
+        let request = Client::new()
+            .get(
+                "https://myaccount.blob.core.windows.net/mycontainer?restype=container\
+                 &comp=list&include=snapshots&include=metadata&include=uncommittedblobs",
+            )
+            .build()
+            .unwrap();
+        assert!(string_to_sign("myaccount", &request).ends_with(
+            "/myaccount/mycontainer\n\
+             comp:list\n\
+             include:metadata,snapshots,uncommittedblobs\n\
+             restype:container"
+        ));
+    }
+
+    #[test]
+    fn parses_account_key_connection_strings() {
+        let cache = AzureBlobCache::from_connection_string(
+            &format!(
+                "DefaultEndpointsProtocol=https;AccountName=myaccount;AccountKey={};\
+                 EndpointSuffix=core.windows.net",
+                EMULATOR_KEY
+            ),
+            "cache",
+        )
+        .unwrap();
+        assert_eq!(
+            cache.container_url().as_str(),
+            "https://myaccount.blob.core.windows.net/cache"
+        );
+        assert_eq!(cache.container, "cache");
+        assert!(matches!(
+            &cache.credentials,
+            Credentials::SharedKey { account, key }
+                if account == "myaccount" && *key == emulator_key()
+        ));
+    }
+
+    #[test]
+    fn parses_blob_endpoint_connection_strings() {
+        let cache = AzureBlobCache::from_connection_string(
+            &format!(
+                "DefaultEndpointsProtocol=http;AccountName=devstoreaccount1;AccountKey={};\
+                 BlobEndpoint=http://127.0.0.1:10000/devstoreaccount1/;",
+                EMULATOR_KEY
+            ),
+            "cache",
+        )
+        .unwrap();
+        assert_eq!(
+            cache.container_url().as_str(),
+            "http://127.0.0.1:10000/devstoreaccount1/cache"
+        );
+        assert!(matches!(
+            &cache.credentials,
+            Credentials::SharedKey { account, .. } if account == "devstoreaccount1"
+        ));
+    }
+
+    #[test]
+    fn parses_shared_access_signature_connection_strings() {
+        let cache = AzureBlobCache::from_connection_string(
+            "BlobEndpoint=https://myaccount.blob.core.windows.net/;\
+             SharedAccessSignature=?sv=2021-06-08&ss=b&sig=a%2Bb%3D",
+            "cache",
+        )
+        .unwrap();
+        assert!(matches!(
+            &cache.credentials,
+            Credentials::Sas(sas) if sas == "sv=2021-06-08&ss=b&sig=a%2Bb%3D"
+        ));
+        assert_eq!(
+            cache.blob_url("space/key"),
+            "https://myaccount.blob.core.windows.net/cache/space/key\
+             ?sv=2021-06-08&ss=b&sig=a%2Bb%3D"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_connection_strings() {
+        for connection_string in [
+            "AccountName=myaccount",
+            "AccountName=myaccount;AccountKey=not base64",
+            "AccountKey=a2V5",
+            "AccountName=myaccount;malformed",
+        ] {
+            let result = AzureBlobCache::from_connection_string(connection_string, "cache");
+            assert!(
+                matches!(result, Err(Error::InvalidCredentials(_))),
+                "{:?}",
+                connection_string
+            );
+        }
+    }
+}
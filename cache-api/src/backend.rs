@@ -0,0 +1,246 @@
+//! Abstraction over cache implementations.
+use std::{future::Future, path::Path, pin::Pin, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{Cache, CacheHit, NamespacedCache, Result};
+
+/// Boxed future returned by [`CacheBackend`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The operations of a cache, independent of how entries are stored.
+///
+/// Implemented by [`Cache`] and [`NamespacedCache`]. Code written against this trait can use
+/// other implementations, e.g. for testing, without changing call sites. The trait is object
+/// safe, so `&dyn CacheBackend` or `Box<dyn CacheBackend>` can be used to select an
+/// implementation at runtime.
+///
+/// See [`Cache::get_url`] for the semantics of `key_space` and key prefixes.
+pub trait CacheBackend: Send + Sync {
+    /// Looks up a matching entry without retrieving its content.
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>>;
+
+    /// Looks up a matching entry and returns its content.
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>>;
+
+    /// Looks up a matching entry and writes its content to a file.
+    ///
+    /// The file is only written for a hit. The default implementation reads the whole entry
+    /// into memory.
+    fn get_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            let Some((hit, data)) = self.get_bytes(key_space, keys).await? else {
+                return Ok(None);
+            };
+            tokio::fs::write(path, &data).await?;
+            Ok(Some(hit))
+        })
+    }
+
+    /// Stores an entry.
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Stores the content of a file as an entry.
+    ///
+    /// The default implementation reads the whole file into memory.
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let data = tokio::fs::read(path).await?;
+            self.put_bytes(key_space, key, data.into()).await
+        })
+    }
+}
+
+impl CacheBackend for Cache {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            Ok(self
+                .get_url(key_space, key_prefixes)
+                .await?
+                .map(|(hit, _)| hit))
+        })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(Cache::get_bytes(self, key_space, keys))
+    }
+
+    fn get_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            Ok(Cache::get_file(self, key_space, keys, path)
+                .await?
+                .map(|(hit, _)| hit))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            Cache::put_bytes(self, key_space, key, data).await?;
+            Ok(())
+        })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            Cache::put_file(self, key_space, key, path).await?;
+            Ok(())
+        })
+    }
+}
+
+impl CacheBackend for NamespacedCache<'_> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            Ok(self
+                .get_url(key_space, key_prefixes)
+                .await?
+                .map(|(hit, _)| hit))
+        })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(NamespacedCache::get_bytes(self, key_space, keys))
+    }
+
+    fn get_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            Ok(NamespacedCache::get_file(self, key_space, keys, path)
+                .await?
+                .map(|(hit, _)| hit))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            NamespacedCache::put_bytes(self, key_space, key, data).await?;
+            Ok(())
+        })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            NamespacedCache::put_file(self, key_space, key, path).await?;
+            Ok(())
+        })
+    }
+}
+
+macro_rules! forward_backend {
+    ($($ty:ty),*) => {$(
+        impl<T: CacheBackend + ?Sized> CacheBackend for $ty {
+            fn lookup<'a>(
+                &'a self,
+                key_space: &'a str,
+                key_prefixes: &'a [&'a str],
+            ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+                (**self).lookup(key_space, key_prefixes)
+            }
+
+            fn get_bytes<'a>(
+                &'a self,
+                key_space: &'a str,
+                keys: &'a [&'a str],
+            ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+                (**self).get_bytes(key_space, keys)
+            }
+
+            fn get_file<'a>(
+                &'a self,
+                key_space: &'a str,
+                keys: &'a [&'a str],
+                path: &'a Path,
+            ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+                (**self).get_file(key_space, keys, path)
+            }
+
+            fn put_bytes<'a>(
+                &'a self,
+                key_space: &'a str,
+                key: &'a str,
+                data: Bytes,
+            ) -> BoxFuture<'a, Result<()>> {
+                (**self).put_bytes(key_space, key, data)
+            }
+
+            fn put_file<'a>(
+                &'a self,
+                key_space: &'a str,
+                key: &'a str,
+                path: &'a Path,
+            ) -> BoxFuture<'a, Result<()>> {
+                (**self).put_file(key_space, key, path)
+            }
+        }
+    )*};
+}
+
+forward_backend!(&T, Box<T>, Arc<T>);
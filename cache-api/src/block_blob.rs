@@ -0,0 +1,69 @@
+//! Requests of Azure's block blob API, used for artifact uploads and the Azure Blob backend.
+use std::collections::HashMap;
+
+use base64::Engine;
+use bytes::Bytes;
+use reqwest::{Client, RequestBuilder};
+
+/// Maximal number of blocks of a blob.
+pub(crate) const MAX_BLOCKS: usize = 50_000;
+
+/// Returns the size of blocks for a blob, growing the configured size for huge blobs so they
+/// stay within the block limit.
+pub(crate) fn block_size(configured: usize, blob_size: u64) -> u64 {
+    (configured as u64).max(blob_size.div_ceil(MAX_BLOCKS as u64))
+}
+
+/// Returns the id of the block at `index`, all ids of a blob have to be of the same length.
+pub(crate) fn block_id(index: usize) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!("block-{:08}", index))
+}
+
+/// Stages a block of the blob at `url`.
+pub(crate) fn put_block(client: &Client, url: &str, id: &str, data: Bytes) -> RequestBuilder {
+    client
+        .put(url)
+        .query(&[("comp", "block"), ("blockid", id)])
+        .body(data)
+}
+
+/// Commits the staged blocks with the given ids as the content of the blob at `url`.
+pub(crate) fn put_block_list(client: &Client, url: &str, block_ids: &[String]) -> RequestBuilder {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+    for id in block_ids {
+        body.push_str("<Latest>");
+        body.push_str(id);
+        body.push_str("</Latest>");
+    }
+    body.push_str("</BlockList>");
+
+    client.put(url).query(&[("comp", "blocklist")]).body(body)
+}
+
+/// Lists the blocks staged but not yet committed for the blob at `url`, see
+/// [`parse_block_list`].
+pub(crate) fn get_uncommitted_blocks(client: &Client, url: &str) -> RequestBuilder {
+    client
+        .get(url)
+        .query(&[("comp", "blocklist"), ("blocklisttype", "uncommitted")])
+}
+
+/// Returns the sizes of the blocks of a block list response, by block id.
+pub(crate) fn parse_block_list(body: &str) -> HashMap<String, u64> {
+    let mut blocks = HashMap::new();
+    for block in body.split("<Block>").skip(1) {
+        if let (Some(name), Some(size)) = (element(block, "Name"), element(block, "Size")) {
+            if let Ok(size) = size.parse() {
+                blocks.insert(name.to_owned(), size);
+            }
+        }
+    }
+    blocks
+}
+
+/// Returns the content of the first element with the given tag.
+pub(crate) fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
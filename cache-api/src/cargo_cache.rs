@@ -0,0 +1,282 @@
+//! Caching of Cargo's downloads and build artifacts, like [`Swatinem/rust-cache`].
+//!
+//! Two [snapshots][Snapshot] are stored per key. One holds the registry index, the downloaded
+//! crates and git dependencies from Cargo's home directory. Extracted sources are left out, as
+//! Cargo recreates them from the downloaded crates. The other holds the target directory, which
+//! is cleaned before saving so it only contains artifacts of dependencies. Artifacts of the
+//! workspace's own packages are rebuilt anyway when their sources change, and would otherwise
+//! accumulate over time.
+//!
+//! [`Swatinem/rust-cache`]: https://github.com/Swatinem/rust-cache
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    digest, error,
+    key::{self, CacheKey, RestoreKeys},
+    snapshot::Snapshot,
+    CacheBackend, CacheHit, Error, Result,
+};
+
+/// Paths within Cargo's home directory that are cached.
+const HOME_PATHS: &[&str] = &["registry/index", "registry/cache", "git/db"];
+
+/// Files whose content determines the dependencies and the toolchain, hashed for the key.
+const KEY_FILES: &[&str] = &[
+    "**/Cargo.toml",
+    "**/Cargo.lock",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    "!target/**",
+];
+
+/// Entries restored by [`CargoCache::restore`].
+#[derive(Debug, Default)]
+pub struct Restored {
+    /// The entry restored into Cargo's home directory.
+    pub home: Option<CacheHit>,
+    /// The entry restored into the target directory.
+    pub target: Option<CacheHit>,
+}
+
+/// The cache of a Cargo workspace's dependencies.
+#[derive(Clone, Debug)]
+pub struct CargoCache {
+    prefix: String,
+    workspace: PathBuf,
+    cargo_home: PathBuf,
+    target_dir: Option<PathBuf>,
+}
+
+impl CargoCache {
+    /// Creates a cache for the workspace in `GITHUB_WORKSPACE`, or the current directory.
+    ///
+    /// Cargo's home directory is taken from `CARGO_HOME` and defaults to `~/.cargo`, the target
+    /// directory is taken from `CARGO_TARGET_DIR` and defaults to `target` in the workspace.
+    pub fn new() -> Result<Self> {
+        let workspace = match std::env::var_os("GITHUB_WORKSPACE") {
+            Some(workspace) => workspace.into(),
+            None => std::env::current_dir()?,
+        };
+        let cargo_home = match std::env::var_os("CARGO_HOME") {
+            Some(home) => PathBuf::from(home),
+            None => std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+                .map(|home| PathBuf::from(home).join(".cargo"))
+                .ok_or_else(|| std::io::Error::other("could not find Cargo's home directory"))?,
+        };
+        Ok(Self {
+            prefix: "rust".to_owned(),
+            workspace,
+            cargo_home,
+            target_dir: std::env::var_os("CARGO_TARGET_DIR").map(PathBuf::from),
+        })
+    }
+
+    /// Sets the first segment of the keys, `rust` by default.
+    ///
+    /// Use different prefixes for jobs building different configurations, so they don't
+    /// overwrite each other's entries.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets the workspace directory.
+    pub fn with_workspace(mut self, workspace: impl Into<PathBuf>) -> Self {
+        self.workspace = workspace.into();
+        self
+    }
+
+    /// Sets Cargo's home directory.
+    pub fn with_cargo_home(mut self, cargo_home: impl Into<PathBuf>) -> Self {
+        self.cargo_home = cargo_home.into();
+        self
+    }
+
+    /// Sets the target directory, relative to the workspace.
+    pub fn with_target_dir(mut self, target_dir: impl Into<PathBuf>) -> Self {
+        self.target_dir = Some(target_dir.into());
+        self
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.workspace
+            .join(self.target_dir.as_deref().unwrap_or(Path::new("target")))
+    }
+
+    fn snapshots(&self) -> [Snapshot; 2] {
+        let target = self.target_dir();
+        let (root, name) = match (target.parent(), target.file_name()) {
+            (Some(root), Some(name)) => (root.to_owned(), PathBuf::from(name)),
+            _ => (self.workspace.clone(), PathBuf::from("target")),
+        };
+        [
+            Snapshot::new(HOME_PATHS).with_root(&self.cargo_home),
+            Snapshot::new([name]).with_root(root),
+        ]
+    }
+
+    /// Returns the keys of this cache.
+    ///
+    /// The key consists of the prefix, the runner's OS and architecture, a digest of the
+    /// toolchain's version and a digest of the manifests, lock files and toolchain files. Restore
+    /// prefixes fall back to entries of the same toolchain with different dependencies.
+    pub fn keys(&self) -> Result<RestoreKeys> {
+        let output = Command::new("rustc")
+            .arg("-vV")
+            .current_dir(&self.workspace)
+            .output()?;
+        let output = error::command_output("rustc", output)?;
+        let toolchain = digest::sha256_hex(&output.stdout);
+
+        let key = CacheKey::new(&self.prefix)
+            .runner_os()
+            .runner_arch()
+            .segment(&toolchain[..16])
+            .segment(key::hash_files_in(&self.workspace, KEY_FILES)?);
+        // Only the most specific prefix, artifacts of other toolchains are of no use.
+        let mut keys = RestoreKeys::new(key.key()?);
+        if let Some(prefix) = key.restore_prefixes()?.into_iter().next() {
+            keys = keys.prefix(prefix);
+        }
+        Ok(keys)
+    }
+
+    /// Restores the best matching entries.
+    pub async fn restore(&self, cache: &dyn CacheBackend) -> Result<Restored> {
+        let keys = self.keys()?;
+        let [home, target] = self.snapshots();
+        Ok(Restored {
+            home: home.restore(cache, &keys.keys()).await?,
+            target: target.restore(cache, &keys.keys()).await?,
+        })
+    }
+
+    /// Cleans the target directory and saves both entries under the full key.
+    ///
+    /// Directories that don't exist, e.g. the target directory of a job that didn't build, are
+    /// skipped.
+    pub async fn save(&self, cache: &dyn CacheBackend) -> Result<()> {
+        let keys = self.keys()?;
+        self.clean().await?;
+        for snapshot in self.snapshots() {
+            match snapshot.save(cache, keys.key()).await {
+                Ok(_) | Err(Error::EmptySnapshot) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes artifacts of the workspace's own packages and incremental compilation data from
+    /// the target directory.
+    ///
+    /// Packages are listed by running `cargo metadata`.
+    pub async fn clean(&self) -> Result<()> {
+        let target = self.target_dir();
+        if !target.exists() {
+            return Ok(());
+        }
+        let workspace = self.workspace.clone();
+        tokio::task::spawn_blocking(move || {
+            let packages = workspace_packages(&workspace)?;
+            for profile in profile_dirs(&target)? {
+                clean_profile(&profile, &packages)?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+}
+
+/// Returns the names of the workspace's packages and their targets, with `-` replaced by `_`.
+fn workspace_packages(workspace: &Path) -> Result<BTreeSet<String>> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .current_dir(workspace)
+        .output()?;
+    let output = error::command_output("cargo metadata", output)?;
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+    let mut names = BTreeSet::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        names.extend(package["name"].as_str().map(str::to_owned));
+        for target in package["targets"].as_array().into_iter().flatten() {
+            names.extend(target["name"].as_str().map(str::to_owned));
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| name.replace('-', "_"))
+        .collect())
+}
+
+/// Returns the profile directories like `debug`, also those within target triple directories.
+fn profile_dirs(target: &Path) -> Result<Vec<PathBuf>> {
+    let mut profiles = vec![];
+    for entry in std::fs::read_dir(target)? {
+        let path = entry?.path();
+        if path.join(".fingerprint").is_dir() {
+            profiles.push(path);
+        } else if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let path = entry?.path();
+                if path.join(".fingerprint").is_dir() {
+                    profiles.push(path);
+                }
+            }
+        }
+    }
+    Ok(profiles)
+}
+
+fn clean_profile(profile: &Path, packages: &BTreeSet<String>) -> Result<()> {
+    remove(&profile.join("incremental"))?;
+
+    // Top-level files are the final artifacts of the workspace's packages.
+    for entry in std::fs::read_dir(profile)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            remove(&entry.path())?;
+        }
+    }
+
+    // Entries are named `{name}-{hash}`, with an additional `lib` prefix for some libraries.
+    for dir in [".fingerprint", "build", "deps"] {
+        let Ok(entries) = std::fs::read_dir(profile.join(dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let Some((name, _)) = file_name.rsplit_once('-') else {
+                continue;
+            };
+            let name = name.replace('-', "_");
+            if packages.contains(&name)
+                || name
+                    .strip_prefix("lib")
+                    .is_some_and(|name| packages.contains(name))
+            {
+                remove(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<()> {
+    let result = match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+    Ok(result?)
+}
@@ -0,0 +1,267 @@
+//! Content-addressed storage of blobs, with manifests referencing them.
+//!
+//! Blobs are stored under the SHA-256 digest of their content in a key space that does not
+//! depend on the workflow or paths, so identical content is only stored once, no matter which
+//! workflow stores it. The cache service's scoping still applies: blobs stored by runs for the
+//! default branch can be loaded everywhere, others only by runs for the same branch.
+//!
+//! Manifests name a set of blobs, e.g. all files of a build, and are what other runs look up by
+//! key. Counting references from manifests tells which blobs are still needed, see
+//! [`ContentStore::reference_counts`].
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{digest, management::ActionsCache, CacheBackend, CacheHit, Error, Result};
+
+/// Format version of manifests, changed for incompatible changes.
+const MANIFEST_VERSION: u32 = 1;
+
+/// Prefix of the keys of blobs, followed by the hex digest.
+const BLOB_PREFIX: &str = "sha256-";
+
+/// The SHA-256 digest identifying a blob.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    /// Computes the digest of `data`.
+    pub fn of(data: &[u8]) -> Self {
+        let mut hasher = digest::Sha256::new();
+        hasher.update(data);
+        Self(hasher.finish())
+    }
+
+    /// The digest's bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn key(&self) -> String {
+        format!("{}{}", BLOB_PREFIX, self)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&digest::hex(&self.0))
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+/// Parses 64 hex digits.
+impl FromStr for Digest {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self> {
+        let invalid = || Error::InvalidManifest(format!("invalid digest {:?}", hex));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for Digest {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    blobs: Vec<Digest>,
+}
+
+/// Blobs stored under their digests, and manifests referencing them.
+pub struct ContentStore<B> {
+    backend: B,
+    blob_key_space: String,
+    manifest_key_space: String,
+}
+
+impl<B: CacheBackend> ContentStore<B> {
+    /// Creates a store, keeping blobs and manifests in key spaces derived from `namespace`.
+    ///
+    /// Stores with the same namespace share blobs, so use one namespace for all workflows that
+    /// should deduplicate their content.
+    pub fn new(backend: B, namespace: &str) -> Self {
+        Self {
+            backend,
+            blob_key_space: digest::sha256_hex(format!("cas\n{}\nblobs", namespace).as_bytes()),
+            manifest_key_space: digest::sha256_hex(
+                format!("cas\n{}\nmanifests", namespace).as_bytes(),
+            ),
+        }
+    }
+
+    /// Returns the underlying backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the key space of blobs, the version of their entries in the management API.
+    pub fn blob_key_space(&self) -> &str {
+        &self.blob_key_space
+    }
+
+    /// Returns the key space of manifests.
+    pub fn manifest_key_space(&self) -> &str {
+        &self.manifest_key_space
+    }
+
+    /// Returns whether the blob with the given digest is stored.
+    pub async fn contains(&self, digest: &Digest) -> Result<bool> {
+        let key = digest.key();
+        Ok(self
+            .backend
+            .lookup(&self.blob_key_space, &[&key])
+            .await?
+            .is_some_and(|hit| hit.key == key))
+    }
+
+    /// Stores a blob unless it is stored already, returning its digest.
+    pub async fn store(&self, data: Bytes) -> Result<Digest> {
+        let digest = Digest::of(&data);
+        if !self.contains(&digest).await? {
+            match self
+                .backend
+                .put_bytes(&self.blob_key_space, &digest.key(), data)
+                .await
+            {
+                Ok(()) | Err(Error::Conflict(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(digest)
+    }
+
+    /// Loads the blob with the given digest, verifying its content.
+    pub async fn load(&self, digest: &Digest) -> Result<Option<Bytes>> {
+        let key = digest.key();
+        match self
+            .backend
+            .get_bytes(&self.blob_key_space, &[&key])
+            .await?
+        {
+            Some((hit, data)) if hit.key == key => {
+                let actual = Digest::of(&data);
+                if actual != *digest {
+                    return Err(Error::InvalidManifest(format!(
+                        "blob {} has digest {}",
+                        digest, actual
+                    )));
+                }
+                Ok(Some(data))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Stores a manifest referencing the given blobs under `key`.
+    ///
+    /// The blobs should be stored first, so that runs finding the manifest can load them.
+    pub async fn store_manifest(&self, key: &str, blobs: &[Digest]) -> Result<()> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            blobs: blobs.to_vec(),
+        };
+        let data = serde_json::to_vec(&manifest).expect("manifests serialize");
+        self.backend
+            .put_bytes(&self.manifest_key_space, key, data.into())
+            .await
+    }
+
+    /// Loads the first manifest matching `keys`, returning the blobs it references.
+    ///
+    /// See [`Cache::get_url`][crate::Cache::get_url] for how keys are matched.
+    pub async fn load_manifest(&self, keys: &[&str]) -> Result<Option<(CacheHit, Vec<Digest>)>> {
+        let Some((hit, data)) = self
+            .backend
+            .get_bytes(&self.manifest_key_space, keys)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let manifest: Manifest =
+            serde_json::from_slice(&data).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {}",
+                manifest.version
+            )));
+        }
+        Ok(Some((hit, manifest.blobs)))
+    }
+
+    /// Counts how many of the manifests stored under exactly the given keys reference each blob.
+    ///
+    /// Manifests that are not found are skipped. Manifests stored by runs for other branches
+    /// are not visible to this run, so counts are only complete when all manifests that may
+    /// reference a blob are visible.
+    pub async fn reference_counts(&self, keys: &[&str]) -> Result<BTreeMap<Digest, usize>> {
+        let mut counts = BTreeMap::new();
+        for key in keys {
+            let Some((hit, blobs)) = self.load_manifest(&[key]).await? else {
+                continue;
+            };
+            if hit.key != *key {
+                continue;
+            }
+            let mut blobs = blobs;
+            blobs.sort();
+            blobs.dedup();
+            for blob in blobs {
+                *counts.entry(blob).or_default() += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Returns the blob entries among `entries` that no manifest references according to
+    /// `counts`.
+    ///
+    /// The entries are usually listed with the management API, the returned ones can be deleted
+    /// with [`CacheManagement::delete_cache`][crate::management::CacheManagement::delete_cache].
+    pub fn unreferenced<'a>(
+        &self,
+        entries: &'a [ActionsCache],
+        counts: &BTreeMap<Digest, usize>,
+    ) -> Vec<&'a ActionsCache> {
+        entries
+            .iter()
+            .filter(|entry| entry.version == self.blob_key_space)
+            .filter(|entry| {
+                entry
+                    .key
+                    .strip_prefix(BLOB_PREFIX)
+                    .and_then(|hex| hex.parse::<Digest>().ok())
+                    .is_some_and(|digest| counts.get(&digest).copied().unwrap_or(0) == 0)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,169 @@
+//! Layering backends, so entries evicted from one are still found in another.
+use std::path::Path;
+
+use bytes::Bytes;
+
+use crate::{backend::BoxFuture, CacheBackend, CacheHit, Error, Result};
+
+/// A [`CacheBackend`] trying several backends in order, e.g. GitHub's cache, then a bucket,
+/// then a local directory.
+///
+/// Lookups and gets return the first backend's match. A backend that fails is skipped, its
+/// error is only returned if no other backend has a match. Puts are mirrored to all backends by
+/// default, and only fail if no backend stored the entry. Entries that already exist in a
+/// backend count as stored.
+pub struct BackendChain {
+    backends: Vec<Box<dyn CacheBackend>>,
+    mirror_puts: bool,
+    backfill: bool,
+}
+
+impl Default for BackendChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackendChain {
+    /// Creates an empty chain, which finds nothing and fails every put.
+    pub fn new() -> Self {
+        Self {
+            backends: vec![],
+            mirror_puts: true,
+            backfill: false,
+        }
+    }
+
+    /// Appends a backend, tried after the ones added before.
+    pub fn with(mut self, backend: impl CacheBackend + 'static) -> Self {
+        self.backends.push(Box::new(backend));
+        self
+    }
+
+    /// Sets whether puts are mirrored to all backends, enabled by default.
+    ///
+    /// When disabled, entries are only stored in the first backend that accepts them.
+    pub fn with_mirror_puts(mut self, mirror_puts: bool) -> Self {
+        self.mirror_puts = mirror_puts;
+        self
+    }
+
+    /// Sets whether entries found in a later backend are stored in the ones before it,
+    /// disabled by default.
+    ///
+    /// This makes subsequent gets cheaper when earlier backends are faster, at the cost of an
+    /// upload per backfilled entry.
+    pub fn with_backfill(mut self, backfill: bool) -> Self {
+        self.backfill = backfill;
+        self
+    }
+
+    /// Returns the backends in the order they are tried.
+    pub fn backends(&self) -> &[Box<dyn CacheBackend>] {
+        &self.backends
+    }
+
+    /// Stores an entry in the backends that accept it, depending on
+    /// [`with_mirror_puts`][Self::with_mirror_puts].
+    async fn put_each<'a, F>(&'a self, mut put: F) -> Result<()>
+    where
+        F: FnMut(&'a dyn CacheBackend) -> BoxFuture<'a, Result<()>>,
+    {
+        let mut stored = false;
+        let mut last_error = None;
+        for backend in &self.backends {
+            match put(&**backend).await {
+                Ok(()) | Err(Error::Conflict(_)) => {
+                    stored = true;
+                    if !self.mirror_puts {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    tracing::debug!(%err, "backend failed to store entry");
+                    last_error = Some(err);
+                }
+            }
+        }
+        match last_error {
+            Some(err) if !stored => Err(err),
+            _ if self.backends.is_empty() => {
+                Err(std::io::Error::other("no backends to store the entry in").into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CacheBackend for BackendChain {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for backend in &self.backends {
+                match backend.lookup(key_space, key_prefixes).await {
+                    Ok(Some(hit)) => return Ok(Some(hit)),
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::debug!(%err, "backend failed to look up entry");
+                        last_error = Some(err);
+                    }
+                }
+            }
+            last_error.map_or(Ok(None), Err)
+        })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for (index, backend) in self.backends.iter().enumerate() {
+                match backend.get_bytes(key_space, keys).await {
+                    Ok(Some((hit, data))) => {
+                        if self.backfill {
+                            for earlier in &self.backends[..index] {
+                                if let Err(err) =
+                                    earlier.put_bytes(key_space, &hit.key, data.clone()).await
+                                {
+                                    tracing::debug!(%err, "failed to backfill entry");
+                                }
+                            }
+                        }
+                        return Ok(Some((hit, data)));
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::debug!(%err, "backend failed to get entry");
+                        last_error = Some(err);
+                    }
+                }
+            }
+            last_error.map_or(Ok(None), Err)
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.put_each(move |backend| backend.put_bytes(key_space, key, data.clone())))
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.put_each(move |backend| backend.put_file(key_space, key, path)))
+    }
+}
@@ -0,0 +1,424 @@
+//! Entries stored as content-defined chunks, so similar entries share most of their storage.
+//!
+//! Chunk boundaries are chosen with [FastCDC], based on the content around them instead of
+//! fixed offsets. When bytes are inserted into or removed from an entry, only the chunks around
+//! the change differ from those of the previous entry, and only those are uploaded. This works
+//! best for uncompressed data, like a tar archive of a target directory, as compression spreads
+//! small changes over the rest of the output.
+//!
+//! [FastCDC]: https://www.usenix.org/conference/atc16/technical-sessions/presentation/xia
+use std::{collections::HashSet, path::Path, sync::Mutex};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{backend::BoxFuture, digest, CacheBackend, CacheHit, Error, Result};
+
+/// Format version of recipes, changed for incompatible changes.
+const RECIPE_VERSION: u32 = 1;
+
+/// Pseudo-random values for the gear hash, one per byte value.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // SplitMix64, so the table is fixed without spelling out 256 constants.
+    let mut table = [0; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Splits data into chunks at content-defined boundaries.
+///
+/// Uses FastCDC's normalized chunking: below the average size, a boundary requires more bits
+/// of the rolling hash to be zero than above it, which keeps chunk sizes close to the average.
+#[derive(Clone, Copy, Debug)]
+pub struct Chunker {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    mask_small: u64,
+    mask_large: u64,
+}
+
+impl Chunker {
+    /// Creates a chunker for chunks of the given average size, between a quarter and four times
+    /// the average.
+    ///
+    /// The average is rounded to a power of two.
+    pub fn new(avg_size: usize) -> Self {
+        let bits = avg_size.max(256).next_power_of_two().trailing_zeros();
+        // The hash is shifted left for every byte, so its highest bits depend on the most bytes.
+        let mask = |bits: u32| !(u64::MAX >> bits);
+        Self {
+            min_size: 1 << (bits - 2),
+            avg_size: 1 << bits,
+            max_size: 1 << (bits + 2),
+            mask_small: mask(bits + 1),
+            mask_large: mask(bits - 1),
+        }
+    }
+
+    /// Returns the average chunk size.
+    pub fn avg_size(&self) -> usize {
+        self.avg_size
+    }
+
+    /// Returns the maximal chunk size.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Returns the length of the first chunk of `data`.
+    ///
+    /// The result only depends on the first [`max_size`][Self::max_size] bytes, so for data
+    /// arriving incrementally, it is enough to buffer that many bytes, or all remaining ones.
+    pub fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let normal = end.min(self.avg_size);
+        let mut hash = 0u64;
+        for (i, &byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if i < normal {
+                self.mask_small
+            } else {
+                self.mask_large
+            };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Splits `data` into chunks.
+    pub fn chunks<'a>(&self, mut data: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let chunker = *self;
+        std::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let (chunk, rest) = data.split_at(chunker.cut(data));
+            data = rest;
+            Some(chunk)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Recipe {
+    version: u32,
+    size: u64,
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Chunk {
+    sha256: String,
+    size: u64,
+}
+
+impl Chunk {
+    fn key(&self) -> String {
+        format!("chunk-{}", self.sha256)
+    }
+}
+
+/// What [`ChunkedCache::save`] stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkedSave {
+    /// Number of chunks of the entry.
+    pub chunks: usize,
+    /// Size of the entry in bytes.
+    pub size: u64,
+    /// Number of chunks that were not stored already and were uploaded.
+    pub uploaded_chunks: usize,
+    /// Total size of the uploaded chunks in bytes.
+    pub uploaded_bytes: u64,
+}
+
+/// A [`CacheBackend`] storing entries as content-defined chunks.
+///
+/// Each entry is stored as a recipe under the requested key, listing chunks stored under their
+/// digests in a separate key space. Chunks are shared by all entries of the same key space, and
+/// a chunk is only uploaded if no entry stored it before. Like with
+/// [`SplitCache`][crate::split::SplitCache], lookups only see recipes and entries are only found
+/// once all their chunks are stored. Chunks are verified against their digests when
+/// reassembled.
+pub struct ChunkedCache<B> {
+    inner: B,
+    chunker: Chunker,
+    /// Chunks known to be stored, by chunk key space and key.
+    present: Mutex<HashSet<(String, String)>>,
+}
+
+impl<B: CacheBackend> ChunkedCache<B> {
+    /// Wraps `inner`, storing chunks of 4 MiB on average.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            chunker: Chunker::new(4 << 20),
+            present: Mutex::default(),
+        }
+    }
+
+    /// Sets the average chunk size, rounded to a power of two.
+    ///
+    /// Smaller chunks find more shared content, but need more requests.
+    pub fn with_avg_chunk_size(mut self, avg_size: usize) -> Self {
+        self.chunker = Chunker::new(avg_size);
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn chunk_key_space(key_space: &str) -> String {
+        digest::sha256_hex(format!("{}\nchunks", key_space).as_bytes())
+    }
+
+    async fn recipe(&self, key_space: &str, keys: &[&str]) -> Result<Option<(CacheHit, Recipe)>> {
+        let Some((hit, data)) = self.inner.get_bytes(key_space, keys).await? else {
+            return Ok(None);
+        };
+        let recipe: Recipe =
+            serde_json::from_slice(&data).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        if recipe.version != RECIPE_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {}",
+                recipe.version
+            )));
+        }
+        Ok(Some((hit, recipe)))
+    }
+
+    async fn get_chunk(&self, chunk_key_space: &str, chunk: &Chunk) -> Result<Bytes> {
+        let key = chunk.key();
+        match self.inner.get_bytes(chunk_key_space, &[&key]).await? {
+            Some((hit, data)) if hit.key == key && digest::sha256_hex(&data) == chunk.sha256 => {
+                Ok(data)
+            }
+            _ => Err(Error::InvalidManifest(format!(
+                "missing or corrupted chunk {}",
+                chunk.sha256
+            ))),
+        }
+    }
+
+    async fn put_chunk(
+        &self,
+        chunk_key_space: &str,
+        data: Bytes,
+        save: &mut ChunkedSave,
+    ) -> Result<Chunk> {
+        let chunk = Chunk {
+            sha256: digest::sha256_hex(&data),
+            size: data.len() as u64,
+        };
+        save.chunks += 1;
+        save.size += chunk.size;
+
+        let present = (chunk_key_space.to_owned(), chunk.key());
+        if self.present.lock().unwrap().contains(&present) {
+            return Ok(chunk);
+        }
+        let key = &present.1;
+        // Lookups match key prefixes, only an entry with exactly this key is the chunk.
+        let stored = self
+            .inner
+            .lookup(chunk_key_space, &[key])
+            .await?
+            .is_some_and(|hit| hit.key == *key);
+        if !stored {
+            match self.inner.put_bytes(chunk_key_space, key, data).await {
+                Ok(()) => {
+                    save.uploaded_chunks += 1;
+                    save.uploaded_bytes += chunk.size;
+                }
+                Err(Error::Conflict(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.present.lock().unwrap().insert(present);
+        Ok(chunk)
+    }
+
+    async fn put_recipe(
+        &self,
+        key_space: &str,
+        key: &str,
+        chunks: Vec<Chunk>,
+        size: u64,
+    ) -> Result<()> {
+        let recipe = Recipe {
+            version: RECIPE_VERSION,
+            size,
+            chunks,
+        };
+        let data = serde_json::to_vec(&recipe).expect("recipes serialize");
+        self.inner.put_bytes(key_space, key, data.into()).await
+    }
+
+    /// Stores the content of the file at `path` as an entry, returning what was uploaded.
+    ///
+    /// The file is read incrementally, so only up to four times the average chunk size is held
+    /// in memory.
+    pub async fn save(
+        &self,
+        key_space: &str,
+        key: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<ChunkedSave> {
+        let chunk_key_space = Self::chunk_key_space(key_space);
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut save = ChunkedSave::default();
+        let mut chunks = vec![];
+        let mut buffer = BytesMut::new();
+        let mut eof = false;
+        loop {
+            while !eof && buffer.len() < self.chunker.max_size() {
+                buffer.reserve(self.chunker.max_size() - buffer.len());
+                eof = file.read_buf(&mut buffer).await? == 0;
+            }
+            if buffer.is_empty() {
+                break;
+            }
+            let data = buffer.split_to(self.chunker.cut(&buffer)).freeze();
+            chunks.push(self.put_chunk(&chunk_key_space, data, &mut save).await?);
+        }
+        self.put_recipe(key_space, key, chunks, save.size).await?;
+        Ok(save)
+    }
+
+    /// Stores `data` as an entry, returning what was uploaded.
+    pub async fn save_bytes(&self, key_space: &str, key: &str, data: Bytes) -> Result<ChunkedSave> {
+        let chunk_key_space = Self::chunk_key_space(key_space);
+        let mut save = ChunkedSave::default();
+        let mut chunks = vec![];
+        let mut offset = 0;
+        for chunk in self.chunker.chunks(&data) {
+            let end = offset + chunk.len();
+            chunks.push(
+                self.put_chunk(&chunk_key_space, data.slice(offset..end), &mut save)
+                    .await?,
+            );
+            offset = end;
+        }
+        self.put_recipe(key_space, key, chunks, save.size).await?;
+        Ok(save)
+    }
+
+    /// Looks up a matching entry and writes its content to the file at `path`.
+    ///
+    /// Chunks are written as they are downloaded, so only one chunk is held in memory.
+    pub async fn get_to_file(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        path: impl AsRef<Path>,
+    ) -> Result<Option<CacheHit>> {
+        let Some((hit, recipe)) = self.recipe(key_space, keys).await? else {
+            return Ok(None);
+        };
+        let chunk_key_space = Self::chunk_key_space(key_space);
+        let mut file = tokio::fs::File::create(path).await?;
+        for chunk in &recipe.chunks {
+            file.write_all(&self.get_chunk(&chunk_key_space, chunk).await?)
+                .await?;
+        }
+        file.flush().await?;
+        Ok(Some(hit))
+    }
+}
+
+impl<B: CacheBackend> CacheBackend for ChunkedCache<B> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        self.inner.lookup(key_space, key_prefixes)
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let Some((hit, recipe)) = self.recipe(key_space, keys).await? else {
+                return Ok(None);
+            };
+            let chunk_key_space = Self::chunk_key_space(key_space);
+            let mut data = BytesMut::with_capacity(recipe.size as usize);
+            for chunk in &recipe.chunks {
+                data.extend_from_slice(&self.get_chunk(&chunk_key_space, chunk).await?);
+            }
+            Ok(Some((hit, data.freeze())))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.save_bytes(key_space, key, data).await?;
+            Ok(())
+        })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.save(key_space, key, path).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryCache;
+
+    #[tokio::test]
+    async fn uploads_chunks_per_key_space() {
+        let cache = ChunkedCache::new(InMemoryCache::new()).with_avg_chunk_size(256);
+        // Pseudo-random, so chunks don't repeat within the entry.
+        let data: Bytes = (0..4096u64)
+            .map(|i| (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8)
+            .collect();
+
+        let first = cache.save_bytes("a", "key", data.clone()).await.unwrap();
+        let second = cache.save_bytes("b", "key", data.clone()).await.unwrap();
+        assert!(first.chunks > 1);
+        assert_eq!(first.uploaded_chunks, first.chunks);
+        assert_eq!(second.uploaded_chunks, second.chunks);
+
+        for key_space in ["a", "b"] {
+            let (_, restored) = cache.get_bytes(key_space, &["key"]).await.unwrap().unwrap();
+            assert_eq!(restored, data);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Circuit breaker for cache service outages.
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, Error, SystemClock};
+
+/// Stops making requests for a while after repeated failures.
+///
+/// After `threshold` failed requests without a successful one in between, all requests fail
+/// immediately with [`Error::Unavailable`] until `cooldown` has passed. After that, requests are
+/// let through again. The first one to fail opens the circuit for another cooldown period, and
+/// the first one to succeed closes it. Concurrent requests are not limited to a single probe.
+///
+/// Only failures that indicate a problem with the service count, i.e.
+/// [retryable][Error::is_retryable] errors. Other errors neither count as failures nor reset
+/// the count. A failure is counted once per request, after any retries.
+///
+/// A breaker passed to [`Cache::with_circuit_breaker`][crate::Cache::with_circuit_breaker]
+/// uses the clock of that cache.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Creates a circuit breaker opening after `threshold` failures.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Uses the given clock for the cooldown.
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Returns an error if the circuit is open.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        let now = self.clock.now();
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) if open_until > now => Err(Error::Unavailable {
+                retry_in: open_until - now,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the outcome of a request.
+    pub(crate) fn record<T>(&self, result: &Result<T, Error>) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Err(err) if err.is_retryable() => {
+                state.failures = state.failures.saturating_add(1);
+                if state.failures >= self.threshold {
+                    if state.open_until.is_none_or(|open_until| open_until <= now) {
+                        tracing::debug!(failures = state.failures, "opening circuit breaker");
+                    }
+                    state.open_until = Some(now + self.cooldown);
+                }
+            }
+            Err(_) => (),
+            Ok(_) => *state = State::default(),
+        }
+    }
+
+    /// Returns whether requests are currently short-circuited.
+    pub fn is_open(&self) -> bool {
+        self.check().is_err()
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("threshold", &self.threshold)
+            .field("cooldown", &self.cooldown)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,38 @@
+//! Source of time for retries and expiry.
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backend::BoxFuture;
+
+/// Provides the current time and waiting, used for retries, rate limits and the circuit breaker.
+///
+/// Set using [`Cache::with_clock`][crate::Cache::with_clock]. The default [`SystemClock`] uses
+/// the system time and tokio timers. Tests can use a clock that skips waiting, so code relying
+/// on backoff or expiry runs without actually sleeping.
+pub trait Clock: Send + Sync {
+    /// Returns the current monotonic time.
+    fn now(&self) -> Instant;
+
+    /// Returns the current wall-clock time, used for interpreting HTTP dates.
+    fn system_now(&self) -> SystemTime;
+
+    /// Waits for the given duration.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()>;
+}
+
+/// The system clock, waiting using tokio timers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
@@ -0,0 +1,784 @@
+//! Differential snapshots of large directories, uploading only files that changed.
+//!
+//! A snapshot consists of a manifest entry listing every file with its digest, and of pack
+//! entries holding file contents. Saving compares the directory with the manifest the job
+//! started from and uploads the changed files as one new pack, so saving a mostly unchanged
+//! `target/` directory only uploads what the build touched. Packs are shared between manifests
+//! and are never modified, so once much of the data referenced by a manifest lives in packs that
+//! are mostly outdated, saving uploads everything into a single fresh pack instead.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    digest,
+    paths::{safe_relative_path, symlinked_parent},
+    CacheBackend, CacheHit, Error, Result,
+};
+
+/// Format version of manifests, changed for incompatible changes.
+const MANIFEST_VERSION: u32 = 1;
+
+/// A file, symlink or empty directory recorded in a manifest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Entry {
+    File {
+        path: String,
+        size: u64,
+        sha256: String,
+        mtime: (u64, u32),
+        executable: bool,
+        /// Index of the pack holding the content, within the manifest's packs.
+        pack: usize,
+        offset: u64,
+    },
+    Symlink {
+        path: String,
+        target: String,
+    },
+    Dir {
+        path: String,
+    },
+}
+
+impl Entry {
+    fn path(&self) -> &str {
+        match self {
+            Entry::File { path, .. } | Entry::Symlink { path, .. } | Entry::Dir { path } => path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Pack {
+    key: String,
+    size: u64,
+}
+
+/// The files of a differential snapshot and where their contents are stored.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+    packs: Vec<Pack>,
+    entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// The number of files, symlinks and empty directories.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The total size of the files.
+    pub fn size(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::File { size, .. } => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn encode(&self) -> Bytes {
+        let json = serde_json::to_vec(self).expect("manifests serialize");
+        miniz_oxide::deflate::compress_to_vec(&json, 6).into()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        let json = miniz_oxide::inflate::decompress_to_vec(data)
+            .map_err(|err| Error::InvalidManifest(format!("{:?}", err.status)))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&json).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {}",
+                manifest.version
+            )));
+        }
+        // Check all paths first, so nothing is restored from malicious manifests.
+        if let Some(entry) = manifest
+            .entries
+            .iter()
+            .find(|entry| safe_relative_path(Path::new(entry.path())).is_none())
+        {
+            return Err(Error::InvalidManifest(format!(
+                "unsafe path {:?}",
+                entry.path()
+            )));
+        }
+        for entry in &manifest.entries {
+            if let Entry::File { path, pack, .. } = entry {
+                if *pack >= manifest.packs.len() {
+                    return Err(Error::InvalidManifest(format!(
+                        "{} refers to missing pack {}",
+                        path, pack
+                    )));
+                }
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// What [`DifferentialSnapshot::save`] uploaded.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct DifferentialSave {
+    /// The number of files in the snapshot.
+    pub files: usize,
+    /// The number of files whose content was uploaded.
+    pub uploaded_files: usize,
+    /// The size of the uploaded pack, excluding the manifest.
+    pub uploaded_bytes: u64,
+    /// Whether all files were uploaded into a fresh pack, as too much of the data in the
+    /// previous packs was outdated.
+    pub compacted: bool,
+}
+
+/// A directory saved to and restored from differential cache entries.
+///
+/// Manifests are stored under the given keys in a key space derived from `key_space`, packs in
+/// a separate key space under keys derived from their content. Restoring writes the recorded
+/// files into the directory, preserving modification times, which Cargo uses to detect
+/// outdated artifacts, as well as executable permissions and symlinks.
+#[derive(Clone, Debug)]
+pub struct DifferentialSnapshot {
+    root: PathBuf,
+    key_space: String,
+    trust_mtime: bool,
+    compaction_threshold: f64,
+}
+
+impl DifferentialSnapshot {
+    /// Creates a snapshot of the directory `root`.
+    pub fn new(root: impl Into<PathBuf>, key_space: &str) -> Self {
+        Self {
+            root: root.into(),
+            key_space: digest::sha256_hex(format!("differential\n{}", key_space).as_bytes()),
+            trust_mtime: true,
+            compaction_threshold: 0.5,
+        }
+    }
+
+    /// Sets whether files with unchanged size and modification time are assumed unchanged,
+    /// enabled by default.
+    ///
+    /// Otherwise, every file is read to compute its digest.
+    pub fn with_trust_mtime(mut self, trust_mtime: bool) -> Self {
+        self.trust_mtime = trust_mtime;
+        self
+    }
+
+    /// Sets the fraction of outdated data in the referenced packs above which everything is
+    /// uploaded into a fresh pack, 0.5 by default.
+    pub fn with_compaction_threshold(mut self, compaction_threshold: f64) -> Self {
+        self.compaction_threshold = compaction_threshold;
+        self
+    }
+
+    fn pack_key_space(&self) -> String {
+        digest::sha256_hex(format!("{}\npacks", self.key_space).as_bytes())
+    }
+
+    /// Returns the manifest of the first entry matching `keys`.
+    pub async fn manifest(
+        &self,
+        cache: &dyn CacheBackend,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Manifest)>> {
+        match cache.get_bytes(&self.key_space, keys).await? {
+            Some((hit, data)) => Ok(Some((hit, Manifest::decode(&data)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Restores the directory from the first entry matching `keys`.
+    ///
+    /// Existing files are overwritten, other files in the directory are left alone.
+    pub async fn restore(
+        &self,
+        cache: &dyn CacheBackend,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Manifest)>> {
+        let Some((hit, manifest)) = self.manifest(cache, keys).await? else {
+            return Ok(None);
+        };
+
+        let entries = Arc::new(manifest.entries.clone());
+        let (root, all) = (self.root.clone(), entries.clone());
+        tokio::task::spawn_blocking(move || restore_dirs_and_links(&root, &all))
+            .await
+            .map_err(io::Error::other)??;
+
+        // Packs are downloaded to a temporary file one at a time, as they can be large.
+        let pack_key_space = self.pack_key_space();
+        for (index, pack) in manifest.packs.iter().enumerate() {
+            let pack_path = temp_pack_path(&pack.key);
+            let result = async {
+                let size = match cache
+                    .get_file(&pack_key_space, &[&pack.key], &pack_path)
+                    .await?
+                {
+                    Some(hit) if hit.key == pack.key => {
+                        Some(tokio::fs::metadata(&pack_path).await?.len())
+                    }
+                    _ => None,
+                };
+                if size != Some(pack.size) {
+                    return Err(Error::InvalidManifest(format!("missing pack {}", pack.key)));
+                }
+                let (root, entries, path) = (self.root.clone(), entries.clone(), pack_path.clone());
+                tokio::task::spawn_blocking(move || restore_files(&root, &entries, index, &path))
+                    .await
+                    .map_err(io::Error::other)?
+            }
+            .await;
+            let _ = tokio::fs::remove_file(&pack_path).await;
+            result?;
+        }
+        Ok(Some((hit, manifest)))
+    }
+
+    /// Saves the directory under `key`, uploading only files that differ from the manifest of
+    /// the first entry matching `base_keys`.
+    ///
+    /// The base is usually the entry restored at the start of the job, looked up with the same
+    /// keys.
+    pub async fn save(
+        &self,
+        cache: &dyn CacheBackend,
+        key: &str,
+        base_keys: &[&str],
+    ) -> Result<DifferentialSave> {
+        let base = match base_keys {
+            [] => None,
+            keys => self
+                .manifest(cache, keys)
+                .await?
+                .map(|(_, manifest)| manifest),
+        };
+
+        let pack_path = temp_pack_path(key);
+        let result = async {
+            let (root, path) = (self.root.clone(), pack_path.clone());
+            let (trust_mtime, compaction_threshold) = (self.trust_mtime, self.compaction_threshold);
+            let (mut manifest, mut save, pack_sha256) = tokio::task::spawn_blocking(move || {
+                scan(&root, base, trust_mtime, compaction_threshold, &path)
+            })
+            .await
+            .map_err(io::Error::other)??;
+
+            if save.uploaded_files > 0 {
+                let pack_key = format!("pack-{}", pack_sha256);
+                cache
+                    .put_file(&self.pack_key_space(), &pack_key, &pack_path)
+                    .await?;
+                save.uploaded_bytes = tokio::fs::metadata(&pack_path).await?.len();
+                manifest.packs.push(Pack {
+                    key: pack_key,
+                    size: save.uploaded_bytes,
+                });
+            }
+            cache
+                .put_bytes(&self.key_space, key, manifest.encode())
+                .await?;
+            Ok(save)
+        }
+        .await;
+        let _ = tokio::fs::remove_file(&pack_path).await;
+        result
+    }
+}
+
+/// Returns a path for a pack file in the runner's temporary directory, unique per process and
+/// `key`.
+fn temp_pack_path(key: &str) -> PathBuf {
+    std::env::var_os("RUNNER_TEMP")
+        .map_or_else(std::env::temp_dir, PathBuf::from)
+        .join(format!(
+            "differential-{}-{}.pack",
+            std::process::id(),
+            digest::sha256_hex(key.as_bytes())
+        ))
+}
+
+fn mtime(metadata: &std::fs::Metadata) -> (u64, u32) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    (mtime.as_secs(), mtime.subsec_nanos())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = digest::Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+    }
+    Ok(hasher.finish_hex())
+}
+
+/// Lists the entries below `root` in sorted order, with `/` separated relative paths.
+fn walk(dir: &Path, prefix: &str, out: &mut Vec<(String, std::fs::Metadata)>) -> io::Result<()> {
+    let mut children = std::fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    children.sort();
+    if children.is_empty() && !prefix.is_empty() {
+        out.push((
+            prefix.trim_end_matches('/').to_owned(),
+            std::fs::symlink_metadata(dir)?,
+        ));
+    }
+    for (name, path) in children {
+        let metadata = std::fs::symlink_metadata(&path)?;
+        let relative = format!("{}{}", prefix, name);
+        if metadata.is_dir() {
+            walk(&path, &format!("{}/", relative), out)?;
+        } else {
+            out.push((relative, metadata));
+        }
+    }
+    Ok(())
+}
+
+/// Compares the directory with `base`, writing changed files to a new pack at `pack_path`.
+///
+/// The returned manifest refers to the new pack with the index after the last of its packs, whose
+/// digest is returned as well.
+fn scan(
+    root: &Path,
+    base: Option<Manifest>,
+    trust_mtime: bool,
+    compaction_threshold: f64,
+    pack_path: &Path,
+) -> Result<(Manifest, DifferentialSave, String)> {
+    let mut found = vec![];
+    walk(root, "", &mut found)?;
+
+    let base = base.unwrap_or(Manifest {
+        version: MANIFEST_VERSION,
+        packs: vec![],
+        entries: vec![],
+    });
+    let mut by_path = HashMap::new();
+    let mut by_digest = HashMap::new();
+    for entry in &base.entries {
+        if let Entry::File {
+            path,
+            size,
+            sha256,
+            mtime,
+            pack,
+            offset,
+            ..
+        } = entry
+        {
+            by_path.insert(path.as_str(), (*size, *mtime, sha256.as_str()));
+            by_digest.insert(sha256.as_str(), (*pack, *offset));
+        }
+    }
+
+    // Digests of all files, reusing those of files that look unchanged.
+    let mut files = vec![];
+    let mut others = vec![];
+    for (path, metadata) in found {
+        if metadata.is_file() {
+            let (size, mtime) = (metadata.len(), mtime(&metadata));
+            let sha256 = match by_path.get(path.as_str()) {
+                Some(&(base_size, base_mtime, sha256))
+                    if trust_mtime && base_size == size && base_mtime == mtime =>
+                {
+                    sha256.to_owned()
+                }
+                _ => hash_file(&root.join(&path))?,
+            };
+            files.push((path, size, mtime, is_executable(&metadata), sha256));
+        } else if metadata.is_symlink() {
+            let target = std::fs::read_link(root.join(&path))?;
+            others.push(Entry::Symlink {
+                path,
+                target: target.to_string_lossy().into_owned(),
+            });
+        } else if metadata.is_dir() {
+            others.push(Entry::Dir { path });
+        }
+    }
+
+    // Compact if too much of the data in the packs still in use is outdated.
+    let mut live: BTreeMap<usize, u64> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for (_, size, _, _, sha256) in &files {
+        if let Some(&(pack, _)) = by_digest.get(sha256.as_str()) {
+            if seen.insert(sha256.as_str()) {
+                *live.entry(pack).or_default() += size;
+            }
+        }
+    }
+    let referenced: u64 = live.keys().map(|&pack| base.packs[pack].size).sum();
+    let live_bytes: u64 = live.values().sum();
+    let compacted = referenced > 0
+        && (referenced - live_bytes.min(referenced)) as f64
+            > compaction_threshold * referenced as f64;
+
+    // Keep only the packs still in use, renumbering them.
+    let mut packs = vec![];
+    let mut renumbered = HashMap::new();
+    if !compacted {
+        for &pack in live.keys() {
+            renumbered.insert(pack, packs.len());
+            packs.push(base.packs[pack].clone());
+        }
+    }
+    let new_pack = packs.len();
+
+    let mut writer = BufWriter::new(File::create(pack_path)?);
+    let mut hasher = digest::Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    let mut written: HashMap<String, u64> = HashMap::new();
+    let mut pack_size = 0;
+    let mut entries = others;
+    for (path, size, mtime, executable, sha256) in files {
+        let (pack, offset) = match by_digest.get(sha256.as_str()) {
+            Some(&(pack, offset)) if !compacted => (renumbered[&pack], offset),
+            _ => match written.get(&sha256) {
+                Some(&offset) => (new_pack, offset),
+                None => {
+                    let mut file = File::open(root.join(&path))?.take(size);
+                    let mut copied = 0;
+                    loop {
+                        let len = file.read(&mut buf)?;
+                        if len == 0 {
+                            break;
+                        }
+                        hasher.update(&buf[..len]);
+                        writer.write_all(&buf[..len])?;
+                        copied += len as u64;
+                    }
+                    if copied != size {
+                        return Err(
+                            io::Error::other(format!("{} changed while saving", path)).into()
+                        );
+                    }
+                    let offset = pack_size;
+                    pack_size += size;
+                    written.insert(sha256.clone(), offset);
+                    (new_pack, offset)
+                }
+            },
+        };
+        entries.push(Entry::File {
+            path,
+            size,
+            sha256,
+            mtime,
+            executable,
+            pack,
+            offset,
+        });
+    }
+    writer.flush()?;
+
+    let files = entries
+        .iter()
+        .filter(|entry| matches!(entry, Entry::File { .. }))
+        .count();
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        packs,
+        entries,
+    };
+    let save = DifferentialSave {
+        files,
+        uploaded_files: written.len(),
+        uploaded_bytes: 0,
+        compacted,
+    };
+    Ok((manifest, save, hasher.finish_hex()))
+}
+
+/// Returns where to restore the entry at `path` below `root`.
+///
+/// Fails for paths leaving `root` and for paths below a symlink, e.g. one restored from an
+/// earlier entry, which could otherwise be used to write outside of `root`.
+fn destination(root: &Path, path: &str) -> Result<PathBuf> {
+    let relative = safe_relative_path(Path::new(path))
+        .ok_or_else(|| Error::InvalidManifest(format!("unsafe path {:?}", path)))?;
+    if let Some(parent) = symlinked_parent(root, &relative) {
+        return Err(Error::InvalidManifest(format!(
+            "{} is below the symlink {}",
+            path,
+            parent.display()
+        )));
+    }
+    Ok(root.join(relative))
+}
+
+fn restore_dirs_and_links(root: &Path, entries: &[Entry]) -> Result<()> {
+    for entry in entries {
+        match entry {
+            Entry::Dir { path } => std::fs::create_dir_all(destination(root, path)?)?,
+            Entry::Symlink { path, target } => {
+                let path = destination(root, path)?;
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                if path.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&path)?;
+                }
+                symlink(Path::new(target), &path)?;
+            }
+            Entry::File { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+/// Restores the files stored in the pack with the given index, read from `pack_path`.
+fn restore_files(
+    root: &Path,
+    entries: &[Entry],
+    pack_index: usize,
+    pack_path: &Path,
+) -> Result<()> {
+    let mut pack_file = File::open(pack_path)?;
+    let pack_size = pack_file.metadata()?.len();
+    for entry in entries {
+        let Entry::File {
+            path,
+            size,
+            mtime,
+            executable,
+            pack,
+            offset,
+            ..
+        } = entry
+        else {
+            continue;
+        };
+        if *pack != pack_index {
+            continue;
+        }
+        if offset.checked_add(*size).is_none_or(|end| end > pack_size) {
+            return Err(Error::InvalidManifest(format!(
+                "{} is outside of its pack",
+                path
+            )));
+        }
+
+        let path = destination(root, path)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Don't write through a symlink that replaced the file.
+        if path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_symlink())
+        {
+            std::fs::remove_file(&path)?;
+        }
+        let mut file = File::create(&path)?;
+        pack_file.seek(SeekFrom::Start(*offset))?;
+        io::copy(&mut (&mut pack_file).take(*size), &mut file)?;
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::new(mtime.0, mtime.1))?;
+        #[cfg(unix)]
+        if *executable {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = file.metadata()?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            file.set_permissions(permissions)?;
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryCache;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("differential-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file(path: &str, size: u64) -> Entry {
+        Entry::File {
+            path: path.to_owned(),
+            size,
+            sha256: String::new(),
+            mtime: (0, 0),
+            executable: false,
+            pack: 0,
+            offset: 0,
+        }
+    }
+
+    /// Stores a manifest with the given entries and a pack holding `content`.
+    async fn store(
+        cache: &InMemoryCache,
+        snapshot: &DifferentialSnapshot,
+        entries: Vec<Entry>,
+        content: &'static [u8],
+    ) {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            packs: vec![Pack {
+                key: "pack-test".to_owned(),
+                size: content.len() as u64,
+            }],
+            entries,
+        };
+        cache
+            .put_bytes(&snapshot.key_space, "key", manifest.encode())
+            .await
+            .unwrap();
+        cache
+            .put_bytes(
+                &snapshot.pack_key_space(),
+                "pack-test",
+                Bytes::from_static(content),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn restores_saved_directory() {
+        let (source, target) = (temp_dir("saved-source"), temp_dir("saved-target"));
+        std::fs::create_dir_all(source.join("sub/empty")).unwrap();
+        std::fs::write(source.join("a.txt"), "a").unwrap();
+        std::fs::write(source.join("sub/b.txt"), "bb").unwrap();
+        std::fs::write(source.join("sub/c.txt"), "a").unwrap();
+
+        let cache = InMemoryCache::new();
+        let save = DifferentialSnapshot::new(&source, "test")
+            .save(&cache, "key", &[])
+            .await
+            .unwrap();
+        assert_eq!((save.files, save.uploaded_files), (3, 2));
+
+        let (_, manifest) = DifferentialSnapshot::new(&target, "test")
+            .restore(&cache, &["key"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.len(), 4);
+        assert_eq!(std::fs::read(target.join("a.txt")).unwrap(), b"a");
+        assert_eq!(std::fs::read(target.join("sub/b.txt")).unwrap(), b"bb");
+        assert_eq!(std::fs::read(target.join("sub/c.txt")).unwrap(), b"a");
+        assert!(target.join("sub/empty").is_dir());
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_leaving_the_directory() {
+        let dir = temp_dir("escape");
+        let root = dir.join("root");
+        let absolute = dir.join("absolute.txt");
+        let snapshot = DifferentialSnapshot::new(&root, "test");
+
+        for path in ["../escaped.txt", absolute.to_str().unwrap(), "a/../../b"] {
+            let cache = InMemoryCache::new();
+            store(&cache, &snapshot, vec![file(path, 4)], b"evil").await;
+            let result = snapshot.restore(&cache, &["key"]).await;
+            assert!(
+                matches!(result, Err(Error::InvalidManifest(_))),
+                "{:?}",
+                path
+            );
+        }
+        assert!(!dir.join("escaped.txt").exists());
+        assert!(!absolute.exists());
+        assert!(!dir.join("b").exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_packs() {
+        let root = temp_dir("missing-pack");
+        let snapshot = DifferentialSnapshot::new(&root, "test");
+        let cache = InMemoryCache::new();
+        let mut entry = file("a.txt", 4);
+        if let Entry::File { pack, .. } = &mut entry {
+            *pack = 1;
+        }
+        store(&cache, &snapshot, vec![entry], b"data").await;
+
+        let result = snapshot.restore(&cache, &["key"]).await;
+        assert!(matches!(result, Err(Error::InvalidManifest(_))));
+        let result = snapshot.save(&cache, "next", &["key"]).await;
+        assert!(matches!(result, Err(Error::InvalidManifest(_))));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_paths_below_symlinks() {
+        let dir = temp_dir("symlink");
+        let (root, elsewhere) = (dir.join("root"), dir.join("elsewhere"));
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        let snapshot = DifferentialSnapshot::new(&root, "test");
+
+        let cache = InMemoryCache::new();
+        let link = Entry::Symlink {
+            path: "link".to_owned(),
+            target: elsewhere.to_str().unwrap().to_owned(),
+        };
+        store(&cache, &snapshot, vec![link, file("link/x", 4)], b"evil").await;
+        let result = snapshot.restore(&cache, &["key"]).await;
+        assert!(matches!(result, Err(Error::InvalidManifest(_))));
+        assert!(!elsewhere.join("x").exists());
+    }
+}
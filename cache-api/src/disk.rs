@@ -0,0 +1,247 @@
+//! Keeping restored entries on local disk, so restoring them again skips the network.
+use std::{
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::BoxFuture, digest, CacheBackend, CacheHit, MatchKind, Result};
+
+/// A [`CacheBackend`] keeping copies of entries in a local directory.
+///
+/// Entries restored from or stored in the inner backend are written to the directory, by key
+/// space and key. Gets whose first key has a copy there return it without any request, other
+/// gets and all lookups go to the inner backend, as a newer entry might match a key prefix.
+///
+/// Within a job this avoids downloading an entry twice, and on persistent self-hosted runners a
+/// directory outside the workspace keeps entries across jobs. Failing to read or write copies
+/// is not an error, the inner backend is used instead.
+pub struct DiskCache<B> {
+    inner: B,
+    dir: PathBuf,
+    max_size: Option<u64>,
+}
+
+/// Stored next to the content of a copy.
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    key: String,
+    scope: String,
+}
+
+impl<B: CacheBackend> DiskCache<B> {
+    /// Creates a backend keeping copies of the entries of `inner` in `dir`.
+    pub fn new(inner: B, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            max_size: None,
+        }
+    }
+
+    /// Limits the total size of the copies, removing the least recently used ones beyond it.
+    ///
+    /// Without a limit, copies are kept until the directory is removed.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Returns the backend that entries are restored from.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns the directory holding the copies.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the copy of an entry, the metadata is stored with a `.json` suffix.
+    fn path(&self, key_space: &str, key: &str) -> PathBuf {
+        self.dir
+            .join(digest::sha256_hex(key_space.as_bytes()))
+            .join(digest::sha256_hex(key.as_bytes()))
+    }
+
+    /// Reads the copy of an entry, marking it as recently used.
+    async fn read(&self, key_space: &str, key: &str) -> Option<(CacheHit, Bytes)> {
+        let path = self.path(key_space, key);
+        let result = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+            let metadata = match std::fs::read(path.with_extension("json")) {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let metadata: Metadata = serde_json::from_slice(&metadata)
+                .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+            let data = std::fs::read(&path)?;
+            File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(SystemTime::now())?;
+            Ok(Some((metadata, data)))
+        })
+        .await
+        .map_err(std::io::Error::other);
+
+        match result {
+            Ok(Ok(Some((metadata, data)))) if metadata.key == key => Some((
+                CacheHit {
+                    key: metadata.key,
+                    scope: metadata.scope,
+                    match_kind: MatchKind::Exact,
+                },
+                data.into(),
+            )),
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) | Err(err) => {
+                tracing::debug!(%err, "failed to read local copy of entry");
+                None
+            }
+        }
+    }
+
+    /// Writes a copy of an entry, from `data` or the file at `source`.
+    async fn write(&self, key_space: &str, hit: &CacheHit, source: Source) {
+        let path = self.path(key_space, &hit.key);
+        let metadata = Metadata {
+            key: hit.key.clone(),
+            scope: hit.scope.clone(),
+        };
+        let dir = self.dir.clone();
+        let max_size = self.max_size;
+        let result = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let parent = path
+                .parent()
+                .expect("copies are within a key space directory");
+            std::fs::create_dir_all(parent)?;
+            let partial = path.with_extension(format!("partial-{}", std::process::id()));
+            match source {
+                Source::Bytes(data) => std::fs::write(&partial, data)?,
+                Source::File(source) => {
+                    std::fs::copy(source, &partial)?;
+                }
+            }
+            std::fs::write(
+                path.with_extension("json"),
+                serde_json::to_vec(&metadata).map_err(std::io::Error::other)?,
+            )?;
+            std::fs::rename(&partial, &path)?;
+            if let Some(max_size) = max_size {
+                evict(&dir, max_size)?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(std::io::Error::other);
+
+        if let Ok(Err(err)) | Err(err) = result {
+            tracing::debug!(%err, "failed to write local copy of entry");
+        }
+    }
+}
+
+/// The content of an entry to copy.
+enum Source {
+    Bytes(Bytes),
+    File(PathBuf),
+}
+
+/// Removes the least recently used copies until their total size is within `max_size`.
+fn evict(dir: &Path, max_size: u64) -> std::io::Result<()> {
+    let mut copies = vec![];
+    for key_space in std::fs::read_dir(dir)? {
+        for entry in std::fs::read_dir(key_space?.path())? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none() {
+                let metadata = entry.metadata()?;
+                copies.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+    }
+
+    let mut total: u64 = copies.iter().map(|(_, size, _)| size).sum();
+    copies.sort();
+    for (_, size, path) in copies {
+        if total <= max_size {
+            break;
+        }
+        std::fs::remove_file(path.with_extension("json"))?;
+        std::fs::remove_file(&path)?;
+        total -= size;
+    }
+    Ok(())
+}
+
+impl<B: CacheBackend> CacheBackend for DiskCache<B> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        self.inner.lookup(key_space, key_prefixes)
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            if let Some(key) = keys.first() {
+                if let Some(copy) = self.read(key_space, key).await {
+                    return Ok(Some(copy));
+                }
+            }
+            let Some((hit, data)) = self.inner.get_bytes(key_space, keys).await? else {
+                return Ok(None);
+            };
+            self.write(key_space, &hit, Source::Bytes(data.clone()))
+                .await;
+            Ok(Some((hit, data)))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.inner.put_bytes(key_space, key, data.clone()).await?;
+            self.write(key_space, &stored(key), Source::Bytes(data))
+                .await;
+            Ok(())
+        })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.inner.put_file(key_space, key, path).await?;
+            self.write(key_space, &stored(key), Source::File(path.to_owned()))
+                .await;
+            Ok(())
+        })
+    }
+}
+
+/// Returns the hit for an entry stored by this job, in the job's own scope.
+fn stored(key: &str) -> CacheHit {
+    CacheHit {
+        key: key.to_owned(),
+        scope: std::env::var("GITHUB_REF").unwrap_or_default(),
+        match_kind: MatchKind::Exact,
+    }
+}
@@ -0,0 +1,162 @@
+//! Client-side encryption of entries, available with the `encryption` feature.
+//!
+//! Anyone with write access to a repository can read its cache entries, e.g. by running a
+//! workflow on a branch. Encrypting entries with a key kept in a secret that such workflows
+//! can't access allows caching sensitive build state.
+use bytes::{BufMut, Bytes, BytesMut};
+use openssl::{
+    hash::MessageDigest,
+    symm::{self, Cipher},
+};
+
+use crate::{backend::BoxFuture, digest, CacheBackend, CacheHit, Error, Result};
+
+/// Identifies the format of encrypted entries, changed for incompatible changes.
+const MAGIC: &[u8; 4] = b"RAE1";
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Salt for deriving keys from passphrases, fixed so every run derives the same key.
+const PASSPHRASE_SALT: &[u8] = b"rust-actions-cache-api encryption";
+const PASSPHRASE_ITERATIONS: usize = 100_000;
+
+/// A [`CacheBackend`] encrypting entries with AES-256-GCM.
+///
+/// The key space and key of an entry are authenticated along with its content, so an entry
+/// can't be passed off as another one. Entries are stored in key spaces derived from the given
+/// ones and a fingerprint of the key, so entries encrypted with another key, or not at all,
+/// are not found instead of failing to decrypt.
+pub struct EncryptedCache<B> {
+    inner: B,
+    key: [u8; 32],
+    fingerprint: String,
+}
+
+impl<B: CacheBackend> EncryptedCache<B> {
+    /// Wraps `inner`, encrypting entries with the given 256-bit key.
+    pub fn new(inner: B, key: [u8; 32]) -> Self {
+        Self {
+            inner,
+            key,
+            fingerprint: digest::sha256_hex(&key)[..16].to_owned(),
+        }
+    }
+
+    /// Wraps `inner`, encrypting entries with a key derived from `passphrase` using PBKDF2.
+    pub fn with_passphrase(inner: B, passphrase: &str) -> Result<Self> {
+        let mut key = [0; 32];
+        openssl::pkcs5::pbkdf2_hmac(
+            passphrase.as_bytes(),
+            PASSPHRASE_SALT,
+            PASSPHRASE_ITERATIONS,
+            MessageDigest::sha256(),
+            &mut key,
+        )
+        .map_err(std::io::Error::other)?;
+        Ok(Self::new(inner, key))
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn key_space(&self, key_space: &str) -> String {
+        digest::sha256_hex(format!("{}\nencrypted\n{}", key_space, self.fingerprint).as_bytes())
+    }
+
+    fn associated_data(key_space: &str, key: &str) -> Vec<u8> {
+        let mut aad = MAGIC.to_vec();
+        for part in [key_space, key] {
+            aad.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            aad.extend_from_slice(part.as_bytes());
+        }
+        aad
+    }
+
+    fn encrypt(&self, key_space: &str, key: &str, data: &[u8]) -> Result<Bytes> {
+        let mut nonce = [0; NONCE_LEN];
+        openssl::rand::rand_bytes(&mut nonce).map_err(std::io::Error::other)?;
+        let mut tag = [0; TAG_LEN];
+        let ciphertext = symm::encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            &Self::associated_data(key_space, key),
+            data,
+            &mut tag,
+        )
+        .map_err(std::io::Error::other)?;
+
+        let mut out = BytesMut::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len() + TAG_LEN);
+        out.put_slice(MAGIC);
+        out.put_slice(&nonce);
+        out.put_slice(&ciphertext);
+        out.put_slice(&tag);
+        Ok(out.freeze())
+    }
+
+    fn decrypt(&self, key_space: &str, key: &str, data: &[u8]) -> Result<Bytes> {
+        let failed = || Error::DecryptionFailed {
+            key: key.to_owned(),
+        };
+        let data = data.strip_prefix(MAGIC).ok_or_else(failed)?;
+        if data.len() < NONCE_LEN + TAG_LEN {
+            return Err(failed());
+        }
+        let (nonce, rest) = data.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let plaintext = symm::decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(nonce),
+            &Self::associated_data(key_space, key),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| failed())?;
+        Ok(plaintext.into())
+    }
+}
+
+impl<B: CacheBackend> CacheBackend for EncryptedCache<B> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            let key_space = self.key_space(key_space);
+            self.inner.lookup(&key_space, key_prefixes).await
+        })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let inner_key_space = self.key_space(key_space);
+            let Some((hit, data)) = self.inner.get_bytes(&inner_key_space, keys).await? else {
+                return Ok(None);
+            };
+            let data = self.decrypt(key_space, &hit.key, &data)?;
+            Ok(Some((hit, data)))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let encrypted = self.encrypt(key_space, key, &data)?;
+            let key_space = self.key_space(key_space);
+            self.inner.put_bytes(&key_space, key, encrypted).await
+        })
+    }
+}
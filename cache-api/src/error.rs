@@ -0,0 +1,399 @@
+//! Error types.
+use std::{
+    path::PathBuf,
+    process::{ExitStatus, Output},
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{Response, StatusCode, Url};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::key;
+
+/// Errors that may occur within this crate.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error making a HTTP request or reading the response.
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    /// Rate-limited HTTP request.
+    #[error("server rate limited the request, asking to wait {retry_after} seconds")]
+    RateLimit {
+        /// Time to wait until making a retry or follow-up request.
+        retry_after: u64,
+        /// Error included in the rate-limit response.
+        #[source]
+        source: Box<StatusError>,
+    },
+    /// The requested resource does not exist (404).
+    #[error("not found: {0}")]
+    NotFound(Box<StatusError>),
+    /// The runtime token was rejected, usually because it expired (401).
+    #[error("unauthorized, the runtime token may have expired: {0}")]
+    Unauthorized(Box<StatusError>),
+    /// The request conflicts with the current state, e.g. when a key is already reserved (409).
+    #[error("conflict: {0}")]
+    Conflict(Box<StatusError>),
+    /// Too many requests, without the server asking for a specific wait time (429).
+    #[error("too many requests: {0}")]
+    TooManyRequests(Box<StatusError>),
+    /// The service is temporarily unavailable (503).
+    #[error("service unavailable: {0}")]
+    ServiceUnavailable(Box<StatusError>),
+    /// Any other client or server error status.
+    #[error(transparent)]
+    Status(Box<StatusError>),
+    /// Requests are short-circuited after repeated failures, see [`CircuitBreaker`].
+    ///
+    /// [`CircuitBreaker`]: crate::CircuitBreaker
+    #[error(
+        "cache service considered unavailable after repeated failures, retrying in {retry_in:?}"
+    )]
+    Unavailable {
+        /// Time until requests are attempted again.
+        retry_in: Duration,
+    },
+    /// The number of uploaded bytes does not match the size of the entry.
+    #[error("uploaded {uploaded} bytes, but the entry is {expected} bytes long")]
+    IncompleteUpload {
+        /// The size of the entry.
+        expected: u64,
+        /// The number of bytes actually uploaded.
+        uploaded: u64,
+    },
+    /// Error reading local data.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A key or key prefix that would be rejected by the cache service.
+    #[error("invalid cache key {key:?}: {reason}")]
+    InvalidKey {
+        /// The rejected key.
+        key: String,
+        /// Why the key is invalid.
+        reason: key::InvalidKeyReason,
+    },
+    /// A key template that could not be resolved.
+    #[error("invalid key template {template:?}: {reason}")]
+    InvalidTemplate {
+        /// The template string.
+        template: String,
+        /// Why the template could not be resolved.
+        reason: String,
+    },
+    /// Missing `ACTIONS_RUNTIME_TOKEN` environment variable.
+    #[error("did not find a runtime token in the ACTIONS_RUNTIME_TOKEN environment variable")]
+    NoRuntimeToken,
+    /// Missing `ACTIONS_CACHE_URL` environment variable.
+    #[error("did not find the endpoint URL in the ACTIONS_CACHE_URL environment variable")]
+    NoEndpointUrl,
+    /// Missing `ACTIONS_RESULTS_URL` environment variable.
+    #[error(
+        "did not find the results service URL in the ACTIONS_RESULTS_URL environment variable"
+    )]
+    NoResultsUrl,
+    /// Missing `ACTIONS_RUNTIME_URL` or `GITHUB_RUN_ID` environment variables needed for the legacy
+    /// artifact API.
+    #[error(
+        "did not find the runtime service URL and run id in the ACTIONS_RUNTIME_URL and \
+         GITHUB_RUN_ID environment variables"
+    )]
+    NoRuntimeUrl,
+    /// A runtime token that does not identify the workflow run and job.
+    #[error("invalid runtime token: {0}")]
+    InvalidRuntimeToken(String),
+    /// The artifact service refused an operation without returning an error status.
+    #[error("artifact service refused to {operation} artifact {name:?}")]
+    ArtifactRejected {
+        /// Name of the artifact.
+        name: String,
+        /// The refused operation, e.g. `create` or `finalize`.
+        operation: &'static str,
+    },
+    /// Downloaded content that does not match the digest recorded when it was uploaded.
+    #[error("artifact {name:?} is corrupted: expected SHA-256 digest {expected}, got {actual}")]
+    DigestMismatch {
+        /// Name of the artifact.
+        name: String,
+        /// The recorded digest, hex encoded.
+        expected: String,
+        /// The digest of the downloaded content, hex encoded.
+        actual: String,
+    },
+    /// An operation the legacy artifact API of GitHub Enterprise Server does not provide.
+    #[error("the legacy artifact API does not support {0}")]
+    UnsupportedByLegacyArtifacts(&'static str),
+    /// Missing `GITHUB_TOKEN` environment variable.
+    #[error("did not find a token in the GITHUB_TOKEN environment variable")]
+    NoGithubToken,
+    /// Missing or malformed `GITHUB_REPOSITORY` environment variable.
+    #[error("did not find an owner/repo in the GITHUB_REPOSITORY environment variable")]
+    NoRepository,
+    /// Credentials, like a GitHub App private key, that could not be used to obtain a token.
+    #[error("invalid credentials: {0}")]
+    InvalidCredentials(String),
+    /// A snapshot path that is not within the snapshot's root directory.
+    #[error("snapshot path {0:?} is not within the root directory")]
+    InvalidSnapshotPath(PathBuf),
+    /// A manifest entry that could not be decoded or refers to missing data.
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(String),
+    /// None of the paths of a snapshot exist.
+    #[error("none of the snapshot's paths exist")]
+    EmptySnapshot,
+    /// An external program that failed, e.g. `tar`.
+    #[error("{program} failed with {status}: {stderr}")]
+    Command {
+        /// The program, including a subcommand if any.
+        program: String,
+        /// The program's exit status.
+        status: ExitStatus,
+        /// What the program wrote to stderr.
+        stderr: String,
+    },
+    /// An encrypted entry that was corrupted or encrypted with a different key.
+    #[error("could not decrypt entry {key:?}")]
+    DecryptionFailed {
+        /// Key of the entry.
+        key: String,
+    },
+    /// A [`CacheLock`][crate::lock::CacheLock] that expired and was acquired by someone else.
+    #[error("lost lock {name:?} after it expired")]
+    LockLost {
+        /// Name of the lock.
+        name: String,
+    },
+}
+
+impl Error {
+    /// Returns the requested time to wait until retrying the rate limited request.
+    ///
+    /// If the cause for failure was not rate limiting, that cause is returned instead.
+    pub fn retry_after(&self) -> Option<u64> {
+        if let Self::RateLimit { retry_after, .. } = *self {
+            Some(retry_after)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the failed operation may succeed when retried.
+    ///
+    /// This is the case for connection failures, timeouts, rate limiting and server errors.
+    /// Rejected requests, like invalid keys, authentication failures or other client errors,
+    /// are considered permanent.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimit { .. } | Self::TooManyRequests(_) | Self::ServiceUnavailable(_) => true,
+            Self::Status(err) => err.status.is_server_error(),
+            Self::Reqwest(err) => match err.status() {
+                Some(status) => status.is_server_error(),
+                None => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the details of an error response from the server.
+    pub fn status_error(&self) -> Option<&StatusError> {
+        match self {
+            Self::RateLimit { source, .. } => Some(source),
+            Self::NotFound(err)
+            | Self::Unauthorized(err)
+            | Self::Conflict(err)
+            | Self::TooManyRequests(err)
+            | Self::ServiceUnavailable(err)
+            | Self::Status(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn status_error_mut(&mut self) -> Option<&mut StatusError> {
+        match self {
+            Self::RateLimit { source, .. } => Some(source),
+            Self::NotFound(err)
+            | Self::Unauthorized(err)
+            | Self::Conflict(err)
+            | Self::TooManyRequests(err)
+            | Self::ServiceUnavailable(err)
+            | Self::Status(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns the contained status error, or the error itself if there is none.
+    pub(crate) fn into_status_error(self) -> Result<Box<StatusError>, Self> {
+        match self {
+            Self::RateLimit { source, .. } => Ok(source),
+            Self::NotFound(err)
+            | Self::Unauthorized(err)
+            | Self::Conflict(err)
+            | Self::TooManyRequests(err)
+            | Self::ServiceUnavailable(err)
+            | Self::Status(err) => Ok(err),
+            err => Err(err),
+        }
+    }
+
+    /// Returns the structured error payload if the server responded with one.
+    pub fn service_error(&self) -> Option<&ServiceError> {
+        self.status_error()?.service_error.as_ref()
+    }
+
+    /// Returns the HTTP status if the server responded with an error status.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status_error().map(|err| err.status)
+    }
+}
+
+/// Result type used for fallible operations in this crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Maximal number of bytes of an error response body kept in a [`StatusError`].
+const MAX_BODY_LEN: usize = 4096;
+
+/// An error status returned by the server.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub struct StatusError {
+    /// The returned status.
+    pub status: StatusCode,
+    /// The requested URL.
+    pub url: Url,
+    /// The response body, usually explaining the failure, truncated to a few KiB.
+    pub body: String,
+    /// The structured error contained in the body, if any.
+    pub service_error: Option<ServiceError>,
+}
+
+/// Structured error payload returned by the cache service.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ServiceError {
+    /// Short identifier of the error, e.g. `ArtifactCacheItemAlreadyExistsException`.
+    pub type_key: String,
+    /// Human readable description of the error.
+    pub message: String,
+    /// Fully qualified name of the error type.
+    #[serde(default)]
+    pub type_name: Option<String>,
+    /// Numeric error code.
+    #[serde(default)]
+    pub error_code: Option<i64>,
+    /// Numeric event identifier.
+    #[serde(default)]
+    pub event_id: Option<i64>,
+}
+
+impl ServiceError {
+    /// Returns whether the error reports that an entry with the same key already exists.
+    pub fn is_already_exists(&self) -> bool {
+        self.type_key == "ArtifactCacheItemAlreadyExistsException"
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP status {} for {}", self.status, self.url)?;
+        if let Some(service_error) = &self.service_error {
+            write!(f, ": {}: {}", service_error.type_key, service_error.message)?;
+        } else if !self.body.is_empty() {
+            write!(f, ": {}", self.body)?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns error responses into the matching error variants.
+///
+/// Reads the body of error responses to include it in the returned error, along with the
+/// requested `url`. Requested wait times are limited to `max_retry_after` if given and HTTP dates
+/// are interpreted relative to `now`.
+pub(crate) async fn error_for_response(
+    response: Response,
+    url: Url,
+    max_retry_after: Option<Duration>,
+    now: SystemTime,
+) -> Result<Response> {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| parse_retry_after(value.to_str().ok()?, now))
+        .map(|retry_after| match max_retry_after {
+            Some(max) => retry_after.min(max.as_secs()),
+            None => retry_after,
+        });
+
+    let (body, service_error) = match response.bytes().await {
+        Ok(body) => (truncated_body(&body), serde_json::from_slice(&body).ok()),
+        Err(err) => (format!("<failed to read response body: {}>", err), None),
+    };
+
+    let err = Box::new(StatusError {
+        status,
+        url,
+        body,
+        service_error,
+    });
+
+    Err(match (retry_after, status) {
+        (Some(retry_after), _) => Error::RateLimit {
+            retry_after,
+            source: err,
+        },
+        (_, StatusCode::NOT_FOUND) => Error::NotFound(err),
+        (_, StatusCode::UNAUTHORIZED) => Error::Unauthorized(err),
+        (_, StatusCode::CONFLICT) => Error::Conflict(err),
+        (_, StatusCode::TOO_MANY_REQUESTS) => Error::TooManyRequests(err),
+        (_, StatusCode::SERVICE_UNAVAILABLE) => Error::ServiceUnavailable(err),
+        _ => Error::Status(err),
+    })
+}
+
+/// Returns the output of an external program, or an error if it failed.
+pub(crate) fn command_output(program: &str, output: Output) -> Result<Output> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(Error::Command {
+            program: program.to_owned(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        })
+    }
+}
+
+/// Parses a `Retry-After` value, given either in seconds or as HTTP-date, into seconds.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // Round up, so we never retry early, and treat dates in the past as no wait time.
+    Some(match date.duration_since(now) {
+        Ok(wait) => wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+        Err(_) => 0,
+    })
+}
+
+/// Decodes a response body for inclusion in an error, truncating it if necessary.
+fn truncated_body(body: &[u8]) -> String {
+    let mut body = String::from_utf8_lossy(body).trim().to_owned();
+    if body.len() > MAX_BODY_LEN {
+        let mut end = MAX_BODY_LEN;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str("...");
+    }
+    body
+}
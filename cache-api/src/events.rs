@@ -0,0 +1,28 @@
+//! Hooks for observing cache operations.
+use std::time::Duration;
+
+use crate::Error;
+
+/// Receives notifications about cache operations.
+///
+/// Set using [`Cache::with_events`][crate::Cache::with_events]. All methods do nothing by default,
+/// so implementations only need to override the events they are interested in. Methods are called
+/// inline, so they should return quickly.
+pub trait Events: Send + Sync {
+    /// Called before a failed request is retried after waiting for `delay`.
+    ///
+    /// The failed attempt is numbered starting at 1.
+    fn on_retry(&self, _attempt: u32, _delay: Duration, _error: &Error) {}
+
+    /// Called after a chunk of `size` bytes was uploaded at `offset` to the reserved entry
+    /// `cache_id`.
+    fn on_chunk_uploaded(&self, _cache_id: i64, _offset: u64, _size: u64) {}
+
+    /// Called when a lookup found no matching entry.
+    fn on_lookup_miss(&self, _key_space: &str, _keys: &[&str]) {}
+
+    /// Called when the server rejected a request due to rate limiting.
+    ///
+    /// The wait time requested by the server is passed if there is one.
+    fn on_rate_limited(&self, _retry_after: Option<Duration>) {}
+}
@@ -0,0 +1,320 @@
+//! Glob pattern matching and file system traversal.
+//!
+//! Follows the semantics of the [`@actions/glob`] package used by the official actions where it
+//! matters for cache keys: `*`, `?`, `[...]` and `**` are supported, `!` negates a pattern,
+//! dotfiles are matched and matching a directory implicitly matches everything below it.
+//!
+//! [`@actions/glob`]: https://github.com/actions/toolkit/tree/main/packages/glob
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// A single glob pattern.
+#[derive(Clone, Debug)]
+pub(crate) struct Pattern {
+    absolute: bool,
+    components: Vec<Component>,
+    negated: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Component {
+    AnyDirs,
+    Literal(String),
+    Wildcard(Vec<Token>),
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let mut pattern = pattern.trim();
+        let mut negated = false;
+        while let Some(rest) = pattern.strip_prefix('!') {
+            negated = !negated;
+            pattern = rest;
+        }
+        let absolute = pattern.starts_with('/');
+
+        let components = pattern
+            .split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .map(|component| {
+                if component == "**" {
+                    Component::AnyDirs
+                } else {
+                    let tokens = tokenize(component);
+                    if tokens.iter().all(|token| matches!(token, Token::Char(_))) {
+                        Component::Literal(component.replace('\\', ""))
+                    } else {
+                        Component::Wildcard(tokens)
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            absolute,
+            components,
+            negated,
+        }
+    }
+
+    /// Matches a path given as a list of components (relative to the traversal root).
+    fn matches_components(&self, path: &[&str]) -> bool {
+        match_components(&self.components, path)
+    }
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut chars = pattern.chars().peekable();
+    let mut tokens = vec![];
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => tokens.push(Token::Char(chars.next().unwrap_or('\\'))),
+            '?' => tokens.push(Token::AnyChar),
+            '*' => {
+                while chars.peek() == Some(&'*') {
+                    chars.next();
+                }
+                tokens.push(Token::AnyString)
+            }
+            '[' => {
+                let mut lookahead = chars.clone();
+                let mut negated = false;
+                if matches!(lookahead.peek(), Some('!') | Some('^')) {
+                    negated = true;
+                    lookahead.next();
+                }
+                let mut ranges = vec![];
+                let mut closed = false;
+                let mut first = true;
+                while let Some(c) = lookahead.next() {
+                    if c == ']' && !first {
+                        closed = true;
+                        break;
+                    }
+                    first = false;
+                    let end = if lookahead.peek() == Some(&'-') {
+                        let mut range = lookahead.clone();
+                        range.next();
+                        match range.next() {
+                            Some(end) if end != ']' => {
+                                lookahead = range;
+                                end
+                            }
+                            _ => c,
+                        }
+                    } else {
+                        c
+                    };
+                    ranges.push((c, end));
+                }
+                if closed {
+                    chars = lookahead;
+                    tokens.push(Token::Class { negated, ranges });
+                } else {
+                    tokens.push(Token::Char('['));
+                }
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    match tokens.split_first() {
+        None => text.is_empty(),
+        Some((Token::AnyString, rest)) => {
+            (0..=text.len()).any(|skip| match_tokens(rest, &text[skip..]))
+        }
+        Some((token, rest)) => match text.split_first() {
+            None => false,
+            Some((&c, text)) => {
+                let matches = match token {
+                    Token::Char(expected) => c == *expected,
+                    Token::AnyChar => true,
+                    Token::Class { negated, ranges } => {
+                        ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+                    }
+                    Token::AnyString => unreachable!(),
+                };
+                matches && match_tokens(rest, text)
+            }
+        },
+    }
+}
+
+fn match_component(component: &Component, name: &str) -> bool {
+    match component {
+        Component::AnyDirs => true,
+        Component::Literal(literal) => literal == name,
+        Component::Wildcard(tokens) => {
+            let name: Vec<char> = name.chars().collect();
+            match_tokens(tokens, &name)
+        }
+    }
+}
+
+fn match_components(components: &[Component], path: &[&str]) -> bool {
+    match components.split_first() {
+        None => path.is_empty(),
+        Some((Component::AnyDirs, rest)) => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((component, rest)) => match path.split_first() {
+            None => false,
+            Some((name, path)) => match_component(component, name) && match_components(rest, path),
+        },
+    }
+}
+
+/// Matches a whole string, like a cache key, against a glob pattern.
+///
+/// Unlike for paths, `*` also matches `/` here.
+pub(crate) fn matches_str(pattern: &str, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    match_tokens(&tokenize(pattern), &text)
+}
+
+/// Finds all files matched by the given patterns.
+///
+/// Relative patterns are resolved against `root`. Negated patterns exclude matching files (and
+/// everything below matching directories). The result is sorted and free of duplicates, and
+/// directories matched by several patterns are only traversed once.
+pub(crate) fn find_files(root: &Path, patterns: &[Pattern]) -> io::Result<Vec<PathBuf>> {
+    let mut found = Found::default();
+
+    for pattern in patterns.iter().filter(|pattern| !pattern.negated) {
+        let base = if pattern.absolute {
+            PathBuf::from("/")
+        } else {
+            root.to_owned()
+        };
+        walk(&base, &pattern.components, &mut found)?;
+    }
+
+    let excludes: Vec<&Pattern> = patterns.iter().filter(|pattern| pattern.negated).collect();
+
+    Ok(found
+        .files
+        .into_iter()
+        .filter(|path| {
+            !excludes.iter().any(|pattern| {
+                let relative = if pattern.absolute {
+                    path.strip_prefix("/").ok()
+                } else {
+                    path.strip_prefix(root).ok()
+                };
+                let components: Vec<&str> = match relative {
+                    Some(relative) => relative.iter().filter_map(|c| c.to_str()).collect(),
+                    None => return false,
+                };
+                (0..=components.len()).any(|len| pattern.matches_components(&components[..len]))
+            })
+        })
+        .collect())
+}
+
+#[derive(Default)]
+struct Found {
+    files: BTreeSet<PathBuf>,
+    /// Directories of which all files were added.
+    complete: Vec<PathBuf>,
+}
+
+impl Found {
+    fn is_complete(&self, path: &Path) -> bool {
+        self.complete.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+fn walk(dir: &Path, components: &[Component], found: &mut Found) -> io::Result<()> {
+    if found.is_complete(dir) {
+        return Ok(());
+    }
+    let (component, rest) = match components.split_first() {
+        None => return add_all(dir, found),
+        Some(split) => split,
+    };
+
+    if let Component::Literal(name) = component {
+        let path = dir.join(name);
+        if rest.is_empty() {
+            add_all(&path, found)?;
+        } else if path.is_dir() {
+            walk(&path, rest, found)?;
+        }
+        return Ok(());
+    }
+
+    if let Component::AnyDirs = component {
+        if rest.is_empty() {
+            return add_all(dir, found);
+        }
+        walk(dir, rest, found)?;
+    }
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let is_dir = path.is_dir();
+
+        if let Component::AnyDirs = component {
+            if is_dir {
+                walk(&path, components, found)?;
+            }
+        } else if match_component(component, name) {
+            if rest.is_empty() {
+                add_all(&path, found)?;
+            } else if is_dir {
+                walk(&path, rest, found)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds a file, or all files below a directory.
+fn add_all(path: &Path, found: &mut Found) -> io::Result<()> {
+    if found.is_complete(path) {
+        return Ok(());
+    }
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            add_all(&entry?.path(), found)?;
+        }
+        found.complete.push(path.to_owned());
+    } else {
+        found.files.insert(path.to_owned());
+    }
+    Ok(())
+}
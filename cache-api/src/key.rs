@@ -0,0 +1,557 @@
+//! Helpers for constructing cache keys.
+use std::{
+    io::Read,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{digest, glob, Error, Result};
+
+/// Maximum length of a cache key accepted by the cache service.
+pub const MAX_KEY_LEN: usize = 512;
+
+/// Reason for rejecting a cache key, see [`Error::InvalidKey`].
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidKeyReason {
+    /// The key contains a comma, which is used to separate keys in lookups.
+    #[error("keys may not contain commas")]
+    Comma,
+    /// The key is longer than [`MAX_KEY_LEN`] characters.
+    #[error("keys may not be longer than 512 characters")]
+    TooLong,
+}
+
+/// Checks that a key or key prefix is accepted by the cache service.
+///
+/// This is done automatically for all keys passed to [`Cache`][crate::Cache], but can be useful
+/// to report invalid keys before doing any other work.
+pub fn validate_key(key: &str) -> Result<()> {
+    let reason = if key.contains(',') {
+        InvalidKeyReason::Comma
+    } else if key.chars().count() > MAX_KEY_LEN {
+        InvalidKeyReason::TooLong
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidKey {
+        key: key.to_owned(),
+        reason,
+    })
+}
+
+/// Computes a digest of all files matching the given glob patterns.
+///
+/// This is equivalent to the `hashFiles()` expression available in workflow files. Patterns are
+/// relative to the `GITHUB_WORKSPACE` directory, or the current directory when that is not set.
+/// See [`hash_files_in`] for details.
+pub fn hash_files(patterns: &[&str]) -> Result<String> {
+    let root = match std::env::var_os("GITHUB_WORKSPACE") {
+        Some(workspace) => workspace.into(),
+        None => std::env::current_dir()?,
+    };
+    hash_files_in(root, patterns)
+}
+
+/// Computes a digest of all files below `root` matching the given glob patterns.
+///
+/// Each matching file is hashed using SHA-256 and the SHA-256 digest of the concatenated file
+/// digests is returned as a hex string. Files are processed in sorted path order, making the
+/// result independent of directory listing order. Patterns prefixed with `!` exclude files and
+/// matched directories include all files below them. Files outside of `root` are skipped.
+///
+/// Like `hashFiles()`, this returns an empty string when no files match.
+pub fn hash_files_in(root: impl AsRef<Path>, patterns: &[&str]) -> Result<String> {
+    let root = root.as_ref();
+    let patterns: Vec<_> = patterns.iter().map(|p| glob::Pattern::new(p)).collect();
+
+    let mut result = digest::Sha256::new();
+    let mut matched = false;
+    let mut buf = vec![0; 1 << 16];
+
+    for path in glob::find_files(root, &patterns)? {
+        if !path.starts_with(root) {
+            continue;
+        }
+
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = digest::Sha256::new();
+        loop {
+            let len = file.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+        result.update(&hasher.finish());
+        matched = true;
+    }
+
+    Ok(if matched {
+        result.finish_hex()
+    } else {
+        String::new()
+    })
+}
+
+/// Returns the runner's operating system as found in `RUNNER_OS`, e.g. `Linux`.
+///
+/// Outside of GitHub Actions, this falls back to the same naming derived from the target the
+/// program was compiled for.
+pub fn runner_os() -> String {
+    std::env::var("RUNNER_OS").unwrap_or_else(|_| {
+        match std::env::consts::OS {
+            "linux" => "Linux",
+            "windows" => "Windows",
+            "macos" => "macOS",
+            other => other,
+        }
+        .to_owned()
+    })
+}
+
+/// Returns the runner's architecture as found in `RUNNER_ARCH`, e.g. `X64`.
+///
+/// Outside of GitHub Actions, this falls back to the same naming derived from the target the
+/// program was compiled for.
+pub fn runner_arch() -> String {
+    std::env::var("RUNNER_ARCH").unwrap_or_else(|_| {
+        match std::env::consts::ARCH {
+            "x86_64" => "X64",
+            "x86" => "X86",
+            "aarch64" => "ARM64",
+            "arm" => "ARM",
+            other => other,
+        }
+        .to_owned()
+    })
+}
+
+/// Builder for cache keys composed of multiple segments.
+///
+/// Segments are joined using `-` as separator. Besides the full key, this derives restore
+/// prefixes by successively dropping trailing segments, so that a lookup falls back to the most
+/// similar entry when there is no exact match.
+#[derive(Clone, Debug)]
+pub struct CacheKey {
+    segments: Vec<String>,
+    /// Indices of the OS segments, removed again in cross-OS mode.
+    os_segments: Vec<usize>,
+    cross_os: bool,
+}
+
+impl CacheKey {
+    /// Separator placed between segments.
+    pub const SEPARATOR: &'static str = "-";
+
+    /// Starts a new key with the given prefix as first segment.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            segments: vec![prefix.into()],
+            os_segments: vec![],
+            cross_os: false,
+        }
+    }
+
+    /// Appends a custom segment.
+    ///
+    /// Empty segments are ignored.
+    pub fn segment(mut self, segment: impl Into<String>) -> Self {
+        let segment = segment.into();
+        if !segment.is_empty() {
+            self.segments.push(segment);
+        }
+        self
+    }
+
+    /// Appends the runner's operating system, see [`runner_os`].
+    ///
+    /// In [cross-OS mode][Self::cross_os] this does nothing.
+    pub fn runner_os(mut self) -> Self {
+        if self.cross_os {
+            return self;
+        }
+        let os = runner_os();
+        if !os.is_empty() {
+            self.os_segments.push(self.segments.len());
+        }
+        self.segment(os)
+    }
+
+    /// Enables cross-OS mode, omitting the OS segment so entries are shared between platforms.
+    ///
+    /// This is the key counterpart of the official action's `enableCrossOsArchive` option, see
+    /// also [`official_key_space_cross_os`]. Only use this if the cached content itself is
+    /// platform independent.
+    pub fn cross_os(mut self) -> Self {
+        self.cross_os = true;
+        for index in std::mem::take(&mut self.os_segments).into_iter().rev() {
+            self.segments.remove(index);
+        }
+        self
+    }
+
+    /// Appends the runner's architecture, see [`runner_arch`].
+    pub fn runner_arch(self) -> Self {
+        self.segment(runner_arch())
+    }
+
+    /// Appends a digest of all files matching the given patterns, see [`hash_files`].
+    ///
+    /// This is typically used with lock files, e.g. `**/Cargo.lock`.
+    pub fn hash_files(self, patterns: &[&str]) -> Result<Self> {
+        Ok(self.segment(hash_files(patterns)?))
+    }
+
+    /// Appends a segment identifying the current time bucket of the given rotation.
+    ///
+    /// Placed before content derived segments, this starts fresh entries at the start of each
+    /// bucket, while the derived restore prefixes still find entries of earlier buckets.
+    pub fn rotating(self, rotation: Rotation) -> Self {
+        self.segment(rotation.segment())
+    }
+
+    /// Returns the full key.
+    pub fn key(&self) -> Result<String> {
+        let key = self.join(self.segments.len());
+        validate_key(&key)?;
+        Ok(key)
+    }
+
+    /// Returns restore prefixes ordered from most to least specific.
+    ///
+    /// Each prefix contains all segments up to some point followed by a trailing separator, so
+    /// that a prefix never matches a key which merely has a longer segment at that position.
+    pub fn restore_prefixes(&self) -> Result<Vec<String>> {
+        (1..self.segments.len())
+            .rev()
+            .map(|len| {
+                let mut prefix = self.join(len);
+                prefix.push_str(Self::SEPARATOR);
+                validate_key(&prefix)?;
+                Ok(prefix)
+            })
+            .collect()
+    }
+
+    /// Returns the full key and its restore prefixes for use in a lookup.
+    pub fn restore_keys(&self) -> Result<RestoreKeys> {
+        Ok(RestoreKeys {
+            key: self.key()?,
+            prefixes: self.restore_prefixes()?,
+        })
+    }
+
+    fn join(&self, len: usize) -> String {
+        self.segments[..len].join(Self::SEPARATOR)
+    }
+}
+
+/// Keys for a lookup, preferring an exact match and falling back to prefixes.
+///
+/// Lookups using these keys report whether the found entry's key was the exact key using
+/// [`CacheHit::match_kind`][crate::CacheHit::match_kind].
+#[derive(Clone, Debug)]
+pub struct RestoreKeys {
+    key: String,
+    prefixes: Vec<String>,
+}
+
+impl RestoreKeys {
+    /// Looks up the exact `key` without any fallback.
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            prefixes: vec![],
+        }
+    }
+
+    /// Adds a fallback prefix, tried after all previously added prefixes.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// The exact key.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The fallback prefixes in order of preference.
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    /// Returns all keys in lookup order, as expected by [`Cache::get_url`][crate::Cache::get_url].
+    pub fn keys(&self) -> Vec<&str> {
+        std::iter::once(&*self.key)
+            .chain(self.prefixes.iter().map(|prefix| &**prefix))
+            .collect()
+    }
+}
+
+/// Time buckets for keys that rotate periodically, see [`CacheKey::rotating`].
+///
+/// All buckets are computed in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rotation {
+    /// Rotates daily, formatted like `2022-01-31`.
+    Daily,
+    /// Rotates weekly on Monday, formatted as ISO week like `2022-W05`.
+    Weekly,
+    /// Rotates monthly, formatted like `2022-01`.
+    Monthly,
+}
+
+impl Rotation {
+    /// Returns the key segment for the current time.
+    pub fn segment(self) -> String {
+        self.segment_at(SystemTime::now())
+    }
+
+    /// Returns the key segment for the given time.
+    pub fn segment_at(self, time: SystemTime) -> String {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64) - 1,
+        };
+        let days = secs.div_euclid(86400);
+
+        match self {
+            Self::Daily => {
+                let (year, month, day) = civil_from_days(days);
+                format!("{:04}-{:02}-{:02}", year, month, day)
+            }
+            Self::Weekly => {
+                // 1970-01-01 was a Thursday, the ISO week's year is the year of its Thursday.
+                let weekday = (days + 3).rem_euclid(7);
+                let thursday = days - weekday + 3;
+                let (year, _, _) = civil_from_days(thursday);
+                let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+                format!("{:04}-W{:02}", year, week)
+            }
+            Self::Monthly => {
+                let (year, month, _) = civil_from_days(days);
+                format!("{:04}-{:02}", year, month)
+            }
+        }
+    }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date to days since 1970-01-01.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Key template resolving `{placeholder}`s, e.g. `"deps-{os}-{arch}-{hash}"`.
+///
+/// The following placeholders are available:
+///
+/// * `{os}` and `{arch}` - the runner's OS and architecture, see [`runner_os`] and
+///   [`runner_arch`]
+/// * `{env.NAME}` - the value of the environment variable `NAME`
+/// * `{hashFiles(PATTERNS)}` - the [`hash_files`] digest of the comma separated `PATTERNS`
+/// * any name assigned a value using [`set`][Self::set]
+///
+/// Literal braces are written as `{{` and `}}`. Unknown placeholders and unset environment
+/// variables are reported as errors.
+#[derive(Clone, Debug)]
+pub struct KeyTemplate {
+    template: String,
+    values: Vec<(String, String)>,
+}
+
+impl KeyTemplate {
+    /// Creates a template from a template string.
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            values: vec![],
+        }
+    }
+
+    /// Assigns a value to a custom placeholder.
+    ///
+    /// Custom placeholders take precedence over the built-in ones.
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.values.push((name.into(), value.into()));
+        self
+    }
+
+    /// Resolves all placeholders and returns the validated key.
+    pub fn render(&self) -> Result<String> {
+        let mut key = String::new();
+        let mut rest = &*self.template;
+
+        while let Some(pos) = rest.find(['{', '}']) {
+            key.push_str(&rest[..pos]);
+            let brace = &rest[pos..pos + 1];
+            rest = &rest[pos + 1..];
+
+            if let Some(after) = rest.strip_prefix(brace) {
+                key.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err(self.error("unmatched '}'"));
+            }
+
+            let end = rest
+                .find('}')
+                .ok_or_else(|| self.error("unterminated placeholder"))?;
+            key.push_str(&self.resolve(rest[..end].trim())?);
+            rest = &rest[end + 1..];
+        }
+        key.push_str(rest);
+
+        validate_key(&key)?;
+        Ok(key)
+    }
+
+    fn resolve(&self, name: &str) -> Result<String> {
+        if let Some((_, value)) = self.values.iter().rev().find(|(n, _)| n == name) {
+            return Ok(value.clone());
+        }
+        if name == "os" {
+            return Ok(runner_os());
+        }
+        if name == "arch" {
+            return Ok(runner_arch());
+        }
+        if let Some(var) = name.strip_prefix("env.") {
+            return std::env::var(var)
+                .map_err(|_| self.error(&format!("environment variable {} is not set", var)));
+        }
+        if let Some(patterns) = name
+            .strip_prefix("hashFiles(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let patterns: Vec<&str> = patterns
+                .split(',')
+                .map(|pattern| pattern.trim().trim_matches(['\'', '"']))
+                .collect();
+            return hash_files(&patterns);
+        }
+        Err(self.error(&format!("unknown placeholder {{{}}}", name)))
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        Error::InvalidTemplate {
+            template: self.template.clone(),
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+/// Archive compression methods used by the official cache action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompressionMethod {
+    /// Gzip compressed tar archive.
+    Gzip,
+    /// Zstd compressed tar archive, compressed without long distance matching.
+    ZstdWithoutLong,
+    /// Zstd compressed tar archive.
+    Zstd,
+}
+
+impl CompressionMethod {
+    /// Returns the name used by the official client.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::ZstdWithoutLong => "zstd-without-long",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Salt used by the official client, changed for breaking changes of the entry format.
+const OFFICIAL_VERSION_SALT: &str = "1.0";
+
+/// Computes the `key_space` the official cache action uses for the given paths.
+///
+/// The official client calls this the cache version. It is derived from the `path` input exactly
+/// as written in the workflow file, i.e. without resolving globs or `~`, and from the compression
+/// method. On Windows, entries are kept separate from other platforms.
+///
+/// Addressing an entry written by the official action only makes sense when the content also
+/// follows its archive format.
+pub fn official_key_space(paths: &[&str], compression: Option<CompressionMethod>) -> String {
+    official_key_space_impl(paths, compression, false)
+}
+
+/// Computes the `key_space` the official cache action uses with `enableCrossOsArchive` set.
+///
+/// In contrast to [`official_key_space`], entries written on Windows share the key space with
+/// other platforms. Like the official action, paths are used as written, so to share entries
+/// between platforms, paths need to be written the same way on all of them, e.g. using `/`.
+pub fn official_key_space_cross_os(
+    paths: &[&str],
+    compression: Option<CompressionMethod>,
+) -> String {
+    official_key_space_impl(paths, compression, true)
+}
+
+fn official_key_space_impl(
+    paths: &[&str],
+    compression: Option<CompressionMethod>,
+    cross_os: bool,
+) -> String {
+    let mut components: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+    if let Some(compression) = compression {
+        components.push(compression.as_str().to_owned());
+    }
+    if cfg!(windows) && !cross_os {
+        components.push("windows-only".to_owned());
+    }
+    components.push(OFFICIAL_VERSION_SALT.to_owned());
+    digest::sha256_hex(components.join("|").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_os_removes_all_os_segments() {
+        let key = CacheKey::new("prefix")
+            .runner_os()
+            .segment("a")
+            .runner_os()
+            .cross_os()
+            .segment("b");
+        assert_eq!(key.key().unwrap(), "prefix-a-b");
+    }
+
+    #[test]
+    fn cross_os_ignores_empty_runner_os() {
+        std::env::set_var("RUNNER_OS", "");
+        let key = CacheKey::new("prefix").runner_os().cross_os();
+        assert_eq!(key.key().unwrap(), "prefix");
+    }
+}
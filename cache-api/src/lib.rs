@@ -6,50 +6,92 @@
 //!
 //! [source code]:https://github.com/actions/toolkit/tree/main/packages/cache
 //! [pinning specific versions]:https://docs.github.com/en/actions/learn-github-actions/finding-and-customizing-actions#using-shas
-use bytes::Bytes;
-use reqwest::{Client, RequestBuilder, Response};
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
-
-/// Errors that may occur within this crate.
-#[derive(Error, Debug)]
-#[non_exhaustive]
-pub enum Error {
-    /// Error making a HTTP request.
-    #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
-    /// Rate-limited HTTP request.
-    #[error("server rate limited the request, asking to wait {retry_after} seconds")]
-    RateLimit {
-        /// Time to wait until making a retry or follow-up request.
-        retry_after: u64,
-        /// Error included in the rate-limit response.
-        #[source]
-        source: reqwest::Error,
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
     },
-    /// Missing `ACTIONS_RUNTIME_TOKEN` environment variable.
-    #[error("did not find a runtime token in the ACTIONS_RUNTIME_TOKEN environment variable")]
-    NoRuntimeToken,
-    /// Missing `ACTIONS_CACHE_URL` environment variable.
-    #[error("did not find the endpoint URL in the ACTIONS_CACHE_URL environment variable")]
-    NoEndpointUrl,
-}
+    time::{Duration, Instant},
+};
 
-impl Error {
-    /// Returns the requested time to wait until retrying the rate limited request.
-    ///
-    /// If the cause for failure was not rate limiting, that cause is returned instead.
-    pub fn retry_after(&self) -> Option<u64> {
-        if let Self::RateLimit { retry_after, .. } = *self {
-            Some(retry_after)
-        } else {
-            None
-        }
-    }
-}
+use bytes::{Bytes, BytesMut};
+use futures_core::TryStream;
+use futures_util::StreamExt;
+use reqwest::{header::HeaderValue, Body, Client, Request, RequestBuilder, Response};
+
+use crate::{error::error_for_response, stats::Tracker, stream::ByteStream};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::Instrument;
 
-/// Result type used for fallible operations in this crate.
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+#[cfg(feature = "annotations")]
+pub mod annotations;
+pub mod artifacts;
+mod audit;
+pub mod azure;
+mod backend;
+mod block_blob;
+pub mod cargo_cache;
+pub mod cas;
+pub mod chain;
+pub mod chunked;
+mod circuit;
+mod clock;
+pub mod differential;
+mod digest;
+pub mod disk;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+mod error;
+mod events;
+mod glob;
+pub mod key;
+pub mod lock;
+pub mod management;
+mod memory;
+pub mod messages;
+pub mod metadata;
+#[cfg(feature = "metrics-recorder")]
+pub mod metrics;
+mod namespaced;
+pub mod objects;
+#[cfg(feature = "otel")]
+mod otel;
+mod paths;
+pub mod prefetch;
+mod progress;
+mod quirks;
+mod rate_limit;
+mod redact;
+mod retry;
+mod scope;
+pub mod snapshot;
+pub mod split;
+mod stats;
+mod stream;
+mod summary;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod transport;
+pub mod ttl;
+
+pub use backend::{BoxFuture, CacheBackend};
+pub use circuit::CircuitBreaker;
+pub use clock::{Clock, SystemClock};
+pub use error::{Error, Result, ServiceError, StatusError};
+pub use events::Events;
+pub use memory::InMemoryCache;
+pub use namespaced::NamespacedCache;
+pub use progress::{Direction, ProgressReporter};
+pub use quirks::ProviderQuirks;
+pub use rate_limit::RateLimitStatus;
+pub use retry::{ExponentialBackoff, NoRetry, RetryPolicy};
+pub use scope::Scope;
+pub use stats::{PutPhases, TransferStats};
+pub use summary::JobSummary;
+pub use transport::Transport;
 
 /// Metadata for a cache hit.
 #[derive(Deserialize, Debug)]
@@ -59,6 +101,57 @@ pub struct CacheHit {
     pub key: String,
     /// The scope (i.e. the branch which stored the entry).
     pub scope: String,
+    /// Whether the entry matched the first key exactly or only one of the key prefixes.
+    #[serde(skip)]
+    pub match_kind: MatchKind,
+}
+
+impl CacheHit {
+    /// Returns the parsed scope of the entry.
+    pub fn scope_info(&self) -> Scope {
+        Scope::parse(&self.scope)
+    }
+
+    /// Returns whether the entry was stored by a run on the repository's default branch.
+    ///
+    /// Returns `None` if the default branch is unknown, see [`Scope::is_default_branch`].
+    pub fn is_from_default_branch(&self) -> Option<bool> {
+        self.scope_info().is_default_branch()
+    }
+
+    /// Returns whether the entry's key is exactly the first key used for the lookup.
+    ///
+    /// This corresponds to the `cache-hit` output of the official cache action. Callers usually
+    /// store an updated entry unless this returns `true`.
+    pub fn is_exact(&self) -> bool {
+        self.match_kind == MatchKind::Exact
+    }
+}
+
+/// How a [`CacheHit`] matched the keys of a lookup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The entry's key is exactly the first key of the lookup.
+    Exact,
+    /// The entry was found as fallback using a key prefix.
+    #[default]
+    Prefix,
+}
+
+/// Outcome of a deduplicating put, see [`Cache::put_bytes_dedup`].
+#[derive(Debug)]
+pub struct DedupOutcome {
+    /// The full key of the entry, including the content digest.
+    pub key: String,
+    /// Statistics of the upload, `None` if an identical entry was already cached.
+    pub stats: Option<TransferStats>,
+}
+
+impl DedupOutcome {
+    /// Whether the content was uploaded, `false` if an identical entry was already cached.
+    pub fn uploaded(&self) -> bool {
+        self.stats.is_some()
+    }
 }
 
 /// Client for the cache API.
@@ -69,6 +162,22 @@ pub struct Cache {
     client: Client,
     token: String,
     endpoint: String,
+    retry_policy: Box<dyn RetryPolicy>,
+    max_retry_after: Option<Duration>,
+    rate_limit: Mutex<Option<RateLimitStatus>>,
+    circuit_breaker: Option<CircuitBreaker>,
+    raw_diagnostics: bool,
+    audit_log: Option<audit::AuditLog>,
+    summary: Option<Arc<JobSummary>>,
+    events: Option<Box<dyn Events>>,
+    progress: Option<Arc<dyn ProgressReporter>>,
+    transport: Option<Box<dyn Transport>>,
+    clock: Arc<dyn Clock>,
+    quirks: ProviderQuirks,
+    #[cfg(feature = "metrics-recorder")]
+    metrics: Option<Box<dyn metrics::Recorder>>,
+    #[cfg(feature = "annotations")]
+    annotator: Option<Box<dyn annotations::Annotator>>,
 }
 
 impl Cache {
@@ -77,29 +186,434 @@ impl Cache {
     /// The passed `user_agent` should identify the program using this library.
     pub fn new(user_agent: &str) -> Result<Self> {
         let token = std::env::var("ACTIONS_RUNTIME_TOKEN").map_err(|_| Error::NoRuntimeToken)?;
+        let cache_url = std::env::var("ACTIONS_CACHE_URL").map_err(|_| Error::NoEndpointUrl)?;
+        Self::with_endpoint(user_agent, &cache_url, &token)
+    }
 
-        let endpoint = format!(
-            "{}/_apis/artifactcache",
-            std::env::var("ACTIONS_CACHE_URL")
-                .map_err(|_| Error::NoEndpointUrl)?
-                .trim_end_matches('/')
-        );
+    /// Creates a new client instance for the given cache URL and runtime token.
+    ///
+    /// [`new`][Self::new] reads these from the `ACTIONS_CACHE_URL` and `ACTIONS_RUNTIME_TOKEN`
+    /// environment variables, which are only available to actions. API requests go to
+    /// `{cache_url}/_apis/artifactcache/...`, so a mock server has to serve these paths.
+    pub fn with_endpoint(user_agent: &str, cache_url: &str, token: &str) -> Result<Self> {
+        let endpoint = format!("{}/_apis/artifactcache", cache_url.trim_end_matches('/'));
 
         let client = Client::builder().user_agent(user_agent).build()?;
 
         Ok(Self {
             client,
-            token,
+            token: token.to_owned(),
             endpoint,
+            retry_policy: Box::new(NoRetry),
+            max_retry_after: None,
+            rate_limit: Mutex::new(None),
+            circuit_breaker: None,
+            raw_diagnostics: false,
+            audit_log: None,
+            summary: None,
+            events: None,
+            progress: None,
+            transport: None,
+            clock: Arc::new(SystemClock),
+            quirks: ProviderQuirks::default(),
+            #[cfg(feature = "metrics-recorder")]
+            metrics: None,
+            #[cfg(feature = "annotations")]
+            annotator: None,
         })
     }
 
+    /// Creates a client for tests against mock servers like `wiremock` or `httpmock`.
+    ///
+    /// This does not need any environment variables and uses the token `test-token`. Retries are
+    /// disabled and `Retry-After` times are capped to zero, so enabling retries, e.g. using
+    /// [`ExponentialBackoff::without_delay`], never waits.
+    pub fn for_testing(cache_url: &str) -> Result<Self> {
+        Ok(
+            Self::with_endpoint("rust-actions-cache-api-test", cache_url, "test-token")?
+                .with_max_retry_after(Duration::ZERO),
+        )
+    }
+
+    /// Sets the policy for retrying failed requests.
+    ///
+    /// By default, failed requests are not retried. Use [`ExponentialBackoff::default()`] for a
+    /// sensible retry policy.
+    pub fn with_retry_policy(mut self, retry_policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Box::new(retry_policy);
+        self
+    }
+
+    /// Disables retries, which is the default.
+    pub fn without_retries(self) -> Self {
+        self.with_retry_policy(NoRetry)
+    }
+
+    /// Limits the wait time requested by the server when rate limiting.
+    ///
+    /// This applies to the time reported by [`Error::retry_after`], which is also what retry
+    /// policies see. By default, the requested time is used as is.
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = Some(max_retry_after);
+        self
+    }
+
+    /// Enables a circuit breaker, failing fast during service outages.
+    ///
+    /// See [`CircuitBreaker`] for details. By default, there is no circuit breaker.
+    pub fn with_circuit_breaker(mut self, mut circuit_breaker: CircuitBreaker) -> Self {
+        circuit_breaker.set_clock(self.clock.clone());
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Notifies the given handler about operations of this client, see [`Events`].
+    pub fn with_events(mut self, events: impl Events + 'static) -> Self {
+        self.events = Some(Box::new(events));
+        self
+    }
+
+    /// Calls an event handler if one is set.
+    fn emit(&self, event: impl FnOnce(&dyn Events)) {
+        if let Some(events) = &self.events {
+            event(&**events);
+        }
+    }
+
+    /// Appends a JSON record for every lookup, download and upload to the file at `path`.
+    ///
+    /// Each line records the operation, key space, requested and resulting keys, the result,
+    /// transferred bytes, duration and number of HTTP request attempts. The file is created if it
+    /// does not exist. Failing to write records does not fail cache operations.
+    pub fn with_audit_log(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.audit_log = Some(audit::AuditLog::open(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Appends an audit log record if an audit log is set.
+    fn audit<'a>(&self, record: impl FnOnce() -> audit::Record<'a>) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.append(&record());
+        }
+    }
+
+    /// Records all restores and saves in the given summary.
+    ///
+    /// The caller keeps a clone of `summary` to render it once the job's cache operations are
+    /// done.
+    pub fn with_summary(mut self, summary: Arc<JobSummary>) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    /// Records an operation in the job summary if one is set.
+    fn summarize(&self, record: impl FnOnce(&JobSummary)) {
+        if let Some(summary) = &self.summary {
+            record(summary);
+        }
+    }
+
+    /// Reports the progress of uploads and downloads to the given reporter.
+    pub fn with_progress(mut self, progress: impl ProgressReporter + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Calls the progress reporter if one is set.
+    fn report(&self, progress: impl FnOnce(&dyn ProgressReporter)) {
+        if let Some(reporter) = &self.progress {
+            progress(&**reporter);
+        }
+    }
+
+    /// Executes requests using the given transport instead of sending them directly.
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    /// Uses the given clock for retries, rate limits and the circuit breaker.
+    ///
+    /// This is mostly useful for testing, see [`Clock`].
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        if let Some(circuit_breaker) = &mut self.circuit_breaker {
+            circuit_breaker.set_clock(self.clock.clone());
+        }
+        self
+    }
+
+    /// Tolerates the deviations of a third-party cache service, see [`ProviderQuirks`].
+    pub fn with_quirks(mut self, quirks: ProviderQuirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Disables redaction of sensitive values in errors and log output.
+    ///
+    /// By default, the runtime token, `Authorization` and cookie headers, and signatures or
+    /// credentials in URLs, like the SAS signatures of blob storage URLs, are replaced with
+    /// `REDACTED`. Enabling raw diagnostics can help debugging, but exposes these values to
+    /// anyone who can read the logs.
+    pub fn with_raw_diagnostics(mut self, raw_diagnostics: bool) -> Self {
+        self.raw_diagnostics = raw_diagnostics;
+        self
+    }
+
+    /// Reports metrics to the given recorder, see the [`metrics`] module.
+    #[cfg(feature = "metrics-recorder")]
+    pub fn with_metrics(mut self, recorder: impl metrics::Recorder + 'static) -> Self {
+        self.metrics = Some(Box::new(recorder));
+        self
+    }
+
+    /// Reports warnings about failures the client recovered from, see the [`annotations`] module.
+    #[cfg(feature = "annotations")]
+    pub fn with_annotations(mut self, annotator: impl annotations::Annotator + 'static) -> Self {
+        self.annotator = Some(Box::new(annotator));
+        self
+    }
+
+    /// Reports a warning if annotations are enabled.
+    #[allow(unused_variables)]
+    fn warn(&self, message: impl FnOnce() -> String) {
+        #[cfg(feature = "annotations")]
+        if let Some(annotator) = &self.annotator {
+            annotator.warning(&message());
+        }
+    }
+
+    /// Increments a counter if metrics are enabled.
+    #[allow(unused_variables)]
+    fn count(&self, name: &'static str, value: u64, labels: &[(&'static str, &str)]) {
+        #[cfg(feature = "metrics-recorder")]
+        if let Some(metrics) = &self.metrics {
+            metrics.increment_counter(name, value, labels);
+        }
+    }
+
+    /// Records a histogram value if metrics are enabled.
+    #[allow(unused_variables)]
+    fn histogram(&self, name: &'static str, value: f64, labels: &[(&'static str, &str)]) {
+        #[cfg(feature = "metrics-recorder")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_histogram(name, value, labels);
+        }
+    }
+
+    /// Returns the most recent rate-limit state reported by the server.
+    ///
+    /// This is `None` until a response including rate-limit headers was received.
+    pub fn rate_limit(&self) -> Option<RateLimitStatus> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+
+    /// Sends a request, retrying it if configured, and turns error responses into errors.
+    ///
+    /// Each request is wrapped in a span recording the outcome and the ids GitHub's services
+    /// assign to requests, which are needed when reporting problems to GitHub support.
+    async fn send(&self, builder: RequestBuilder, tracker: &Tracker) -> Result<Response> {
+        let request = builder.build()?;
+        let span = self.request_span(&request);
+
+        async {
+            if let Some(circuit_breaker) = &self.circuit_breaker {
+                circuit_breaker.check()?;
+            }
+            let start = Instant::now();
+            let result = self.send_with_retries(request, tracker).await;
+            let span = tracing::Span::current();
+            span.record("duration_ms", start.elapsed().as_millis() as u64);
+            if let Err(error) = &result {
+                span.record("otel.status_code", "ERROR");
+                span.record("error.type", error_type(error));
+            }
+            if let Some(circuit_breaker) = &self.circuit_breaker {
+                circuit_breaker.record(&result);
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Sends a request once, without retries or the circuit breaker, for requests whose failure
+    /// says nothing about the service.
+    async fn send_once(&self, builder: RequestBuilder) -> Result<Response> {
+        let request = builder.build()?;
+        let url = request.url().clone();
+        let response = match &self.transport {
+            Some(transport) => transport.execute(request).await,
+            None => self.client.execute(request).await.map_err(Error::from),
+        };
+        error_for_response(
+            response?,
+            url,
+            self.max_retry_after,
+            self.clock.system_now(),
+        )
+        .await
+        .map_err(|error| self.redact(error))
+    }
+
+    #[cfg(not(feature = "otel"))]
+    fn request_span(&self, request: &Request) -> tracing::Span {
+        tracing::info_span!(
+            "cache_request",
+            method = %request.method(),
+            path = request.url().path(),
+            status = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            request_id = tracing::field::Empty,
+            github_request_id = tracing::field::Empty,
+            session_id = tracing::field::Empty,
+            e2e_id = tracing::field::Empty,
+        )
+    }
+
+    /// Creates the request span, including attributes following the OpenTelemetry semantic
+    /// conventions for HTTP clients.
+    ///
+    /// The `otel.*` fields are interpreted by `tracing-opentelemetry`.
+    #[cfg(feature = "otel")]
+    fn request_span(&self, request: &Request) -> tracing::Span {
+        let url = request.url();
+        let full_url = if self.raw_diagnostics {
+            url.clone()
+        } else {
+            redact::redact_url(url)
+        };
+        tracing::info_span!(
+            "cache_request",
+            method = %request.method(),
+            path = url.path(),
+            status = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            request_id = tracing::field::Empty,
+            github_request_id = tracing::field::Empty,
+            session_id = tracing::field::Empty,
+            e2e_id = tracing::field::Empty,
+            otel.name = %request.method(),
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            peer.service = otel::PEER_SERVICE,
+            http.request.method = %request.method(),
+            http.request.resend_count = tracing::field::Empty,
+            http.response.status_code = tracing::field::Empty,
+            url.full = %full_url,
+            server.address = url.host_str(),
+            server.port = url.port_or_known_default(),
+            error.type = tracing::field::Empty,
+        )
+    }
+
+    async fn send_with_retries(&self, mut request: Request, tracker: &Tracker) -> Result<Response> {
+        let span = tracing::Span::current();
+        let start = self.clock.now();
+        let mut attempts = 0;
+        loop {
+            let retry_request = request.try_clone();
+            attempts += 1;
+            tracker.add_attempt();
+            span.record("attempts", attempts);
+            if attempts > 1 {
+                span.record("http.request.resend_count", attempts - 1);
+            }
+
+            let method = request.method().clone();
+            let url = request.url().clone();
+            let attempt_start = Instant::now();
+
+            let result = async {
+                let response = match &self.transport {
+                    Some(transport) => transport.execute(request).await,
+                    None => self.client.execute(request).await.map_err(Error::from),
+                };
+
+                self.histogram(
+                    metric::REQUEST_DURATION,
+                    attempt_start.elapsed().as_secs_f64(),
+                    &[("method", method.as_str())],
+                );
+                let status = match &response {
+                    Ok(response) => response.status().as_str().to_owned(),
+                    Err(_) => "error".to_owned(),
+                };
+                self.count(
+                    metric::REQUESTS,
+                    1,
+                    &[("method", method.as_str()), ("status", &status)],
+                );
+
+                let response = response?;
+
+                if self.raw_diagnostics {
+                    tracing::debug!(response_headers = ?response.headers());
+                } else {
+                    tracing::debug!(response_headers = ?redact::Headers(response.headers()));
+                }
+                record_response(&span, &response);
+
+                if let Some(status) = RateLimitStatus::from_headers(response.headers()) {
+                    *self.rate_limit.lock().unwrap() = Some(status);
+                }
+
+                error_for_response(response, url, self.max_retry_after, self.clock.system_now())
+                    .await
+            }
+            .await;
+
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(error) => self.redact(error),
+            };
+
+            if matches!(error, Error::RateLimit { .. } | Error::TooManyRequests(_)) {
+                let retry_after = error.retry_after().map(Duration::from_secs);
+                self.emit(|events| events.on_rate_limited(retry_after));
+            }
+
+            let delay = self
+                .retry_policy
+                .retry_delay(attempts, self.clock.now() - start, &error);
+
+            match (delay, retry_request) {
+                (Some(delay), Some(retry_request)) => {
+                    tracing::debug!(%error, ?delay, attempts, "retrying request");
+                    self.warn(|| {
+                        format!("cache request failed, retrying in {:?}: {}", delay, error)
+                    });
+                    self.count(metric::RETRIES, 1, &[]);
+                    tracker.add_retry();
+                    self.emit(|events| events.on_retry(attempts, delay, &error));
+                    self.clock.sleep(delay).await;
+                    request = retry_request;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// Redacts sensitive values in an error unless raw diagnostics are enabled.
+    fn redact(&self, error: Error) -> Error {
+        if self.raw_diagnostics {
+            error
+        } else {
+            error.redacted(&self.token)
+        }
+    }
+
     /// Adds authorization and accept headers needed for an API request.
     fn api_request(&self, builder: RequestBuilder) -> RequestBuilder {
-        builder.bearer_auth(&self.token).header(
-            reqwest::header::ACCEPT,
-            "application/json;api-version=6.0-preview.1",
-        )
+        let accept = self.quirks.accept().cloned().unwrap_or_else(|| {
+            HeaderValue::from_static("application/json;api-version=6.0-preview.1")
+        });
+        builder
+            .bearer_auth(&self.token)
+            .header(reqwest::header::ACCEPT, accept)
+            .headers(self.quirks.headers().clone())
     }
 
     /// Performs a cache lookup and returns the URL for a matching entry.
@@ -107,6 +621,9 @@ impl Cache {
     /// * `key_space` - parameter is an identifier, usually a hex string, which must match exactly
     /// * `key_prefixes` - list of key prefixes to look up in order of preference
     ///
+    /// An entry whose key is exactly the first key prefix is reported as [`MatchKind::Exact`]. A
+    /// [`RestoreKeys`][key::RestoreKeys] value can be used to construct this list.
+    ///
     /// See the [official documentation] for the precedence in case of multiple matching entries.
     /// Note that `key_space` is not exposed by the official client and thus not mentioned there.
     ///
@@ -115,6 +632,41 @@ impl Cache {
         &self,
         key_space: &str,
         key_prefixes: &[&str],
+    ) -> Result<Option<(CacheHit, String)>> {
+        let result = self.get_url_audited(key_space, key_prefixes).await;
+        let key = key_prefixes.first().copied().unwrap_or_default();
+        self.summarize(|summary| match &result {
+            Ok(Some((hit, _))) => summary.record_hit(key, hit, None),
+            Ok(None) => summary.record_miss(key),
+            Err(error) => summary.record_error(false, key, error),
+        });
+        result
+    }
+
+    async fn get_url_audited(
+        &self,
+        key_space: &str,
+        key_prefixes: &[&str],
+    ) -> Result<Option<(CacheHit, String)>> {
+        let start = Instant::now();
+        let tracker = Tracker::default();
+        let result = self.lookup(key_space, key_prefixes, &tracker).await;
+        self.audit(|| {
+            let record = audit::Record::new("get_url", key_space, key_prefixes, start, &tracker);
+            match &result {
+                Ok(Some((hit, _))) => record.result("hit", Some(&hit.key)),
+                Ok(None) => record.result("miss", None),
+                Err(error) => record.error(error),
+            }
+        });
+        result
+    }
+
+    async fn lookup(
+        &self,
+        key_space: &str,
+        key_prefixes: &[&str],
+        tracker: &Tracker,
     ) -> Result<Option<(CacheHit, String)>> {
         #[derive(Deserialize)]
         pub struct GetResponse {
@@ -124,19 +676,36 @@ impl Cache {
             location: String,
         }
 
-        let response = self
-            .api_request(self.client.get(format!("{}/cache", self.endpoint)))
-            .query(&[("keys", &*key_prefixes.join(",")), ("version", key_space)])
-            .send()
-            .await?;
+        for key in key_prefixes {
+            key::validate_key(key)?;
+        }
 
-        tracing::debug!(response_headers = ?response.headers());
+        let response = match self
+            .send(
+                self.api_request(self.client.get(format!("{}/cache", self.endpoint)))
+                    .query(&[("keys", &*key_prefixes.join(",")), ("version", key_space)]),
+                tracker,
+            )
+            .await
+        {
+            Err(error) if self.quirks.is_miss(&error) => None,
+            response => Some(response?),
+        };
 
-        if response.status() == reqwest::StatusCode::NO_CONTENT {
-            Ok(None)
-        } else {
-            let response: GetResponse = error_for_response(response)?.json().await?;
-            Ok(Some((response.hit, response.location)))
+        match response {
+            Some(response) if response.status() != reqwest::StatusCode::NO_CONTENT => {
+                self.count(metric::LOOKUPS, 1, &[("result", "hit")]);
+                let mut response: GetResponse = response.json().await?;
+                if key_prefixes.first() == Some(&&*response.hit.key) {
+                    response.hit.match_kind = MatchKind::Exact;
+                }
+                Ok(Some((response.hit, response.location)))
+            }
+            _ => {
+                self.count(metric::LOOKUPS, 1, &[("result", "miss")]);
+                self.emit(|events| events.on_lookup_miss(key_space, key_prefixes));
+                Ok(None)
+            }
         }
     }
 
@@ -148,19 +717,391 @@ impl Cache {
         key_space: &str,
         keys: &[&str],
     ) -> Result<Option<(CacheHit, Bytes)>> {
-        if let Some((hit, location)) = self.get_url(key_space, keys).await? {
-            let response = self.client.get(location).send().await?;
+        Ok(self
+            .get_bytes_with_stats(key_space, keys)
+            .await?
+            .map(|(hit, data, _)| (hit, data)))
+    }
+
+    /// Like [`get_bytes`][Self::get_bytes], but also returns statistics about the download.
+    ///
+    /// The statistics cover both the lookup and the download of the content.
+    pub async fn get_bytes_with_stats(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Bytes, TransferStats)>> {
+        let start = Instant::now();
+        let tracker = Tracker::default();
+        let result = self.download(key_space, keys, start, &tracker).await;
+        self.record_download(
+            "get_bytes",
+            key_space,
+            keys,
+            start,
+            &tracker,
+            result
+                .as_ref()
+                .map(|found| found.as_ref().map(|(hit, _, stats)| (hit, stats.bytes))),
+        );
+        result
+    }
 
-            tracing::debug!(response_headers = ?response.headers());
+    /// Performs a cache lookup and writes the content of a matching entry to a file.
+    ///
+    /// The content is streamed to disk and never fully buffered in memory. The file is only
+    /// created for a hit, replacing any existing file, and is removed again if the download
+    /// fails. See [`get_url`][Self::get_url] for details about the lookup.
+    pub async fn get_file(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        path: impl AsRef<Path>,
+    ) -> Result<Option<(CacheHit, TransferStats)>> {
+        let start = Instant::now();
+        let tracker = Tracker::default();
+        let result = self
+            .download_file(key_space, keys, path.as_ref(), start, &tracker)
+            .await;
+        self.record_download(
+            "get_file",
+            key_space,
+            keys,
+            start,
+            &tracker,
+            result
+                .as_ref()
+                .map(|found| found.as_ref().map(|(hit, stats)| (hit, stats.bytes))),
+        );
+        result
+    }
 
-            Ok(Some((hit, response.bytes().await?)))
-        } else {
-            Ok(None)
+    /// Records the outcome of a download in the audit log and the job summary.
+    fn record_download(
+        &self,
+        operation: &'static str,
+        key_space: &str,
+        keys: &[&str],
+        start: Instant,
+        tracker: &Tracker,
+        result: std::result::Result<Option<(&CacheHit, u64)>, &Error>,
+    ) {
+        self.audit(|| {
+            let record = audit::Record::new(operation, key_space, keys, start, tracker);
+            match result {
+                Ok(Some((hit, bytes))) => record.result("hit", Some(&hit.key)).bytes(bytes),
+                Ok(None) => record.result("miss", None),
+                Err(error) => record.error(error),
+            }
+        });
+        let key = keys.first().copied().unwrap_or_default();
+        self.summarize(|summary| match result {
+            Ok(Some((hit, bytes))) => summary.record_hit(key, hit, Some(bytes)),
+            Ok(None) => summary.record_miss(key),
+            Err(error) => summary.record_error(false, key, error),
+        });
+    }
+
+    async fn download(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        start: Instant,
+        tracker: &Tracker,
+    ) -> Result<Option<(CacheHit, Bytes, TransferStats)>> {
+        let Some((hit, response)) = self.fetch(key_space, keys, tracker).await? else {
+            return Ok(None);
+        };
+        let mut data = vec![];
+        let bytes = self.receive(&hit.key, response, &mut data).await?;
+        Ok(Some((
+            hit,
+            data.into(),
+            download_stats(bytes, start, tracker),
+        )))
+    }
+
+    async fn download_file(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        path: &Path,
+        start: Instant,
+        tracker: &Tracker,
+    ) -> Result<Option<(CacheHit, TransferStats)>> {
+        let Some((hit, response)) = self.fetch(key_space, keys, tracker).await? else {
+            return Ok(None);
+        };
+        let result = async {
+            let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(path).await?);
+            self.receive(&hit.key, response, &mut file).await
         }
+        .await;
+        match result {
+            Ok(bytes) => Ok(Some((hit, download_stats(bytes, start, tracker)))),
+            Err(err) => {
+                let _ = tokio::fs::remove_file(path).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Looks up a matching entry and starts downloading its content.
+    async fn fetch(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        tracker: &Tracker,
+    ) -> Result<Option<(CacheHit, Response)>> {
+        let Some((hit, location)) = self.lookup(key_space, keys, tracker).await? else {
+            return Ok(None);
+        };
+        let response = self.send(self.client.get(location), tracker).await?;
+        Ok(Some((hit, response)))
+    }
+
+    /// Writes the content of a download to `out`, returning its size.
+    async fn receive(
+        &self,
+        key: &str,
+        mut response: Response,
+        out: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<u64> {
+        self.report(|progress| progress.start(Direction::Download, key, response.content_length()));
+        let result = async {
+            let mut size = 0;
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|err| self.redact(err.into()))?
+            {
+                self.report(|progress| progress.advance(Direction::Download, chunk.len() as u64));
+                out.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+            }
+            out.flush().await?;
+            Ok::<_, Error>(size)
+        }
+        .await;
+        self.report(|progress| progress.finish(Direction::Download));
+
+        let size = result?;
+        self.count(metric::DOWNLOADED_BYTES, size, &[]);
+        Ok(size)
     }
 
     /// Stores an entry in the cache.
-    pub async fn put_bytes(&self, key_space: &str, key: &str, data: Bytes) -> Result<()> {
+    pub async fn put_bytes(
+        &self,
+        key_space: &str,
+        key: &str,
+        data: Bytes,
+    ) -> Result<TransferStats> {
+        self.put_content(key_space, key, Content::Bytes(data)).await
+    }
+
+    /// Stores the content of a file as an entry in the cache.
+    ///
+    /// The file is streamed from disk and never fully buffered in memory.
+    pub async fn put_file(
+        &self,
+        key_space: &str,
+        key: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<TransferStats> {
+        let file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        self.put_stream(key_space, key, size, ReaderStream::new(file))
+            .await
+    }
+
+    /// Stores an entry in the cache, streaming its content.
+    ///
+    /// The `stream` must produce exactly `size` bytes.
+    pub async fn put_stream<S>(
+        &self,
+        key_space: &str,
+        key: &str,
+        size: u64,
+        stream: S,
+    ) -> Result<TransferStats>
+    where
+        S: TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        self.put_content(
+            key_space,
+            key,
+            Content::Stream(size, ByteStream::new(stream)),
+        )
+        .await
+    }
+
+    /// Stores an entry in the cache unless an entry with identical content already exists.
+    ///
+    /// The SHA-256 digest of `data` is appended to `key_prefix` to form the full key. If an entry
+    /// with exactly that key is found, the upload is skipped. Restoring with `key_prefix` as a
+    /// key prefix finds the entry as usual.
+    pub async fn put_bytes_dedup(
+        &self,
+        key_space: &str,
+        key_prefix: &str,
+        data: Bytes,
+    ) -> Result<DedupOutcome> {
+        let key = format!("{}{}", key_prefix, digest::sha256_hex(&data));
+        let stats = if self.has_exact(key_space, &key).await? {
+            self.audit_skipped(key_space, &key);
+            None
+        } else {
+            Some(self.put_bytes(key_space, &key, data).await?)
+        };
+        Ok(DedupOutcome { key, stats })
+    }
+
+    /// Stores the content of a file unless an entry with identical content already exists.
+    ///
+    /// This reads the file twice, once to compute the digest and once to upload it. See
+    /// [`put_bytes_dedup`][Self::put_bytes_dedup] for details.
+    pub async fn put_file_dedup(
+        &self,
+        key_space: &str,
+        key_prefix: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<DedupOutcome> {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut hasher = digest::Sha256::new();
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let len = file.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+
+        let key = format!("{}{}", key_prefix, hasher.finish_hex());
+        let stats = if self.has_exact(key_space, &key).await? {
+            self.audit_skipped(key_space, &key);
+            None
+        } else {
+            Some(self.put_file(key_space, &key, path).await?)
+        };
+        Ok(DedupOutcome { key, stats })
+    }
+
+    fn audit_skipped(&self, key_space: &str, key: &str) {
+        self.audit(|| {
+            audit::Record::new("put", key_space, &[], Instant::now(), &Tracker::default())
+                .result("skipped", Some(key))
+        });
+        self.summarize(|summary| summary.record_skipped(key));
+    }
+
+    /// Checks whether an entry with exactly the given key exists.
+    async fn has_exact(&self, key_space: &str, key: &str) -> Result<bool> {
+        Ok(matches!(
+            self.get_url_audited(key_space, &[key]).await?,
+            Some((hit, _)) if hit.key == key
+        ))
+    }
+
+    async fn put_content(
+        &self,
+        key_space: &str,
+        key: &str,
+        content: Content,
+    ) -> Result<TransferStats> {
+        let start = Instant::now();
+        let tracker = Arc::new(Tracker::default());
+        self.report(|progress| progress.start(Direction::Upload, key, Some(content.size())));
+        let result = self
+            .store(key_space, key, content, start, tracker.clone())
+            .await;
+        self.report(|progress| progress.finish(Direction::Upload));
+        self.audit(|| {
+            let record = audit::Record::new("put", key_space, &[], start, &tracker);
+            match &result {
+                Ok(stats) => record.result("stored", Some(key)).bytes(stats.bytes),
+                Err(error) => record.result("error", Some(key)).error(error),
+            }
+        });
+        self.summarize(|summary| match &result {
+            Ok(stats) => summary.record_stored(key, stats.bytes),
+            Err(error) => summary.record_error(true, key, error),
+        });
+        result
+    }
+
+    async fn store(
+        &self,
+        key_space: &str,
+        key: &str,
+        content: Content,
+        start: Instant,
+        tracker: Arc<Tracker>,
+    ) -> Result<TransferStats> {
+        let reserved = self.reserve_tracked(key_space, key, tracker).await?;
+        let mut phases = PutPhases {
+            reserve: start.elapsed(),
+            ..PutPhases::default()
+        };
+
+        let size = content.size();
+        let result: Result<()> = async {
+            let upload_start = Instant::now();
+            match self.quirks.max_chunk_size() {
+                Some(max_chunk_size) if size > max_chunk_size => {
+                    reserved.upload_chunked(content, max_chunk_size).await?
+                }
+                _ => reserved.upload_content(0, content).await?,
+            }
+            phases.upload = upload_start.elapsed();
+
+            let finalize_start = Instant::now();
+            reserved.commit(size).await?;
+            phases.finalize = finalize_start.elapsed();
+            Ok(())
+        }
+        .await;
+
+        if result.is_err() {
+            reserved.abort().await;
+        }
+        result?;
+
+        tracing::debug!(
+            reserve_ms = phases.reserve.as_millis() as u64,
+            upload_ms = phases.upload.as_millis() as u64,
+            finalize_ms = phases.finalize.as_millis() as u64,
+            "stored cache entry"
+        );
+
+        Ok(TransferStats {
+            bytes: size,
+            elapsed: start.elapsed(),
+            retries: reserved.tracker.retries(),
+            phases: Some(phases),
+        })
+    }
+
+    /// Reserves a cache entry for the given key.
+    ///
+    /// This is the first step of storing an entry. The returned handle is used to upload the
+    /// content and to commit the entry. Most users should use one of the `put_*` methods instead,
+    /// which perform all steps and clean up after failures.
+    pub async fn reserve(&self, key_space: &str, key: &str) -> Result<ReservedCache<'_>> {
+        self.reserve_tracked(key_space, key, Arc::new(Tracker::default()))
+            .await
+    }
+
+    async fn reserve_tracked(
+        &self,
+        key_space: &str,
+        key: &str,
+        tracker: Arc<Tracker>,
+    ) -> Result<ReservedCache<'_>> {
         #[derive(Serialize)]
         struct ReserveRequest<'a> {
             key: &'a str,
@@ -172,78 +1113,272 @@ impl Cache {
             cache_id: i64,
         }
 
+        key::validate_key(key)?;
+
         let response = self
-            .api_request(self.client.post(format!("{}/caches", self.endpoint)))
-            .json(&ReserveRequest {
-                key,
-                version: key_space,
-            })
-            .send()
-            .await?;
+            .send(
+                self.api_request(self.client.post(format!("{}/caches", self.endpoint)))
+                    .json(&ReserveRequest {
+                        key,
+                        version: key_space,
+                    }),
+                &tracker,
+            )
+            .await
+            .map_err(|error| self.quirks.reserve_error(error))?;
 
-        tracing::debug!(response_headers = ?response.headers());
+        let ReserveResponse { cache_id } = response.json().await?;
 
-        let ReserveResponse { cache_id } = error_for_response(response)?.json().await?;
+        Ok(ReservedCache {
+            cache: self,
+            cache_id,
+            uploaded: Arc::new(AtomicU64::new(0)),
+            tracker,
+        })
+    }
+}
 
-        if !data.is_empty() {
-            let response = self
-                .api_request(
-                    self.client
-                        .patch(format!("{}/caches/{}", self.endpoint, cache_id)),
-                )
-                .header(
-                    reqwest::header::CONTENT_RANGE,
-                    format!("bytes {}-{}/*", 0, data.len() - 1),
-                )
-                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
-                .body(data.clone())
-                .send()
-                .await?;
+/// Metric names, available independent of the `metrics-recorder` feature to keep call sites simple.
+mod metric {
+    pub(crate) const REQUESTS: &str = "gha_cache_requests_total";
+    pub(crate) const REQUEST_DURATION: &str = "gha_cache_request_duration_seconds";
+    pub(crate) const RETRIES: &str = "gha_cache_retries_total";
+    pub(crate) const LOOKUPS: &str = "gha_cache_lookups_total";
+    pub(crate) const UPLOADED_BYTES: &str = "gha_cache_uploaded_bytes_total";
+    pub(crate) const DOWNLOADED_BYTES: &str = "gha_cache_downloaded_bytes_total";
+}
+
+/// Records the status and correlation ids of a response in the request span.
+fn record_response(span: &tracing::Span, response: &Response) {
+    span.record("status", response.status().as_u16());
+    span.record("http.response.status_code", response.status().as_u16());
+    for (field, header) in [
+        ("request_id", "x-ms-request-id"),
+        ("github_request_id", "x-github-request-id"),
+        ("session_id", "x-tfs-session"),
+        ("e2e_id", "x-vss-e2eid"),
+    ] {
+        if let Some(value) = response.headers().get(header).and_then(|v| v.to_str().ok()) {
+            span.record(field, value);
+        }
+    }
+}
 
-            tracing::debug!(response_headers = ?response.headers());
+/// Returns the statistics of a download started at `start`.
+fn download_stats(bytes: u64, start: Instant, tracker: &Tracker) -> TransferStats {
+    TransferStats {
+        bytes,
+        elapsed: start.elapsed(),
+        retries: tracker.retries(),
+        phases: None,
+    }
+}
+
+/// Returns a low-cardinality classification of an error, used for the `error.type` attribute.
+fn error_type(error: &Error) -> String {
+    match error {
+        Error::Reqwest(err) if err.is_timeout() => "timeout".to_owned(),
+        Error::Reqwest(err) if err.is_connect() => "connect".to_owned(),
+        Error::Reqwest(_) => "request".to_owned(),
+        Error::Unavailable { .. } => "circuit_open".to_owned(),
+        _ => match error.status() {
+            Some(status) => status.as_str().to_owned(),
+            None => "other".to_owned(),
+        },
+    }
+}
 
-            error_for_response(response)?;
+/// Handle for a reserved but not yet committed cache entry.
+///
+/// Returned by [`Cache::reserve`]. The content is uploaded using one or more `upload_*` calls,
+/// after which the entry is made visible using [`commit`][Self::commit]. If anything goes wrong
+/// in between, [`abort`][Self::abort] should be called.
+pub struct ReservedCache<'a> {
+    cache: &'a Cache,
+    cache_id: i64,
+    uploaded: Arc<AtomicU64>,
+    tracker: Arc<Tracker>,
+}
+
+/// Content of an upload.
+enum Content {
+    Bytes(Bytes),
+    Stream(u64, ByteStream),
+}
+
+impl Content {
+    fn size(&self) -> u64 {
+        match self {
+            Self::Bytes(data) => data.len() as u64,
+            Self::Stream(size, _) => *size,
         }
+    }
+}
 
-        #[derive(Serialize)]
-        struct RequestBody<'a> {
-            key: &'a str,
-            version: &'a str,
+impl<'a> ReservedCache<'a> {
+    /// The id assigned to this entry by the cache service.
+    pub fn id(&self) -> i64 {
+        self.cache_id
+    }
+
+    fn url(&self) -> String {
+        format!("{}/caches/{}", self.cache.endpoint, self.cache_id)
+    }
+
+    /// Returns the number of bytes uploaded so far.
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of requests retried so far, including the reservation.
+    pub fn retries(&self) -> u32 {
+        self.tracker.retries()
+    }
+
+    /// Uploads a chunk of data starting at the given `offset`.
+    pub async fn upload_bytes(&self, offset: u64, data: Bytes) -> Result<()> {
+        self.upload_content(offset, Content::Bytes(data)).await
+    }
+
+    /// Uploads a streamed chunk of `size` bytes starting at the given `offset`.
+    pub async fn upload_stream<S>(&self, offset: u64, size: u64, stream: S) -> Result<()>
+    where
+        S: TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        self.upload_content(offset, Content::Stream(size, ByteStream::new(stream)))
+            .await
+    }
+
+    /// Uploads the content from the start in requests of at most `max_chunk_size` bytes.
+    async fn upload_chunked(&self, content: Content, max_chunk_size: u64) -> Result<()> {
+        let max_chunk_size = max_chunk_size as usize;
+        match content {
+            Content::Bytes(data) => {
+                for offset in (0..data.len()).step_by(max_chunk_size) {
+                    let end = data.len().min(offset + max_chunk_size);
+                    self.upload_bytes(offset as u64, data.slice(offset..end))
+                        .await?;
+                }
+            }
+            Content::Stream(_, mut stream) => {
+                let mut offset = 0;
+                let mut buffer = BytesMut::new();
+                while let Some(chunk) = stream.next().await {
+                    let mut chunk = chunk.map_err(std::io::Error::other)?;
+                    while !chunk.is_empty() {
+                        let take = chunk.len().min(max_chunk_size - buffer.len());
+                        buffer.extend_from_slice(&chunk.split_to(take));
+                        if buffer.len() == max_chunk_size {
+                            let data = buffer.split().freeze();
+                            let size = data.len() as u64;
+                            self.upload_bytes(offset, data).await?;
+                            offset += size;
+                        }
+                    }
+                }
+                self.upload_bytes(offset, buffer.freeze()).await?;
+            }
         }
+        Ok(())
+    }
 
-        #[derive(Serialize)]
-        struct FinalizeRequest {
-            size: usize,
+    async fn upload_content(&self, offset: u64, content: Content) -> Result<()> {
+        let size = content.size();
+        if size == 0 {
+            return Ok(());
         }
 
-        let response = self
-            .api_request(
-                self.client
-                    .post(format!("{}/caches/{}", self.endpoint, cache_id)),
+        // Streams count bytes as they are sent, as they might end early.
+        let (body, counted) = match content {
+            Content::Bytes(data) => (Body::from(data), false),
+            Content::Stream(_, stream) => {
+                let uploaded = self.uploaded.clone();
+                let progress = self.cache.progress.clone();
+                let stream = stream.inspect(move |chunk| {
+                    uploaded.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                    if let Some(progress) = &progress {
+                        progress.advance(Direction::Upload, chunk.len() as u64);
+                    }
+                });
+                (Body::wrap_stream(stream), true)
+            }
+        };
+
+        self.cache
+            .send(
+                self.cache
+                    .api_request(self.cache.client.patch(self.url()))
+                    .header(
+                        reqwest::header::CONTENT_RANGE,
+                        format!("bytes {}-{}/*", offset, offset + size - 1),
+                    )
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .header(reqwest::header::CONTENT_LENGTH, size)
+                    .body(body),
+                &self.tracker,
             )
-            .json(&FinalizeRequest { size: data.len() })
-            .send()
             .await?;
 
-        tracing::debug!(response_headers = ?response.headers());
-
-        error_for_response(response)?;
+        if !counted {
+            self.uploaded.fetch_add(size, Ordering::Relaxed);
+            self.cache
+                .report(|progress| progress.advance(Direction::Upload, size));
+        }
+        self.cache.count(metric::UPLOADED_BYTES, size, &[]);
+        self.cache
+            .emit(|events| events.on_chunk_uploaded(self.cache_id, offset, size));
         Ok(())
     }
-}
 
-fn error_for_response(response: Response) -> Result<Response> {
-    if response.status().is_client_error() || response.status().is_server_error() {
-        if let Some(retry_after) = response
-            .headers()
-            .get(reqwest::header::RETRY_AFTER)
-            .and_then(|v| v.to_str().ok()?.parse().ok())
-        {
-            return Err(Error::RateLimit {
-                retry_after,
-                source: response.error_for_status().unwrap_err(),
+    /// Commits the entry, making it visible to lookups.
+    ///
+    /// The `size` must be the total number of bytes uploaded. To avoid committing a truncated
+    /// entry, this fails with [`Error::IncompleteUpload`] if it differs from the number of bytes
+    /// actually uploaded using this handle.
+    pub async fn commit(&self, size: u64) -> Result<()> {
+        let uploaded = self.uploaded();
+        if uploaded != size {
+            return Err(Error::IncompleteUpload {
+                expected: size,
+                uploaded,
             });
         }
+
+        #[derive(Serialize)]
+        struct FinalizeRequest {
+            size: u64,
+        }
+
+        self.cache
+            .send(
+                self.cache
+                    .api_request(self.cache.client.post(self.url()))
+                    .json(&FinalizeRequest { size }),
+                &self.tracker,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Discards the reservation on a best-effort basis.
+    ///
+    /// The cache API has no way to release a reservation, so by default this does nothing and
+    /// the reservation is left to expire on the server side. For services that offer it, see
+    /// [`ProviderQuirks::with_abort_endpoint`], a single `DELETE` is sent for the reserved
+    /// entry, bypassing retries and the circuit breaker, and any failure is ignored.
+    pub async fn abort(&self) {
+        if !self.cache.quirks.has_abort_endpoint() {
+            return;
+        }
+        let result = self
+            .cache
+            .send_once(self.cache.api_request(self.cache.client.delete(self.url())))
+            .await;
+
+        if let Err(err) = result {
+            tracing::debug!(cache_id = self.cache_id, %err, "failed to abort reservation");
+        }
     }
-    response.error_for_status().map_err(Into::into)
 }
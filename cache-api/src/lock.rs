@@ -0,0 +1,253 @@
+//! Best-effort mutual exclusion between workflow runs, using cache entries.
+//!
+//! Entries can't be overwritten or deleted, so a lock is a sequence of claims. Acquiring the
+//! lock stores the next claim, and as only one of several concurrent puts of the same key
+//! succeeds, only one run acquires it. The holder stores heartbeats while it holds the lock,
+//! and a release entry when it is done. A claim without a release or a recent heartbeat has
+//! expired, so a crashed holder blocks others for at most the lock's time to live.
+//!
+//! This relies on the clocks of runners being roughly in sync, and entries are only visible
+//! according to the cache service's scoping: runs for different branches only see the default
+//! branch's claims, so they can't exclude each other.
+use std::{
+    future::Future,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use futures_util::future::{self, Either};
+use serde::{Deserialize, Serialize};
+
+use crate::{digest, CacheBackend, Clock, Error, Result, SystemClock};
+
+#[derive(Serialize, Deserialize)]
+struct Claim {
+    owner: String,
+    acquired_at: u64,
+}
+
+struct Holder {
+    generation: u64,
+    owner: String,
+    alive_at: u64,
+    released: bool,
+}
+
+/// A named lock shared by all runs using the same name.
+pub struct CacheLock<B> {
+    backend: B,
+    name: String,
+    key_space: String,
+    owner: String,
+    ttl: Duration,
+    poll_interval: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl<B: CacheBackend> CacheLock<B> {
+    /// Creates the lock with the given name, expiring after 10 minutes without a heartbeat.
+    pub fn new(backend: B, name: impl Into<String>) -> Self {
+        let name = name.into();
+        let now = SystemClock.system_now().duration_since(UNIX_EPOCH);
+        let owner = format!(
+            "{}-{}-{}",
+            std::env::var("GITHUB_RUN_ID").unwrap_or_default(),
+            std::process::id(),
+            now.map_or(0, |now| now.as_nanos()),
+        );
+        Self {
+            backend,
+            key_space: digest::sha256_hex(format!("lock\n{}", name).as_bytes()),
+            name,
+            owner,
+            ttl: Duration::from_secs(600),
+            poll_interval: Duration::from_secs(10),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Sets the time after which a claim without a heartbeat expires.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl.max(Duration::from_secs(3));
+        self
+    }
+
+    /// Sets how often [`acquire`][Self::acquire] checks whether the lock is free, every 10
+    /// seconds by default.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the identity recorded in claims, unique to this process by default.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    /// Sets the clock used for timestamps and waiting.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Returns the lock's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn now(&self) -> u64 {
+        self.clock
+            .system_now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        // Lookups match key prefixes, only an entry with exactly this key counts.
+        Ok(self
+            .backend
+            .lookup(&self.key_space, &[key])
+            .await?
+            .is_some_and(|hit| hit.key == key))
+    }
+
+    /// Returns the latest claim, as lookups return the most recently stored match.
+    async fn holder(&self) -> Result<Option<Holder>> {
+        let Some(hit) = self.backend.lookup(&self.key_space, &["claim-"]).await? else {
+            return Ok(None);
+        };
+        let Some(generation) = hit
+            .key
+            .strip_prefix("claim-")
+            .and_then(|generation| generation.parse().ok())
+        else {
+            return Ok(None);
+        };
+        let claim = match self.backend.get_bytes(&self.key_space, &[&hit.key]).await? {
+            Some((found, data)) if found.key == hit.key => {
+                serde_json::from_slice::<Claim>(&data)
+                    .map_err(|err| Error::InvalidManifest(err.to_string()))?
+            }
+            _ => return Ok(None),
+        };
+        let heartbeat = self
+            .backend
+            .lookup(
+                &self.key_space,
+                &[&format!("heartbeat-{:010}-", generation)],
+            )
+            .await?
+            .and_then(|hit| hit.key.rsplit_once('-')?.1.parse().ok());
+        Ok(Some(Holder {
+            generation,
+            owner: claim.owner,
+            alive_at: heartbeat.unwrap_or(0).max(claim.acquired_at),
+            released: self.exists(&format!("release-{:010}", generation)).await?,
+        }))
+    }
+
+    /// Acquires the lock if it is free or expired.
+    ///
+    /// Returns `None` if another run holds the lock or acquired it concurrently.
+    pub async fn try_acquire(&self) -> Result<Option<LockGuard<'_, B>>> {
+        let generation = match self.holder().await? {
+            None => 0,
+            Some(holder)
+                if holder.released
+                    || self.now().saturating_sub(holder.alive_at) > self.ttl.as_secs() =>
+            {
+                holder.generation + 1
+            }
+            Some(_) => return Ok(None),
+        };
+        let claim = Claim {
+            owner: self.owner.clone(),
+            acquired_at: self.now(),
+        };
+        let data = serde_json::to_vec(&claim).expect("claims serialize");
+        let key = format!("claim-{:010}", generation);
+        match self
+            .backend
+            .put_bytes(&self.key_space, &key, data.into())
+            .await
+        {
+            Ok(()) => Ok(Some(LockGuard {
+                lock: self,
+                generation,
+            })),
+            Err(Error::Conflict(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits until the lock is acquired.
+    pub async fn acquire(&self) -> Result<LockGuard<'_, B>> {
+        loop {
+            if let Some(guard) = self.try_acquire().await? {
+                return Ok(guard);
+            }
+            self.clock.sleep(self.poll_interval).await;
+        }
+    }
+}
+
+/// A held [`CacheLock`].
+///
+/// Dropping the guard without [releasing][Self::release] it leaves the lock held until it
+/// expires.
+pub struct LockGuard<'a, B> {
+    lock: &'a CacheLock<B>,
+    generation: u64,
+}
+
+impl<B: CacheBackend> LockGuard<'_, B> {
+    /// Extends the lock's lifetime by its time to live.
+    ///
+    /// Fails with [`Error::LockLost`] if the lock expired and someone else acquired it.
+    pub async fn heartbeat(&self) -> Result<()> {
+        let lock = self.lock;
+        match lock.holder().await? {
+            Some(holder) if holder.generation == self.generation && holder.owner == lock.owner => {}
+            _ => {
+                return Err(Error::LockLost {
+                    name: lock.name.clone(),
+                })
+            }
+        }
+        let key = format!("heartbeat-{:010}-{}", self.generation, lock.now());
+        match lock
+            .backend
+            .put_bytes(&lock.key_space, &key, "".into())
+            .await
+        {
+            Ok(()) | Err(Error::Conflict(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Runs `future` while holding the lock, storing heartbeats every third of its time to live.
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output> {
+        let mut future = std::pin::pin!(future);
+        loop {
+            let sleep = self.lock.clock.sleep(self.lock.ttl / 3);
+            match future::select(future.as_mut(), sleep).await {
+                Either::Left((output, _)) => return Ok(output),
+                Either::Right(((), _)) => self.heartbeat().await?,
+            }
+        }
+    }
+
+    /// Releases the lock.
+    pub async fn release(self) -> Result<()> {
+        let lock = self.lock;
+        let key = format!("release-{:010}", self.generation);
+        match lock
+            .backend
+            .put_bytes(&lock.key_space, &key, "".into())
+            .await
+        {
+            Ok(()) | Err(Error::Conflict(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,834 @@
+//! Client for the cache management REST API.
+//!
+//! Unlike [`Cache`][crate::Cache], which uses the runtime token only available to actions,
+//! [`CacheManagement`] uses the [REST API] with a `GITHUB_TOKEN` or other token having the
+//! `actions` permission. It can inspect entries of a repository from anywhere. Instead of a
+//! fixed token, any [`TokenSource`] can be used, like a GitHub App installation token with the
+//! `github-app` feature.
+//!
+//! [REST API]: https://docs.github.com/en/rest/actions/cache
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+
+use crate::{artifacts::Artifact, error::error_for_response, glob, key, Error, Result};
+
+mod pages;
+mod token;
+
+pub use pages::Paginated;
+#[cfg(feature = "github-app")]
+pub use token::InstallationToken;
+pub use token::TokenSource;
+
+/// Maximal page size of list endpoints.
+const MAX_PER_PAGE: u32 = 100;
+
+/// Client for the cache management REST API of a repository.
+pub struct CacheManagement {
+    client: Client,
+    token: Box<dyn TokenSource>,
+    api_url: String,
+    owner: String,
+    repo: String,
+}
+
+/// A cache entry as reported by the REST API.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct ActionsCache {
+    /// The id of the entry.
+    pub id: u64,
+    /// The git ref which stored the entry, i.e. its scope.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// The key of the entry.
+    pub key: String,
+    /// The key space of the entry, called version by the API.
+    pub version: String,
+    /// When the entry was last restored, as RFC 3339 timestamp.
+    pub last_accessed_at: String,
+    /// When the entry was created, as RFC 3339 timestamp.
+    pub created_at: String,
+    /// The size of the entry.
+    pub size_in_bytes: u64,
+}
+
+impl ActionsCache {
+    /// Returns the parsed time the entry was last restored.
+    pub fn last_accessed(&self) -> Option<SystemTime> {
+        parse_timestamp(&self.last_accessed_at)
+    }
+
+    /// Returns the parsed time the entry was created.
+    pub fn created(&self) -> Option<SystemTime> {
+        parse_timestamp(&self.created_at)
+    }
+}
+
+/// A page of cache entries.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct CacheList {
+    /// The total number of entries matching the query, across all pages.
+    pub total_count: u64,
+    /// The entries of the requested page.
+    pub actions_caches: Vec<ActionsCache>,
+}
+
+/// A page of artifacts as returned by the REST API.
+#[derive(Deserialize)]
+struct ArtifactList {
+    total_count: u64,
+    artifacts: Vec<ListedArtifact>,
+}
+
+#[derive(Deserialize)]
+struct ListedArtifact {
+    id: u64,
+    name: String,
+    size_in_bytes: u64,
+    created_at: Option<String>,
+    expires_at: Option<String>,
+    digest: Option<String>,
+}
+
+/// Cache usage of a repository.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct RepositoryCacheUsage {
+    /// The repository as `owner/repo`.
+    pub full_name: String,
+    /// Total size of all active entries.
+    pub active_caches_size_in_bytes: u64,
+    /// Number of active entries.
+    pub active_caches_count: u64,
+}
+
+/// Cache usage summed over all repositories of an organization.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct OrganizationCacheUsage {
+    /// Number of active entries.
+    pub total_active_caches_count: u64,
+    /// Total size of all active entries.
+    pub total_active_caches_size_in_bytes: u64,
+}
+
+/// A page of per-repository cache usage of an organization.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct RepositoryCacheUsageList {
+    /// The total number of repositories with cache usage, across all pages.
+    pub total_count: u64,
+    /// The usage of the repositories of the requested page.
+    pub repository_cache_usages: Vec<RepositoryCacheUsage>,
+}
+
+/// Selection of entries to delete using [`CacheManagement::prune`].
+///
+/// An entry is selected if it matches all configured filters. Without any filters, all entries
+/// are selected.
+#[derive(Clone, Debug, Default)]
+pub struct Prune {
+    unused_for: Option<Duration>,
+    key_patterns: Vec<String>,
+    ref_patterns: Vec<String>,
+    dry_run: bool,
+    concurrency: Option<usize>,
+}
+
+impl Prune {
+    /// Creates a selection of all entries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only selects entries not restored for at least the given duration.
+    pub fn unused_for(mut self, duration: Duration) -> Self {
+        self.unused_for = Some(duration);
+        self
+    }
+
+    /// Only selects entries whose key matches one of the given glob patterns, e.g. `Linux-*`.
+    ///
+    /// Can be called multiple times. `*` matches any characters, including `/`.
+    pub fn key_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.key_patterns.push(pattern.into());
+        self
+    }
+
+    /// Only selects entries whose git ref matches one of the given glob patterns, e.g.
+    /// `refs/pull/*`.
+    ///
+    /// Can be called multiple times.
+    pub fn ref_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.ref_patterns.push(pattern.into());
+        self
+    }
+
+    /// Only reports the selected entries without deleting them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets the number of concurrent delete requests, see [`CacheManagement::delete_caches`].
+    ///
+    /// Entries are deleted one at a time by default.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn selects(&self, cache: &ActionsCache, now: SystemTime) -> bool {
+        let unused = match self.unused_for {
+            Some(duration) => cache.last_accessed().is_some_and(|accessed| {
+                now.duration_since(accessed).unwrap_or_default() >= duration
+            }),
+            None => true,
+        };
+        let matches = |patterns: &[String], text: &str| {
+            patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|pattern| glob::matches_str(pattern, text))
+        };
+        unused
+            && matches(&self.key_patterns, &cache.key)
+            && matches(&self.ref_patterns, &cache.git_ref)
+    }
+}
+
+/// Outcome of [`CacheManagement::prune`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct PruneReport {
+    /// The selected entries that were deleted, or would be deleted in a dry run.
+    pub deleted: Vec<ActionsCache>,
+    /// The selected entries that could not be deleted.
+    pub failed: Vec<(ActionsCache, Error)>,
+    /// Whether this was a dry run.
+    pub dry_run: bool,
+}
+
+/// Options for [`CacheManagement::evict_to_fit`].
+#[derive(Clone, Debug, Default)]
+pub struct Evict {
+    protected_keys: Vec<String>,
+    dry_run: bool,
+    concurrency: Option<usize>,
+}
+
+impl Evict {
+    /// Creates options allowing the eviction of any entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never evicts entries whose key matches the given glob pattern, e.g. `Linux-cargo-*`.
+    ///
+    /// Can be called multiple times. `*` matches any characters, including `/`.
+    pub fn protect_key(mut self, pattern: impl Into<String>) -> Self {
+        self.protected_keys.push(pattern.into());
+        self
+    }
+
+    /// Only reports the entries that would be evicted without deleting them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets the number of concurrent delete requests, see [`CacheManagement::delete_caches`].
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn protects(&self, cache: &ActionsCache) -> bool {
+        self.protected_keys
+            .iter()
+            .any(|pattern| glob::matches_str(pattern, &cache.key))
+    }
+}
+
+/// Outcome of [`CacheManagement::evict_to_fit`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct EvictionReport {
+    /// Total size of all entries before evicting any.
+    pub size_before: u64,
+    /// Total size of the remaining entries, or of those that would remain in a dry run.
+    pub size_after: u64,
+    /// The requested target size.
+    pub target: u64,
+    /// The evicted entries, least recently used first, or those that would be evicted in a dry
+    /// run.
+    pub evicted: Vec<ActionsCache>,
+    /// The entries selected for eviction that could not be deleted.
+    pub failed: Vec<(ActionsCache, Error)>,
+    /// Whether this was a dry run.
+    pub dry_run: bool,
+}
+
+impl EvictionReport {
+    /// Returns whether the remaining entries fit into the target size.
+    ///
+    /// This is not the case if deletions failed or the protected entries alone exceed the target.
+    pub fn fits(&self) -> bool {
+        self.size_after <= self.target
+    }
+}
+
+/// Outcome of [`CacheManagement::delete_caches`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct BulkDeleteReport {
+    /// Ids of the deleted entries, in the order the deletions completed.
+    pub deleted: Vec<u64>,
+    /// Ids of the entries that could not be deleted, with the error returned for each.
+    pub failed: Vec<(u64, Error)>,
+}
+
+impl BulkDeleteReport {
+    /// Returns whether all entries were deleted.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl PruneReport {
+    /// Returns the total size of the deleted entries.
+    pub fn deleted_bytes(&self) -> u64 {
+        self.deleted.iter().map(|cache| cache.size_in_bytes).sum()
+    }
+}
+
+/// Property by which listed entries are sorted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Creation time.
+    CreatedAt,
+    /// Time of the last restore, the API's default.
+    LastAccessedAt,
+    /// Size of the entry.
+    SizeInBytes,
+}
+
+/// Direction in which listed entries are sorted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    /// Ascending order.
+    Asc,
+    /// Descending order, the API's default.
+    Desc,
+}
+
+/// Filters and ordering for listing cache entries.
+#[derive(Clone, Debug, Default)]
+pub struct CacheQuery {
+    key: Option<String>,
+    git_ref: Option<String>,
+    sort: Option<(SortBy, SortDirection)>,
+    per_page: Option<u32>,
+    page: Option<u32>,
+}
+
+impl CacheQuery {
+    /// Creates a query for all entries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only lists entries whose key starts with `key`.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Only lists entries stored by the given git ref, e.g. `refs/heads/main`.
+    pub fn git_ref(mut self, git_ref: impl Into<String>) -> Self {
+        self.git_ref = Some(git_ref.into());
+        self
+    }
+
+    /// Sorts the entries.
+    pub fn sort(mut self, by: SortBy, direction: SortDirection) -> Self {
+        self.sort = Some((by, direction));
+        self
+    }
+
+    /// Sets the number of entries per page, at most 100.
+    pub fn per_page(mut self, per_page: u32) -> Self {
+        self.per_page = Some(per_page);
+        self
+    }
+
+    /// Selects the page to list, starting at 1.
+    pub fn page(mut self, page: u32) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![];
+        if let Some(key) = &self.key {
+            params.push(("key", key.clone()));
+        }
+        if let Some(git_ref) = &self.git_ref {
+            params.push(("ref", git_ref.clone()));
+        }
+        if let Some((by, direction)) = self.sort {
+            let by = match by {
+                SortBy::CreatedAt => "created_at",
+                SortBy::LastAccessedAt => "last_accessed_at",
+                SortBy::SizeInBytes => "size_in_bytes",
+            };
+            let direction = match direction {
+                SortDirection::Asc => "asc",
+                SortDirection::Desc => "desc",
+            };
+            params.push(("sort", by.to_owned()));
+            params.push(("direction", direction.to_owned()));
+        }
+        if let Some(per_page) = self.per_page {
+            params.push(("per_page", per_page.to_string()));
+        }
+        if let Some(page) = self.page {
+            params.push(("page", page.to_string()));
+        }
+        params
+    }
+}
+
+impl CacheManagement {
+    /// Creates a client for the repository of the current workflow run.
+    ///
+    /// This uses the `GITHUB_TOKEN`, `GITHUB_REPOSITORY` and `GITHUB_API_URL` environment
+    /// variables. Note that `GITHUB_TOKEN` is not set automatically, but has to be passed to
+    /// steps explicitly.
+    pub fn new(user_agent: &str) -> Result<Self> {
+        let token = std::env::var("GITHUB_TOKEN").map_err(|_| Error::NoGithubToken)?;
+        let repository = std::env::var("GITHUB_REPOSITORY").map_err(|_| Error::NoRepository)?;
+        let (owner, repo) = repository.split_once('/').ok_or(Error::NoRepository)?;
+        let api_url =
+            std::env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".to_owned());
+        Self::with_repository(user_agent, &api_url, &token, owner, repo)
+    }
+
+    /// Creates a client for the given repository, API URL and token.
+    pub fn with_repository(
+        user_agent: &str,
+        api_url: &str,
+        token: &str,
+        owner: &str,
+        repo: &str,
+    ) -> Result<Self> {
+        let client = Client::builder().user_agent(user_agent).build()?;
+        Ok(Self {
+            client,
+            token: Box::new(token.to_owned()),
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+        })
+    }
+
+    /// Uses the given source for the tokens of all following requests.
+    pub fn with_token_source(mut self, source: impl TokenSource + 'static) -> Self {
+        self.token = Box::new(source);
+        self
+    }
+
+    /// Lists a page of the repository's cache entries.
+    pub async fn list_caches(&self, query: &CacheQuery) -> Result<CacheList> {
+        let response = self
+            .send(
+                self.client
+                    .get(self.repo_url("actions/caches"))
+                    .query(&query.params()),
+            )
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Returns a stream of all entries matching `query`, requesting pages as needed.
+    ///
+    /// Any page set on the query is ignored, its page size is used unless overridden with
+    /// [`Paginated::per_page`].
+    pub fn caches(&self, query: &CacheQuery) -> Paginated<'_, ActionsCache> {
+        let query = query.clone();
+        Paginated::new(query.per_page, move |page, per_page| {
+            let query = query.clone().per_page(per_page).page(page);
+            Box::pin(async move {
+                let list = self.list_caches(&query).await?;
+                Ok((list.total_count, list.actions_caches))
+            })
+        })
+    }
+
+    /// Lists all entries matching `query`, requesting all pages.
+    ///
+    /// Any page set on the query is ignored.
+    pub async fn list_all_caches(&self, query: &CacheQuery) -> Result<Vec<ActionsCache>> {
+        self.caches(query).try_collect().await
+    }
+
+    /// Deletes the entries selected by `prune`.
+    ///
+    /// All entries are listed before deleting any, so deletions do not shift pages. Failing to
+    /// delete an entry does not stop deleting the others, failures are collected in the report
+    /// instead.
+    pub async fn prune(&self, prune: &Prune) -> Result<PruneReport> {
+        let now = SystemTime::now();
+        let selected: Vec<ActionsCache> = self
+            .list_all_caches(&CacheQuery::new())
+            .await?
+            .into_iter()
+            .filter(|cache| prune.selects(cache, now))
+            .collect();
+
+        let mut report = PruneReport {
+            dry_run: prune.dry_run,
+            ..PruneReport::default()
+        };
+        if prune.dry_run {
+            report.deleted = selected;
+            return Ok(report);
+        }
+
+        (report.deleted, report.failed) = self
+            .delete_entries(selected, prune.concurrency.unwrap_or(1))
+            .await;
+        Ok(report)
+    }
+
+    /// Deletes least recently used entries until the repository uses at most `target_bytes`.
+    ///
+    /// This lets jobs make room for new entries themselves, instead of leaving it to the service,
+    /// which evicts entries once the repository exceeds its limit without regard for which ones
+    /// are still needed. Entries are ordered by the time of their last restore. Protected entries
+    /// are never evicted, but count towards the total size.
+    ///
+    /// If the usage reported by the API is already within the target, no entries are listed.
+    /// Otherwise the total size is computed from the listed entries, as the reported usage is only
+    /// updated periodically.
+    pub async fn evict_to_fit(&self, target_bytes: u64, evict: &Evict) -> Result<EvictionReport> {
+        let usage = self.usage().await?;
+        if usage.active_caches_size_in_bytes <= target_bytes {
+            return Ok(EvictionReport {
+                size_before: usage.active_caches_size_in_bytes,
+                size_after: usage.active_caches_size_in_bytes,
+                target: target_bytes,
+                dry_run: evict.dry_run,
+                ..EvictionReport::default()
+            });
+        }
+
+        let mut caches = self.list_all_caches(&CacheQuery::new()).await?;
+        let size_before: u64 = caches.iter().map(|cache| cache.size_in_bytes).sum();
+        // Entries with unparsable timestamps are treated as least recently used.
+        caches.sort_by_key(|cache| (cache.last_accessed(), cache.id));
+
+        let mut size = size_before;
+        let selected: Vec<ActionsCache> = caches
+            .into_iter()
+            .filter(|cache| !evict.protects(cache))
+            .take_while(|cache| {
+                let needed = size > target_bytes;
+                if needed {
+                    size -= cache.size_in_bytes;
+                }
+                needed
+            })
+            .collect();
+
+        let mut report = EvictionReport {
+            size_before,
+            size_after: size_before,
+            target: target_bytes,
+            dry_run: evict.dry_run,
+            ..EvictionReport::default()
+        };
+
+        if evict.dry_run {
+            report.evicted = selected;
+        } else {
+            (report.evicted, report.failed) = self
+                .delete_entries(selected, evict.concurrency.unwrap_or(1))
+                .await;
+        }
+
+        report.size_after -= report
+            .evicted
+            .iter()
+            .map(|cache| cache.size_in_bytes)
+            .sum::<u64>();
+        Ok(report)
+    }
+
+    /// Deletes the given entries, splitting them into deleted and failed ones.
+    async fn delete_entries(
+        &self,
+        entries: Vec<ActionsCache>,
+        concurrency: usize,
+    ) -> (Vec<ActionsCache>, Vec<(ActionsCache, Error)>) {
+        let ids: Vec<u64> = entries.iter().map(|cache| cache.id).collect();
+        let mut outcome = self.delete_caches(ids, concurrency).await;
+        let mut deleted = vec![];
+        let mut failed = vec![];
+        for cache in entries {
+            match outcome.failed.iter().position(|(id, _)| *id == cache.id) {
+                Some(index) => failed.push((cache, outcome.failed.swap_remove(index).1)),
+                None => deleted.push(cache),
+            }
+        }
+        (deleted, failed)
+    }
+
+    /// Deletes the entries with the given ids, running up to `concurrency` requests at once.
+    ///
+    /// Failing to delete an entry does not stop deleting the others, failures are collected in
+    /// the report instead. Entries that are already gone are reported as failed with
+    /// [`Error::NotFound`]. A `concurrency` of 0 is treated as 1.
+    pub async fn delete_caches(
+        &self,
+        ids: impl IntoIterator<Item = u64>,
+        concurrency: usize,
+    ) -> BulkDeleteReport {
+        let mut results = futures_util::stream::iter(ids)
+            .map(|id| async move { (id, self.delete_cache(id).await) })
+            .buffer_unordered(concurrency.max(1));
+
+        let mut report = BulkDeleteReport::default();
+        while let Some((id, result)) = results.next().await {
+            match result {
+                Ok(()) => report.deleted.push(id),
+                Err(err) => report.failed.push((id, err)),
+            }
+        }
+        report
+    }
+
+    /// Deletes all entries with exactly the given key, optionally only those of `git_ref`.
+    ///
+    /// Returns the deleted entries. Fails with [`Error::NotFound`] if no entry matched.
+    pub async fn delete_caches_by_key(
+        &self,
+        key: &str,
+        git_ref: Option<&str>,
+    ) -> Result<CacheList> {
+        let mut params = vec![("key", key)];
+        if let Some(git_ref) = git_ref {
+            params.push(("ref", git_ref));
+        }
+        let response = self
+            .send(
+                self.client
+                    .delete(self.repo_url("actions/caches"))
+                    .query(&params),
+            )
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Deletes the entry with the given id.
+    pub async fn delete_cache(&self, cache_id: u64) -> Result<()> {
+        self.send(
+            self.client
+                .delete(self.repo_url(&format!("actions/caches/{}", cache_id))),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns a stream of the artifacts of a workflow run, requesting pages as needed.
+    ///
+    /// The `run_id` is the id shown in the web interface and available to workflows as
+    /// `GITHUB_RUN_ID`. Unlike [`ArtifactClient::list_artifacts`], this includes the time at
+    /// which artifacts expire.
+    ///
+    /// [`ArtifactClient::list_artifacts`]: crate::artifacts::ArtifactClient::list_artifacts
+    pub fn run_artifacts(&self, run_id: u64) -> Paginated<'_, Artifact> {
+        self.artifacts(format!("actions/runs/{}/artifacts", run_id), None)
+    }
+
+    /// Returns a stream of the artifacts of all workflow runs of the repository, optionally only
+    /// those with the given name.
+    ///
+    /// Artifacts are listed newest first.
+    pub fn repository_artifacts(&self, name: Option<&str>) -> Paginated<'_, Artifact> {
+        self.artifacts("actions/artifacts".to_owned(), name.map(str::to_owned))
+    }
+
+    /// Deletes the artifact with the given id.
+    pub async fn delete_artifact(&self, artifact_id: u64) -> Result<()> {
+        self.send(
+            self.client
+                .delete(self.repo_url(&format!("actions/artifacts/{}", artifact_id))),
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn artifacts(&self, path: String, name: Option<String>) -> Paginated<'_, Artifact> {
+        Paginated::new(None, move |page, per_page| {
+            let mut params = vec![
+                ("page", page.to_string()),
+                ("per_page", per_page.to_string()),
+            ];
+            if let Some(name) = &name {
+                params.push(("name", name.clone()));
+            }
+            let url = self.repo_url(&path);
+            Box::pin(async move {
+                let response = self.send(self.client.get(url).query(&params)).await?;
+                let list: ArtifactList = response.json().await?;
+                let artifacts = list
+                    .artifacts
+                    .into_iter()
+                    .map(|artifact| Artifact {
+                        id: artifact.id,
+                        name: artifact.name,
+                        size: artifact.size_in_bytes,
+                        created_at: artifact.created_at,
+                        expires_at: artifact.expires_at,
+                        sha256: crate::artifacts::sha256_of_digest(artifact.digest),
+                    })
+                    .collect();
+                Ok((list.total_count, artifacts))
+            })
+        })
+    }
+
+    /// Returns the cache usage of the repository.
+    pub async fn usage(&self) -> Result<RepositoryCacheUsage> {
+        let response = self
+            .send(self.client.get(self.repo_url("actions/cache/usage")))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Returns the total cache usage of all repositories of the organization `org`.
+    ///
+    /// This needs a token with the `read:org` scope or organization administration permission.
+    pub async fn organization_usage(&self, org: &str) -> Result<OrganizationCacheUsage> {
+        let response = self
+            .send(self.client.get(self.org_url(org, "actions/cache/usage")))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Returns a page of the cache usage of the repositories of the organization `org`.
+    ///
+    /// Pages start at 1 and have at most 100 entries.
+    pub async fn organization_usage_by_repository(
+        &self,
+        org: &str,
+        page: u32,
+        per_page: u32,
+    ) -> Result<RepositoryCacheUsageList> {
+        let response = self
+            .send(
+                self.client
+                    .get(self.org_url(org, "actions/cache/usage-by-repository"))
+                    .query(&[("page", page), ("per_page", per_page)]),
+            )
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// Returns a stream of the cache usage of all repositories of the organization `org`,
+    /// requesting pages as needed.
+    pub fn organization_usages_by_repository(
+        &self,
+        org: &str,
+    ) -> Paginated<'_, RepositoryCacheUsage> {
+        let org = org.to_owned();
+        Paginated::new(None, move |page, per_page| {
+            let org = org.clone();
+            Box::pin(async move {
+                let list = self
+                    .organization_usage_by_repository(&org, page, per_page)
+                    .await?;
+                Ok((list.total_count, list.repository_cache_usages))
+            })
+        })
+    }
+
+    /// Returns the cache usage of all repositories of the organization `org`, requesting all
+    /// pages.
+    pub async fn organization_usage_by_repository_all(
+        &self,
+        org: &str,
+    ) -> Result<Vec<RepositoryCacheUsage>> {
+        self.organization_usages_by_repository(org)
+            .try_collect()
+            .await
+    }
+
+    fn org_url(&self, org: &str, path: &str) -> String {
+        format!("{}/orgs/{}/{}", self.api_url, org, path)
+    }
+
+    fn repo_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/{}",
+            self.api_url, self.owner, self.repo, path
+        )
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let token = self.token.token().await?;
+        let request = builder
+            .bearer_auth(&token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .header("x-github-api-version", "2022-11-28")
+            .build()?;
+        let url = request.url().clone();
+        let response = self
+            .client
+            .execute(request)
+            .await
+            .map_err(|err| Error::from(err).redacted(&token))?;
+        error_for_response(response, url, None, SystemTime::now())
+            .await
+            .map_err(|err| err.redacted(&token))
+    }
+}
+
+/// Parses an RFC 3339 timestamp like `2022-09-21T09:57:04Z`.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: u32 = date.next()?.parse().ok()?;
+    let day: u32 = date.next()?.parse().ok()?;
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => return None,
+    };
+    let mut parts = time.splitn(3, ':');
+    let hour: i64 = parts.next()?.parse().ok()?;
+    let minute: i64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+
+    let offset_secs = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    let days = key::days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + minute * 60 - offset_secs;
+    let time = Duration::try_from_secs_f64(secs as f64 + seconds).ok()?;
+    UNIX_EPOCH.checked_add(time)
+}
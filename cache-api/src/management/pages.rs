@@ -0,0 +1,116 @@
+//! Pagination of list endpoints.
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use super::MAX_PER_PAGE;
+use crate::{backend::BoxFuture, Result};
+
+/// A page as returned by a list endpoint: the total number of items and the page's items.
+type Page<T> = (u64, Vec<T>);
+
+type Fetch<'a, T> = Box<dyn FnMut(u32, u32) -> BoxFuture<'a, Result<Page<T>>> + Send + 'a>;
+
+/// [`Stream`] of all items of a list endpoint, requesting pages as needed.
+///
+/// Returned by [`CacheManagement::caches`] and the other streaming list methods of
+/// [`CacheManagement`][super::CacheManagement]. Pages are requested one after another
+/// once the items of the previous page have been consumed. The stream ends after the last page,
+/// which is detected by a short page or by reaching the total count reported by the API. After an
+/// error, the stream ends as well.
+///
+/// [`CacheManagement::caches`]: super::CacheManagement::caches
+pub struct Paginated<'a, T> {
+    fetch: Fetch<'a, T>,
+    per_page: u32,
+    next_page: u32,
+    seen: u64,
+    buffered: VecDeque<T>,
+    pending: Option<BoxFuture<'a, Result<Page<T>>>>,
+    done: bool,
+}
+
+impl<'a, T> Paginated<'a, T> {
+    pub(crate) fn new(
+        per_page: Option<u32>,
+        fetch: impl FnMut(u32, u32) -> BoxFuture<'a, Result<Page<T>>> + Send + 'a,
+    ) -> Self {
+        Self {
+            fetch: Box::new(fetch),
+            per_page: per_page.unwrap_or(MAX_PER_PAGE).clamp(1, MAX_PER_PAGE),
+            next_page: 1,
+            seen: 0,
+            buffered: VecDeque::new(),
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Sets the number of items requested per page, between 1 and 100.
+    ///
+    /// Defaults to the page size of the query, if any, and otherwise to the maximum of 100. Smaller
+    /// pages return the first items sooner, larger pages need fewer requests.
+    pub fn per_page(mut self, per_page: u32) -> Self {
+        self.per_page = per_page.clamp(1, MAX_PER_PAGE);
+        self
+    }
+
+    /// Collects all remaining items, failing on the first error.
+    pub async fn try_collect(mut self) -> Result<Vec<T>>
+    where
+        T: Unpin,
+    {
+        let mut items = vec![];
+        while let Some(item) = std::future::poll_fn(|cx| Pin::new(&mut self).poll_next(cx)).await {
+            items.push(item?);
+        }
+        Ok(items)
+    }
+}
+
+impl<'a, T: Unpin> Stream for Paginated<'a, T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(item) = this.buffered.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            let pending = match &mut this.pending {
+                Some(pending) => pending,
+                None => {
+                    let page = this.next_page;
+                    this.next_page += 1;
+                    this.pending.insert((this.fetch)(page, this.per_page))
+                }
+            };
+
+            let result = match pending.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result,
+            };
+            this.pending = None;
+
+            match result {
+                Ok((total_count, items)) => {
+                    this.seen += items.len() as u64;
+                    this.done = items.len() < this.per_page as usize || this.seen >= total_count;
+                    this.buffered.extend(items);
+                }
+                Err(err) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+}
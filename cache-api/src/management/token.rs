@@ -0,0 +1,171 @@
+//! Tokens for authenticating with the REST API.
+use std::sync::Arc;
+
+use crate::{backend::BoxFuture, Result};
+
+/// Provides the token used by [`CacheManagement`][super::CacheManagement].
+///
+/// Implemented for `String` and `&'static str` for fixed tokens. Sources of short-lived tokens
+/// should cache them and only refresh them when they are about to expire, as this is called for
+/// every request.
+pub trait TokenSource: Send + Sync {
+    /// Returns a currently valid token.
+    fn token(&self) -> BoxFuture<'_, Result<String>>;
+}
+
+impl TokenSource for String {
+    fn token(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(async move { Ok(self.clone()) })
+    }
+}
+
+impl TokenSource for &'static str {
+    fn token(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(async move { Ok((*self).to_owned()) })
+    }
+}
+
+impl<T: TokenSource + ?Sized> TokenSource for Arc<T> {
+    fn token(&self) -> BoxFuture<'_, Result<String>> {
+        (**self).token()
+    }
+}
+
+#[cfg(feature = "github-app")]
+pub use app::InstallationToken;
+
+#[cfg(feature = "github-app")]
+mod app {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
+    use reqwest::Client;
+    use serde::Deserialize;
+    use tokio::sync::Mutex;
+
+    use super::TokenSource;
+    use crate::{
+        backend::BoxFuture, error::error_for_response, management::parse_timestamp, Error, Result,
+    };
+
+    /// Time before expiry at which installation tokens are refreshed.
+    const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+    /// [`TokenSource`] for a GitHub App installation, available with the `github-app` feature.
+    ///
+    /// Signs a JWT with the app's private key and exchanges it for an installation access token,
+    /// which is reused until shortly before it expires.
+    pub struct InstallationToken {
+        client: Client,
+        api_url: String,
+        app_id: String,
+        installation_id: u64,
+        key: PKey<openssl::pkey::Private>,
+        cached: Mutex<Option<(String, SystemTime)>>,
+    }
+
+    impl InstallationToken {
+        /// Creates a token source for the given app and installation.
+        ///
+        /// The `private_key_pem` is the PEM encoded RSA key generated for the app.
+        pub fn new(
+            api_url: &str,
+            app_id: impl Into<String>,
+            installation_id: u64,
+            private_key_pem: &[u8],
+        ) -> Result<Self> {
+            let key = Rsa::private_key_from_pem(private_key_pem)
+                .and_then(PKey::from_rsa)
+                .map_err(|err| Error::InvalidCredentials(err.to_string()))?;
+            Ok(Self {
+                client: Client::builder()
+                    .user_agent(concat!(
+                        "rust-actions-cache-api/",
+                        env!("CARGO_PKG_VERSION")
+                    ))
+                    .build()?,
+                api_url: api_url.trim_end_matches('/').to_owned(),
+                app_id: app_id.into(),
+                installation_id,
+                key,
+                cached: Mutex::new(None),
+            })
+        }
+
+        /// Creates the JWT authenticating as the app.
+        fn jwt(&self, now: SystemTime) -> Result<String> {
+            let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            // Backdated to allow for clock drift, GitHub accepts at most 10 minutes of validity.
+            let claims = serde_json::json!({
+                "iat": now - 60,
+                "exp": now + 9 * 60,
+                "iss": self.app_id,
+            });
+            let message = format!(
+                "{}.{}",
+                base64_url(br#"{"alg":"RS256","typ":"JWT"}"#),
+                base64_url(claims.to_string().as_bytes())
+            );
+            let signature = Signer::new(MessageDigest::sha256(), &self.key)
+                .and_then(|mut signer| {
+                    signer.update(message.as_bytes())?;
+                    signer.sign_to_vec()
+                })
+                .map_err(|err| Error::InvalidCredentials(err.to_string()))?;
+            Ok(format!("{}.{}", message, base64_url(&signature)))
+        }
+
+        async fn fetch(&self) -> Result<(String, SystemTime)> {
+            #[derive(Deserialize)]
+            struct AccessToken {
+                token: String,
+                expires_at: String,
+            }
+
+            let jwt = self.jwt(SystemTime::now())?;
+            let request = self
+                .client
+                .post(format!(
+                    "{}/app/installations/{}/access_tokens",
+                    self.api_url, self.installation_id
+                ))
+                .bearer_auth(&jwt)
+                .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+                .header("x-github-api-version", "2022-11-28")
+                .build()?;
+            let url = request.url().clone();
+            let response = self.client.execute(request).await?;
+            let response = error_for_response(response, url, None, SystemTime::now())
+                .await
+                .map_err(|err| err.redacted(&jwt))?;
+            let AccessToken { token, expires_at } = response.json().await?;
+            let expires_at = parse_timestamp(&expires_at)
+                .unwrap_or_else(|| SystemTime::now() + Duration::from_secs(60 * 60));
+            Ok((token, expires_at))
+        }
+    }
+
+    impl TokenSource for InstallationToken {
+        fn token(&self) -> BoxFuture<'_, Result<String>> {
+            Box::pin(async move {
+                let mut cached = self.cached.lock().await;
+                if let Some((token, expires_at)) = &*cached {
+                    if SystemTime::now() + REFRESH_MARGIN < *expires_at {
+                        return Ok(token.clone());
+                    }
+                }
+                let (token, expires_at) = self.fetch().await?;
+                *cached = Some((token.clone(), expires_at));
+                Ok(token)
+            })
+        }
+    }
+
+    /// Encodes bytes as unpadded URL-safe base64, as used by JWTs.
+    fn base64_url(data: &[u8]) -> String {
+        openssl::base64::encode_block(data)
+            .trim_end_matches('=')
+            .replace('+', "-")
+            .replace('/', "_")
+    }
+}
@@ -0,0 +1,193 @@
+//! In-memory cache backend for testing.
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use reqwest::{StatusCode, Url};
+
+use crate::{
+    backend::{BoxFuture, CacheBackend},
+    key, CacheHit, Error, MatchKind, Result, ServiceError, StatusError,
+};
+
+/// A [`CacheBackend`] keeping entries in memory, for testing code using the cache.
+///
+/// This follows the matching rules of the cache service: Entries are only found using the same
+/// key space. Scopes are searched in order, starting with the scope entries are stored in,
+/// followed by the fallback scopes. Within a scope, each key is tried in order, first as an
+/// exact match and then as a prefix, where the most recently stored matching entry wins.
+/// Storing an entry under an existing key of the same scope and key space fails with
+/// [`Error::Conflict`], just like the service refuses to overwrite entries.
+///
+/// By default, entries are stored in the scope `refs/heads/main` and no fallback scopes are
+/// searched.
+pub struct InMemoryCache {
+    scope: String,
+    fallback_scopes: Vec<String>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: Vec<Entry>,
+    next_seq: u64,
+}
+
+struct Entry {
+    key_space: String,
+    key: String,
+    scope: String,
+    data: Bytes,
+    seq: u64,
+}
+
+impl Default for InMemoryCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            scope: "refs/heads/main".to_owned(),
+            fallback_scopes: vec![],
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Sets the scope in which entries are stored and which is searched first.
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = scope.into();
+        self
+    }
+
+    /// Adds a scope that is searched after the own scope, like the base or default branch.
+    pub fn with_fallback_scope(mut self, scope: impl Into<String>) -> Self {
+        self.fallback_scopes.push(scope.into());
+        self
+    }
+
+    /// Stores an entry in the given scope, e.g. to prepare entries of other branches.
+    pub fn insert(&self, scope: &str, key_space: &str, key: &str, data: Bytes) -> Result<()> {
+        key::validate_key(key)?;
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .entries
+            .iter()
+            .any(|entry| entry.key_space == key_space && entry.key == key && entry.scope == scope)
+        {
+            return Err(Error::Conflict(Box::new(StatusError {
+                status: StatusCode::CONFLICT,
+                url: Url::parse("memory:/caches").unwrap(),
+                body: String::new(),
+                service_error: Some(ServiceError {
+                    type_key: "ArtifactCacheItemAlreadyExistsException".to_owned(),
+                    message: format!("Cache already exists. Scope: {}, Key: {}", scope, key),
+                    type_name: None,
+                    error_code: None,
+                    event_id: None,
+                }),
+            })));
+        }
+
+        let seq = entries.next_seq;
+        entries.next_seq += 1;
+        entries.entries.push(Entry {
+            key_space: key_space.to_owned(),
+            key: key.to_owned(),
+            scope: scope.to_owned(),
+            data,
+            seq,
+        });
+        Ok(())
+    }
+
+    /// Returns the number of stored entries across all scopes and key spaces.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().entries.len()
+    }
+
+    /// Returns whether no entries are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the scope in which entries are stored.
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub(crate) fn find(&self, key_space: &str, keys: &[&str]) -> Result<Option<(CacheHit, Bytes)>> {
+        for key in keys {
+            key::validate_key(key)?;
+        }
+
+        let entries = self.entries.lock().unwrap();
+
+        for scope in std::iter::once(&self.scope).chain(&self.fallback_scopes) {
+            let candidates: Vec<&Entry> = entries
+                .entries
+                .iter()
+                .filter(|entry| entry.key_space == key_space && entry.scope == *scope)
+                .collect();
+
+            for (index, key) in keys.iter().enumerate() {
+                let found = candidates
+                    .iter()
+                    .find(|entry| entry.key == *key)
+                    .or_else(|| {
+                        candidates
+                            .iter()
+                            .filter(|entry| entry.key.starts_with(key))
+                            .max_by_key(|entry| entry.seq)
+                    });
+                if let Some(entry) = found {
+                    let match_kind = if index == 0 && entry.key == *key {
+                        MatchKind::Exact
+                    } else {
+                        MatchKind::Prefix
+                    };
+                    let hit = CacheHit {
+                        key: entry.key.clone(),
+                        scope: entry.scope.clone(),
+                        match_kind,
+                    };
+                    return Ok(Some((hit, entry.data.clone())));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl CacheBackend for InMemoryCache {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        let result = self.find(key_space, key_prefixes);
+        Box::pin(async move { Ok(result?.map(|(hit, _)| hit)) })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        let result = self.find(key_space, keys);
+        Box::pin(async move { result })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        let result = self.insert(&self.scope, key_space, key, data);
+        Box::pin(async move { result })
+    }
+}
@@ -0,0 +1,148 @@
+//! Passing structured values between jobs, without uploading artifacts.
+//!
+//! Each topic is a sequence of versions stored as entries of a dedicated key space. Publishing
+//! stores the next version, and as entries can't be overwritten, concurrent publishers end up
+//! with distinct versions. Values are visible according to the cache service's scoping, so jobs
+//! see values published by runs for the same branch and for the default branch.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{digest, CacheBackend, CacheHit, Error, Result};
+
+/// How often publishing retries with a later version when another job published first.
+const MAX_PUBLISH_ATTEMPTS: usize = 8;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    published_at: u64,
+    value: T,
+}
+
+/// A value retrieved from a topic.
+#[derive(Debug)]
+pub struct Message<T> {
+    /// The version of the value within its topic, increasing with every publish.
+    pub version: u64,
+    /// When the value was published, according to the publisher's clock.
+    pub published_at: SystemTime,
+    /// The entry the value was retrieved from.
+    pub hit: CacheHit,
+    /// The published value.
+    pub value: T,
+}
+
+/// Topics that jobs publish values to and fetch values from.
+pub struct Messages<B> {
+    backend: B,
+    namespace: String,
+}
+
+impl<B: CacheBackend> Messages<B> {
+    /// Creates topics within `namespace`, e.g. the workflow's name.
+    pub fn new(backend: B, namespace: impl Into<String>) -> Self {
+        Self {
+            backend,
+            namespace: namespace.into(),
+        }
+    }
+
+    /// Returns the underlying backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn key_space(&self, topic: &str) -> String {
+        digest::sha256_hex(format!("messages\n{}\n{}", self.namespace, topic).as_bytes())
+    }
+
+    fn key(version: u64) -> String {
+        // Zero-padded, so no version's key is a prefix of another's.
+        format!("v{:020}", version)
+    }
+
+    /// Publishes `value` to `topic`, returning its version.
+    pub async fn publish<T: Serialize>(&self, topic: &str, value: &T) -> Result<u64> {
+        let key_space = self.key_space(topic);
+        let published_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let data = serde_json::to_vec(&Envelope {
+            published_at,
+            value,
+        })
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        let mut version = self.latest_version(&key_space).await?.map_or(0, |v| v + 1);
+        let mut attempts = 0;
+        loop {
+            match self
+                .backend
+                .put_bytes(&key_space, &Self::key(version), data.clone().into())
+                .await
+            {
+                Ok(()) => return Ok(version),
+                Err(Error::Conflict(_)) if attempts + 1 < MAX_PUBLISH_ATTEMPTS => {
+                    attempts += 1;
+                    version = self
+                        .latest_version(&key_space)
+                        .await?
+                        .map_or(version, |v| v.max(version))
+                        + 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn latest_version(&self, key_space: &str) -> Result<Option<u64>> {
+        Ok(self
+            .backend
+            .lookup(key_space, &["v"])
+            .await?
+            .and_then(|hit| hit.key.strip_prefix('v')?.parse().ok()))
+    }
+
+    /// Fetches the most recently published value of `topic`.
+    pub async fn fetch_latest<T: DeserializeOwned>(
+        &self,
+        topic: &str,
+    ) -> Result<Option<Message<T>>> {
+        self.fetch_key(topic, "v").await
+    }
+
+    /// Fetches the value of `topic` with the given version.
+    pub async fn fetch<T: DeserializeOwned>(
+        &self,
+        topic: &str,
+        version: u64,
+    ) -> Result<Option<Message<T>>> {
+        let key = Self::key(version);
+        Ok(self
+            .fetch_key(topic, &key)
+            .await?
+            .filter(|message| message.hit.key == key))
+    }
+
+    async fn fetch_key<T: DeserializeOwned>(
+        &self,
+        topic: &str,
+        key: &str,
+    ) -> Result<Option<Message<T>>> {
+        let key_space = self.key_space(topic);
+        let Some((hit, data)) = self.backend.get_bytes(&key_space, &[key]).await? else {
+            return Ok(None);
+        };
+        let Some(version) = hit.key.strip_prefix('v').and_then(|v| v.parse().ok()) else {
+            return Ok(None);
+        };
+        let envelope: Envelope<T> = serde_json::from_slice(&data)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        Ok(Some(Message {
+            version,
+            published_at: UNIX_EPOCH + Duration::from_secs(envelope.published_at),
+            hit,
+            value: envelope.value,
+        }))
+    }
+}
@@ -0,0 +1,121 @@
+//! Small documents describing entries, retrieved without downloading the entries.
+//!
+//! Entries are stored unchanged, so they can still be restored without this module. Each
+//! entry's metadata is stored as a companion entry under the same key in a derived key space.
+use std::path::Path;
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{digest, CacheBackend, CacheHit, Result};
+
+/// Maximal size of the serialized metadata of an entry.
+pub const MAX_METADATA_SIZE: usize = 64 << 10;
+
+/// Stores entries along with JSON metadata, e.g. provenance or the versions of the tools that
+/// produced them.
+///
+/// Metadata is stored after its entry, so an entry whose metadata failed to store has none.
+pub struct MetadataStore<B> {
+    backend: B,
+}
+
+impl<B: CacheBackend> MetadataStore<B> {
+    /// Creates a store for entries of `backend`.
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    /// Returns the underlying backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn key_space(key_space: &str) -> String {
+        digest::sha256_hex(format!("metadata\n{}", key_space).as_bytes())
+    }
+
+    fn encode<T: Serialize>(metadata: &T) -> Result<Bytes> {
+        let data = serde_json::to_vec(metadata)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        if data.len() > MAX_METADATA_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "metadata of {} bytes exceeds the maximum of {} bytes",
+                    data.len(),
+                    MAX_METADATA_SIZE
+                ),
+            )
+            .into());
+        }
+        Ok(data.into())
+    }
+
+    /// Stores an entry and its metadata.
+    pub async fn put_bytes<T: Serialize>(
+        &self,
+        key_space: &str,
+        key: &str,
+        data: Bytes,
+        metadata: &T,
+    ) -> Result<()> {
+        let metadata = Self::encode(metadata)?;
+        self.backend.put_bytes(key_space, key, data).await?;
+        self.backend
+            .put_bytes(&Self::key_space(key_space), key, metadata)
+            .await
+    }
+
+    /// Stores the content of a file as an entry, along with its metadata.
+    pub async fn put_file<T: Serialize>(
+        &self,
+        key_space: &str,
+        key: &str,
+        path: &Path,
+        metadata: &T,
+    ) -> Result<()> {
+        let metadata = Self::encode(metadata)?;
+        self.backend.put_file(key_space, key, path).await?;
+        self.backend
+            .put_bytes(&Self::key_space(key_space), key, metadata)
+            .await
+    }
+
+    /// Looks up the entry a restore with the same keys would return, and retrieves its
+    /// metadata.
+    ///
+    /// The metadata is `None` for entries stored without any.
+    pub async fn metadata<T: DeserializeOwned>(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Option<T>)>> {
+        let Some(hit) = self.backend.lookup(key_space, keys).await? else {
+            return Ok(None);
+        };
+        let metadata = self.metadata_of(key_space, &hit.key).await?;
+        Ok(Some((hit, metadata)))
+    }
+
+    /// Retrieves the metadata of the entry with exactly the given key.
+    pub async fn metadata_of<T: DeserializeOwned>(
+        &self,
+        key_space: &str,
+        key: &str,
+    ) -> Result<Option<T>> {
+        let Some((hit, data)) = self
+            .backend
+            .get_bytes(&Self::key_space(key_space), &[key])
+            .await?
+        else {
+            return Ok(None);
+        };
+        if hit.key != key {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&data).map_err(|err| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+        })?))
+    }
+}
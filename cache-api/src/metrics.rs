@@ -0,0 +1,28 @@
+//! Metrics emitted by the client, available with the `metrics-recorder` feature.
+//!
+//! This is a custom hook, not an integration with the [`metrics`] facade: metrics are only
+//! reported to a [`Recorder`] set using [`Cache::with_metrics`][crate::Cache::with_metrics], so
+//! exporters of the facade see nothing by themselves. The recorder interface mirrors the
+//! facade's counters and histograms, so forwarding to it only takes calling `metrics::counter!`
+//! and `metrics::histogram!` with the given names and labels.
+//!
+//! The following metrics are reported:
+//!
+//! * `gha_cache_requests_total` - counter of HTTP requests, labeled with `method` and `status`
+//! * `gha_cache_request_duration_seconds` - histogram of HTTP request durations, labeled with
+//!   `method`
+//! * `gha_cache_retries_total` - counter of retried HTTP requests
+//! * `gha_cache_lookups_total` - counter of lookups, labeled with `result` being `hit` or `miss`
+//! * `gha_cache_uploaded_bytes_total` - counter of uploaded bytes
+//! * `gha_cache_downloaded_bytes_total` - counter of downloaded bytes
+//!
+//! [`metrics`]: https://docs.rs/metrics
+
+/// Receives metrics emitted by the client.
+pub trait Recorder: Send + Sync {
+    /// Increments the counter `name` with the given labels by `value`.
+    fn increment_counter(&self, name: &'static str, value: u64, labels: &[(&'static str, &str)]);
+
+    /// Records `value` in the histogram `name` with the given labels.
+    fn record_histogram(&self, name: &'static str, value: f64, labels: &[(&'static str, &str)]);
+}
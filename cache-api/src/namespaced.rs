@@ -0,0 +1,198 @@
+//! Key namespacing for sharing a client between multiple users.
+use std::path::Path;
+
+use bytes::Bytes;
+use futures_core::TryStream;
+
+use crate::{Cache, CacheHit, DedupOutcome, ReservedCache, Result, TransferStats};
+
+/// Adapter that prepends a fixed prefix to all keys and key spaces.
+///
+/// Returned by [`Cache::namespaced`]. Keys reported back in [`CacheHit`]s and
+/// [`DedupOutcome`]s have the prefix removed again, so code using this adapter never sees
+/// the namespace.
+#[derive(Clone)]
+pub struct NamespacedCache<'a> {
+    cache: &'a Cache,
+    namespace: String,
+}
+
+impl Cache {
+    /// Returns an adapter that places all keys and key spaces in the given namespace.
+    ///
+    /// The namespace is prepended verbatim, so it should end with a separator, e.g. `"my-tool-"`.
+    pub fn namespaced(&self, namespace: impl Into<String>) -> NamespacedCache<'_> {
+        NamespacedCache {
+            cache: self,
+            namespace: namespace.into(),
+        }
+    }
+}
+
+impl<'a> NamespacedCache<'a> {
+    /// The namespace prepended to keys and key spaces.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns the underlying client.
+    pub fn inner(&self) -> &'a Cache {
+        self.cache
+    }
+
+    fn full(&self, key: &str) -> String {
+        format!("{}{}", self.namespace, key)
+    }
+
+    fn strip(&self, mut hit: CacheHit) -> CacheHit {
+        if let Some(key) = hit.key.strip_prefix(&self.namespace) {
+            hit.key = key.to_owned();
+        }
+        hit
+    }
+
+    /// Namespaced version of [`Cache::get_url`].
+    pub async fn get_url(
+        &self,
+        key_space: &str,
+        key_prefixes: &[&str],
+    ) -> Result<Option<(CacheHit, String)>> {
+        let prefixes: Vec<String> = key_prefixes.iter().map(|key| self.full(key)).collect();
+        let prefixes: Vec<&str> = prefixes.iter().map(|key| &**key).collect();
+        Ok(self
+            .cache
+            .get_url(&self.full(key_space), &prefixes)
+            .await?
+            .map(|(hit, location)| (self.strip(hit), location)))
+    }
+
+    /// Namespaced version of [`Cache::get_bytes`].
+    pub async fn get_bytes(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Bytes)>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.full(key)).collect();
+        let keys: Vec<&str> = keys.iter().map(|key| &**key).collect();
+        Ok(self
+            .cache
+            .get_bytes(&self.full(key_space), &keys)
+            .await?
+            .map(|(hit, data)| (self.strip(hit), data)))
+    }
+
+    /// Namespaced version of [`Cache::get_bytes_with_stats`].
+    pub async fn get_bytes_with_stats(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Bytes, TransferStats)>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.full(key)).collect();
+        let keys: Vec<&str> = keys.iter().map(|key| &**key).collect();
+        Ok(self
+            .cache
+            .get_bytes_with_stats(&self.full(key_space), &keys)
+            .await?
+            .map(|(hit, data, stats)| (self.strip(hit), data, stats)))
+    }
+
+    /// Namespaced version of [`Cache::get_file`].
+    pub async fn get_file(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        path: impl AsRef<Path>,
+    ) -> Result<Option<(CacheHit, TransferStats)>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.full(key)).collect();
+        let keys: Vec<&str> = keys.iter().map(|key| &**key).collect();
+        Ok(self
+            .cache
+            .get_file(&self.full(key_space), &keys, path)
+            .await?
+            .map(|(hit, stats)| (self.strip(hit), stats)))
+    }
+
+    /// Namespaced version of [`Cache::put_bytes`].
+    pub async fn put_bytes(
+        &self,
+        key_space: &str,
+        key: &str,
+        data: Bytes,
+    ) -> Result<TransferStats> {
+        self.cache
+            .put_bytes(&self.full(key_space), &self.full(key), data)
+            .await
+    }
+
+    /// Namespaced version of [`Cache::put_file`].
+    pub async fn put_file(
+        &self,
+        key_space: &str,
+        key: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<TransferStats> {
+        self.cache
+            .put_file(&self.full(key_space), &self.full(key), path)
+            .await
+    }
+
+    /// Namespaced version of [`Cache::put_stream`].
+    pub async fn put_stream<S>(
+        &self,
+        key_space: &str,
+        key: &str,
+        size: u64,
+        stream: S,
+    ) -> Result<TransferStats>
+    where
+        S: TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        self.cache
+            .put_stream(&self.full(key_space), &self.full(key), size, stream)
+            .await
+    }
+
+    /// Namespaced version of [`Cache::put_bytes_dedup`].
+    pub async fn put_bytes_dedup(
+        &self,
+        key_space: &str,
+        key_prefix: &str,
+        data: Bytes,
+    ) -> Result<DedupOutcome> {
+        let outcome = self
+            .cache
+            .put_bytes_dedup(&self.full(key_space), &self.full(key_prefix), data)
+            .await?;
+        Ok(self.strip_outcome(outcome))
+    }
+
+    /// Namespaced version of [`Cache::put_file_dedup`].
+    pub async fn put_file_dedup(
+        &self,
+        key_space: &str,
+        key_prefix: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<DedupOutcome> {
+        let outcome = self
+            .cache
+            .put_file_dedup(&self.full(key_space), &self.full(key_prefix), path)
+            .await?;
+        Ok(self.strip_outcome(outcome))
+    }
+
+    /// Namespaced version of [`Cache::reserve`].
+    pub async fn reserve(&self, key_space: &str, key: &str) -> Result<ReservedCache<'a>> {
+        self.cache
+            .reserve(&self.full(key_space), &self.full(key))
+            .await
+    }
+
+    fn strip_outcome(&self, mut outcome: DedupOutcome) -> DedupOutcome {
+        if let Some(key) = outcome.key.strip_prefix(&self.namespace) {
+            outcome.key = key.to_owned();
+        }
+        outcome
+    }
+}
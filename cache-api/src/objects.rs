@@ -0,0 +1,223 @@
+//! Content-addressed storage of many small objects, as used by compiler caches like sccache.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::{OnceCell, Semaphore};
+
+use crate::{key, CacheBackend, Error, Result};
+
+/// Counters of the requests an [`ObjectStore`] made and avoided.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObjectStats {
+    /// Objects requested with [`ObjectStore::get`].
+    pub gets: u64,
+    /// Requested objects that were found.
+    pub hits: u64,
+    /// Gets answered without a request, by joining a concurrent request for the same object or
+    /// from an earlier miss.
+    pub coalesced: u64,
+    /// Objects stored with [`ObjectStore::put`].
+    pub puts: u64,
+    /// Puts skipped because the object was already known to exist.
+    pub skipped_puts: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    gets: AtomicU64,
+    hits: AtomicU64,
+    coalesced: AtomicU64,
+    puts: AtomicU64,
+    skipped_puts: AtomicU64,
+}
+
+#[derive(Default)]
+struct Known {
+    present: HashSet<String>,
+    missing: HashSet<String>,
+}
+
+/// Stores objects under their content's key, e.g. a hash chosen by the caller.
+///
+/// As every compilation performs a lookup and as entries can't be overwritten, this keeps
+/// request volume low:
+///
+/// * Concurrent gets of the same object share a single request.
+/// * Objects found missing are remembered until they are put, so a repeated get doesn't make
+///   another request. Entries stored concurrently by other jobs are thus only seen by new
+///   stores.
+/// * Puts of objects known to exist are skipped, as are puts of an object that another job
+///   stored first.
+/// * At most a fixed number of requests is in flight, see
+///   [`with_max_concurrency`][Self::with_max_concurrency].
+pub struct ObjectStore<B> {
+    backend: B,
+    key_space: String,
+    prefix: String,
+    negative_caching: bool,
+    permits: Semaphore,
+    known: Mutex<Known>,
+    pending: Mutex<HashMap<String, Arc<OnceCell<Option<Bytes>>>>>,
+    counters: Counters,
+}
+
+impl<B: CacheBackend> ObjectStore<B> {
+    /// Creates a store keeping objects in the given key space, with keys prefixed by `prefix`.
+    pub fn new(backend: B, key_space: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            backend,
+            key_space: key_space.into(),
+            prefix: prefix.into(),
+            negative_caching: true,
+            permits: Semaphore::new(16),
+            known: Mutex::default(),
+            pending: Mutex::default(),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Sets the maximal number of requests in flight, 16 by default.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.permits = Semaphore::new(max_concurrency.max(1));
+        self
+    }
+
+    /// Sets whether objects found missing are remembered, enabled by default.
+    pub fn with_negative_caching(mut self, negative_caching: bool) -> Self {
+        self.negative_caching = negative_caching;
+        self
+    }
+
+    /// Returns the underlying backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the request counters.
+    pub fn stats(&self) -> ObjectStats {
+        let Counters {
+            gets,
+            hits,
+            coalesced,
+            puts,
+            skipped_puts,
+        } = &self.counters;
+        ObjectStats {
+            gets: gets.load(Ordering::Relaxed),
+            hits: hits.load(Ordering::Relaxed),
+            coalesced: coalesced.load(Ordering::Relaxed),
+            puts: puts.load(Ordering::Relaxed),
+            skipped_puts: skipped_puts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn full_key(&self, key: &str) -> Result<String> {
+        let full = format!("{}{}", self.prefix, key);
+        key::validate_key(&full)?;
+        Ok(full)
+    }
+
+    /// Returns the object stored under `key`.
+    ///
+    /// If the request for an object fails, concurrent gets waiting for it make their own request.
+    pub async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        let full = self.full_key(key)?;
+        self.counters.gets.fetch_add(1, Ordering::Relaxed);
+        if self.known.lock().unwrap().missing.contains(&full) {
+            self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+
+        let cell = self
+            .pending
+            .lock()
+            .unwrap()
+            .entry(full.clone())
+            .or_default()
+            .clone();
+        let mut requested = false;
+        let result = cell
+            .get_or_try_init(|| {
+                requested = true;
+                self.fetch(&full)
+            })
+            .await
+            .cloned();
+        if requested {
+            self.pending.lock().unwrap().remove(&full);
+        } else if result.is_ok() {
+            self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let data = result?;
+        if data.is_some() {
+            self.counters.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(data)
+    }
+
+    async fn fetch(&self, full: &str) -> Result<Option<Bytes>> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        // Lookups match key prefixes, only an entry with exactly this key is the object.
+        let data = match self.backend.get_bytes(&self.key_space, &[full]).await? {
+            Some((hit, data)) if hit.key == full => Some(data),
+            _ => None,
+        };
+        let mut known = self.known.lock().unwrap();
+        if data.is_some() {
+            known.present.insert(full.to_owned());
+        } else if self.negative_caching {
+            known.missing.insert(full.to_owned());
+        }
+        Ok(data)
+    }
+
+    /// Stores `data` as the object `key`.
+    ///
+    /// Objects are expected to be content-addressed, so if an entry with this key already exists,
+    /// it is assumed to hold the same data and the put succeeds without storing anything.
+    pub async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        let full = self.full_key(key)?;
+        {
+            let mut known = self.known.lock().unwrap();
+            if !known.present.insert(full.clone()) {
+                self.counters.skipped_puts.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            known.missing.remove(&full);
+        }
+
+        let result = {
+            let _permit = self
+                .permits
+                .acquire()
+                .await
+                .expect("semaphore is never closed");
+            self.backend.put_bytes(&self.key_space, &full, data).await
+        };
+        match result {
+            Ok(()) => {
+                self.counters.puts.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(Error::Conflict(_)) => {
+                self.counters.skipped_puts.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(err) => {
+                self.known.lock().unwrap().present.remove(&full);
+                Err(err)
+            }
+        }
+    }
+}
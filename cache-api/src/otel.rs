@@ -0,0 +1,11 @@
+//! OpenTelemetry span attributes, available with the `otel` feature.
+//!
+//! With this feature, request spans additionally carry the attributes of the OpenTelemetry
+//! [semantic conventions for HTTP clients], like `http.request.method`, `url.full` and
+//! `http.response.status_code`, as well as `peer.service` and the `otel.*` fields understood by
+//! `tracing-opentelemetry`. URLs are redacted unless raw diagnostics are enabled.
+//!
+//! [semantic conventions for HTTP clients]: https://opentelemetry.io/docs/specs/semconv/http/http-spans/
+
+/// Value of the `peer.service` attribute.
+pub(crate) const PEER_SERVICE: &str = "github-actions-cache";
@@ -0,0 +1,32 @@
+//! Checks for relative paths read from untrusted sources, like archives and manifests.
+use std::path::{Component, Path, PathBuf};
+
+/// Returns `path` without `.` components, or `None` if it is empty, absolute or contains `..`,
+/// so that joining it to a directory stays within that directory.
+pub(crate) fn safe_relative_path(path: &Path) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!safe.as_os_str().is_empty()).then_some(safe)
+}
+
+/// Returns the first of the parent directories of `relative` within `root` that is a symlink.
+///
+/// Writing to a path below a symlink would write wherever the symlink points to.
+pub(crate) fn symlinked_parent(root: &Path, relative: &Path) -> Option<PathBuf> {
+    relative
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .map(|ancestor| root.join(ancestor))
+        .find(|parent| {
+            parent
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.is_symlink())
+        })
+}
@@ -0,0 +1,133 @@
+//! Restoring entries in the background, while a job performs other steps.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+use crate::{digest, CacheBackend, CacheHit, Result};
+
+/// An entry to restore, given like the arguments of [`CacheBackend::get_bytes`].
+#[derive(Clone, Debug)]
+pub struct PrefetchQuery {
+    key_space: String,
+    keys: Vec<String>,
+}
+
+impl PrefetchQuery {
+    /// Creates a query for the first entry matching `keys`.
+    ///
+    /// The query is identified by its first key when [waiting][Prefetcher::wait_for] for it.
+    pub fn new(
+        key_space: impl Into<String>,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            key_space: key_space.into(),
+            keys: keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// An entry restored by a [`Prefetcher`].
+#[derive(Debug)]
+pub struct Prefetched {
+    /// The entry that was found.
+    pub hit: CacheHit,
+    /// The file in the staging directory holding the entry's content.
+    pub path: PathBuf,
+}
+
+/// Restores entries into a staging directory in the background.
+///
+/// Start prefetching at the beginning of a job, and wait for entries when they are needed, so
+/// downloads overlap with earlier steps like checking out or installing tools. Prefetches that
+/// were not waited for are aborted when the prefetcher is dropped.
+pub struct Prefetcher<B> {
+    backend: Arc<B>,
+    staging_dir: PathBuf,
+    permits: Arc<Semaphore>,
+    pending: Mutex<HashMap<String, JoinHandle<Result<Option<Prefetched>>>>>,
+}
+
+impl<B: CacheBackend + 'static> Prefetcher<B> {
+    /// Creates a prefetcher staging entries in a directory within `RUNNER_TEMP`, or the system's
+    /// temporary directory.
+    pub fn new(backend: impl Into<Arc<B>>) -> Self {
+        let staging_dir = std::env::var_os("RUNNER_TEMP")
+            .map_or_else(std::env::temp_dir, PathBuf::from)
+            .join(format!("prefetch-{}", std::process::id()));
+        Self {
+            backend: backend.into(),
+            staging_dir,
+            permits: Arc::new(Semaphore::new(4)),
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Sets the directory that entries are restored into.
+    pub fn with_staging_dir(mut self, staging_dir: impl Into<PathBuf>) -> Self {
+        self.staging_dir = staging_dir.into();
+        self
+    }
+
+    /// Sets the maximal number of entries restored concurrently, 4 by default.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+        self
+    }
+
+    /// Starts restoring the given entries in the background.
+    ///
+    /// Must be called within a tokio runtime. A query whose first key is already being
+    /// prefetched is ignored.
+    pub fn prefetch(&self, queries: impl IntoIterator<Item = PrefetchQuery>) {
+        let mut pending = self.pending.lock().unwrap();
+        for query in queries {
+            let Some(id) = query.keys.first().cloned() else {
+                continue;
+            };
+            if pending.contains_key(&id) {
+                continue;
+            }
+            let backend = self.backend.clone();
+            let permits = self.permits.clone();
+            let path = self.staging_dir.join(digest::sha256_hex(
+                format!("{}\n{}", query.key_space, id).as_bytes(),
+            ));
+            let staging_dir = self.staging_dir.clone();
+            let task = tokio::spawn(async move {
+                let _permit = permits.acquire().await.expect("semaphore is never closed");
+                let keys: Vec<&str> = query.keys.iter().map(String::as_str).collect();
+                let Some((hit, data)) = backend.get_bytes(&query.key_space, &keys).await? else {
+                    return Ok(None);
+                };
+                tokio::fs::create_dir_all(&staging_dir).await?;
+                tokio::fs::write(&path, &data).await?;
+                Ok(Some(Prefetched { hit, path }))
+            });
+            pending.insert(id, task);
+        }
+    }
+
+    /// Waits for the prefetch of the query with the given first key.
+    ///
+    /// Returns `None` if no matching entry was found or no such query was prefetched. Each
+    /// prefetched entry is only returned once.
+    pub async fn wait_for(&self, key: &str) -> Result<Option<Prefetched>> {
+        let Some(task) = self.pending.lock().unwrap().remove(key) else {
+            return Ok(None);
+        };
+        task.await.map_err(std::io::Error::other)?
+    }
+}
+
+impl<B> Drop for Prefetcher<B> {
+    fn drop(&mut self) {
+        for task in self.pending.get_mut().unwrap().values() {
+            task.abort();
+        }
+    }
+}
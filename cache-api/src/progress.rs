@@ -0,0 +1,31 @@
+//! Progress reporting for uploads and downloads.
+
+/// Direction of a transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Storing an entry.
+    Upload,
+    /// Restoring an entry.
+    Download,
+}
+
+/// Receives progress of uploads and downloads.
+///
+/// Set using [`Cache::with_progress`][crate::Cache::with_progress]. The same interface is used for
+/// both directions, so a single implementation, e.g. driving a progress bar, covers all
+/// transfers. The `put_*` and `get_bytes*` methods call [`start`][Self::start] and
+/// [`finish`][Self::finish] around each transfer. Uploads using a [`ReservedCache`] directly only
+/// report [`advance`][Self::advance]. Artifact uploads report progress too, see
+/// [`ArtifactClient::with_progress`][crate::artifacts::ArtifactClient::with_progress].
+///
+/// [`ReservedCache`]: crate::ReservedCache
+pub trait ProgressReporter: Send + Sync {
+    /// Called when a transfer of the entry `key` starts, with its size if known.
+    fn start(&self, _direction: Direction, _key: &str, _total: Option<u64>) {}
+
+    /// Called when `bytes` more bytes were transferred.
+    fn advance(&self, direction: Direction, bytes: u64);
+
+    /// Called when a transfer ends, successfully or not.
+    fn finish(&self, _direction: Direction) {}
+}
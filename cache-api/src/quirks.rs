@@ -0,0 +1,244 @@
+//! Differences of third-party implementations of the cache API.
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+
+use crate::Error;
+
+/// Deviations from GitHub's cache service that a [`Cache`][crate::Cache] should tolerate.
+///
+/// Hosted runner providers like BuildJet or Blacksmith serve their own cache behind the
+/// `ACTIONS_CACHE_URL` of their runners. These implement the same API, but may answer some
+/// requests differently, e.g. report a missing entry with `404 Not Found` instead of
+/// `204 No Content`, reject an existing key with a status other than `409 Conflict`, require
+/// additional headers or limit the size of upload requests. Set using
+/// [`Cache::with_quirks`][crate::Cache::with_quirks].
+#[derive(Clone, Debug, Default)]
+pub struct ProviderQuirks {
+    miss_statuses: Vec<StatusCode>,
+    conflict_statuses: Vec<StatusCode>,
+    accept: Option<HeaderValue>,
+    headers: HeaderMap,
+    max_chunk_size: Option<u64>,
+    abort_endpoint: bool,
+}
+
+impl ProviderQuirks {
+    /// GitHub's own cache service, without any deviations. This is the default.
+    pub fn github() -> Self {
+        Self::default()
+    }
+
+    /// Tolerates the common deviations of third-party implementations.
+    ///
+    /// Lookups treat `404 Not Found` as a miss, reservations treat `412 Precondition Failed` as
+    /// an existing key, API requests accept plain JSON and uploads are split into requests of at
+    /// most 32 MiB, like the official client does.
+    pub fn compatible() -> Self {
+        Self::default()
+            .with_miss_status(StatusCode::NOT_FOUND)
+            .with_conflict_status(StatusCode::PRECONDITION_FAILED)
+            .with_accept(HeaderValue::from_static("application/json"))
+            .with_max_chunk_size(32 << 20)
+    }
+
+    /// Treats the given status of a lookup as a miss.
+    pub fn with_miss_status(mut self, status: StatusCode) -> Self {
+        self.miss_statuses.push(status);
+        self
+    }
+
+    /// Treats the given status of a reservation as an existing key, i.e. [`Error::Conflict`].
+    pub fn with_conflict_status(mut self, status: StatusCode) -> Self {
+        self.conflict_statuses.push(status);
+        self
+    }
+
+    /// Sets the `Accept` header of API requests, instead of the versioned JSON type GitHub
+    /// expects.
+    pub fn with_accept(mut self, accept: HeaderValue) -> Self {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Adds a header sent with every API request.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Splits uploads into requests of at most `max_chunk_size` bytes.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: u64) -> Self {
+        self.max_chunk_size = Some(max_chunk_size.max(1));
+        self
+    }
+
+    /// Releases the reservations of failed uploads using `DELETE .../caches/{id}`.
+    ///
+    /// GitHub's service has no such endpoint, so by default reservations are left to expire.
+    /// Only enable this for services that document it.
+    pub fn with_abort_endpoint(mut self) -> Self {
+        self.abort_endpoint = true;
+        self
+    }
+
+    pub(crate) fn accept(&self) -> Option<&HeaderValue> {
+        self.accept.as_ref()
+    }
+
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub(crate) fn max_chunk_size(&self) -> Option<u64> {
+        self.max_chunk_size
+    }
+
+    pub(crate) fn has_abort_endpoint(&self) -> bool {
+        self.abort_endpoint
+    }
+
+    /// Returns whether a failed lookup means that no entry matched.
+    pub(crate) fn is_miss(&self, error: &Error) -> bool {
+        error
+            .status()
+            .is_some_and(|status| self.miss_statuses.contains(&status))
+    }
+
+    /// Reports a failed reservation of an existing key as [`Error::Conflict`].
+    pub(crate) fn reserve_error(&self, error: Error) -> Error {
+        match error.status() {
+            Some(status) if self.conflict_statuses.contains(&status) => {
+                match error.into_status_error() {
+                    Ok(err) => Error::Conflict(err),
+                    Err(error) => error,
+                }
+            }
+            _ => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::testing::{MockResponse, MockServer};
+
+    fn client(server: &MockServer, quirks: ProviderQuirks) -> crate::Cache {
+        server
+            .client("quirks-test")
+            .unwrap()
+            .without_retries()
+            .with_quirks(quirks)
+    }
+
+    #[tokio::test]
+    async fn github_reports_missing_entries_with_not_found_as_errors() {
+        let server = MockServer::start().await.unwrap();
+        let cache = client(&server, ProviderQuirks::github());
+        server.push_response(MockResponse::status(404));
+        let result = cache.get_url("space", &["key"]).await;
+        assert!(matches!(result, Err(Error::NotFound(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn compatible_treats_not_found_lookups_as_misses() {
+        let server = MockServer::start().await.unwrap();
+        let cache = client(&server, ProviderQuirks::compatible());
+        server.push_response(MockResponse::status(404));
+        assert!(cache.get_url("space", &["key"]).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn github_reports_precondition_failed_reservations_as_errors() {
+        let server = MockServer::start().await.unwrap();
+        let cache = client(&server, ProviderQuirks::github());
+        server.push_response(MockResponse::status(412));
+        let result = cache
+            .put_bytes("space", "key", Bytes::from_static(b"data"))
+            .await;
+        assert!(matches!(result, Err(Error::Status(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn compatible_treats_precondition_failed_reservations_as_conflicts() {
+        let server = MockServer::start().await.unwrap();
+        let cache = client(&server, ProviderQuirks::compatible());
+        server.push_response(MockResponse::status(412));
+        let result = cache
+            .put_bytes("space", "key", Bytes::from_static(b"data"))
+            .await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn sends_accept_and_extra_headers() {
+        let server = MockServer::start().await.unwrap();
+        let cache = client(&server, ProviderQuirks::github());
+        cache.get_url("space", &["key"]).await.unwrap();
+        let quirks = ProviderQuirks::compatible().with_header(
+            HeaderName::from_static("x-provider"),
+            HeaderValue::from_static("test"),
+        );
+        let cache = client(&server, quirks);
+        cache.get_url("space", &["key"]).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].header("accept"),
+            Some("application/json;api-version=6.0-preview.1")
+        );
+        assert_eq!(requests[0].header("x-provider"), None);
+        assert_eq!(requests[1].header("accept"), Some("application/json"));
+        assert_eq!(requests[1].header("x-provider"), Some("test"));
+    }
+
+    #[tokio::test]
+    async fn splits_uploads_into_chunks() {
+        assert_eq!(
+            ProviderQuirks::compatible().max_chunk_size(),
+            Some(32 << 20)
+        );
+        assert_eq!(ProviderQuirks::github().max_chunk_size(), None);
+
+        let server = MockServer::start().await.unwrap();
+        let quirks = ProviderQuirks::compatible().with_max_chunk_size(1000);
+        let cache = client(&server, quirks);
+        let data: Bytes = (0..2500u32).map(|i| i as u8).collect();
+        cache.put_bytes("space", "key", data.clone()).await.unwrap();
+
+        let ranges: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == "PATCH")
+            .map(|request| request.header("content-range").unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            ranges,
+            ["bytes 0-999/*", "bytes 1000-1999/*", "bytes 2000-2499/*"]
+        );
+        let (_, restored) = cache.get_bytes("space", &["key"]).await.unwrap().unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[tokio::test]
+    async fn only_aborts_reservations_with_abort_endpoint() {
+        let server = MockServer::start().await.unwrap();
+        for (key, quirks) in [
+            ("github", ProviderQuirks::github()),
+            ("abort", ProviderQuirks::compatible().with_abort_endpoint()),
+        ] {
+            let cache = client(&server, quirks);
+            cache.reserve("space", key).await.unwrap().abort().await;
+        }
+        let methods: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.method)
+            .collect();
+        assert_eq!(methods, ["POST", "POST", "DELETE"]);
+    }
+}
@@ -0,0 +1,49 @@
+//! Rate-limit state reported by the server.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderMap;
+
+/// Rate-limit state as reported by the `x-ratelimit-*` response headers.
+///
+/// Every field is optional, as servers may report any subset of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RateLimitStatus {
+    /// Maximal number of requests in the current window.
+    pub limit: Option<u64>,
+    /// Remaining number of requests in the current window.
+    pub remaining: Option<u64>,
+    /// Number of requests made in the current window.
+    pub used: Option<u64>,
+    /// Time at which the current window ends.
+    pub reset: Option<SystemTime>,
+    /// The rate-limited resource the values apply to.
+    pub resource: Option<String>,
+}
+
+impl RateLimitStatus {
+    /// Parses the rate-limit headers of a response, returning `None` if there are none.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+        let number = |name: &str| get(name)?.parse::<u64>().ok();
+
+        let status = Self {
+            limit: number("x-ratelimit-limit"),
+            remaining: number("x-ratelimit-remaining"),
+            used: number("x-ratelimit-used"),
+            reset: number("x-ratelimit-reset").map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            resource: get("x-ratelimit-resource").map(str::to_owned),
+        };
+
+        if status == Self::default() {
+            None
+        } else {
+            Some(status)
+        }
+    }
+
+    /// Returns whether no requests remain in the current window.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
@@ -0,0 +1,115 @@
+//! Redaction of sensitive values in diagnostics.
+//!
+//! Blob storage URLs returned by the cache service carry SAS signatures granting access to the
+//! entry, and requests carry the runtime token. Neither should end up in CI logs, so errors and
+//! log output pass through these helpers unless raw diagnostics are enabled using
+//! [`Cache::with_raw_diagnostics`][crate::Cache::with_raw_diagnostics].
+use std::fmt;
+
+use reqwest::{header::HeaderMap, Url};
+
+use crate::Error;
+
+/// Replacement for redacted values.
+pub(crate) const REDACTED: &str = "REDACTED";
+
+/// Query parameters holding signatures or credentials.
+const SENSITIVE_PARAMS: &[&str] = &[
+    "sig",
+    "signature",
+    "token",
+    "access_token",
+    "code",
+    "x-amz-signature",
+    "x-amz-security-token",
+    "x-goog-signature",
+];
+
+/// Headers holding credentials.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-ms-copy-source-authorization",
+];
+
+/// Returns the URL with credentials and signature query parameters replaced.
+pub(crate) fn redact_url(url: &Url) -> Url {
+    let mut redacted = url.clone();
+    if redacted.password().is_some() {
+        let _ = redacted.set_password(Some(REDACTED));
+    }
+    if url.query().is_some() {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                if is_sensitive_param(&name) {
+                    (name.into_owned(), REDACTED.to_owned())
+                } else {
+                    (name.into_owned(), value.into_owned())
+                }
+            })
+            .collect();
+        redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    redacted
+}
+
+fn is_sensitive_param(name: &str) -> bool {
+    SENSITIVE_PARAMS
+        .iter()
+        .any(|param| param.eq_ignore_ascii_case(name))
+}
+
+/// Replaces all occurrences of `secret` in `text`.
+pub(crate) fn redact_secret(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        text.to_owned()
+    } else {
+        text.replace(secret, REDACTED)
+    }
+}
+
+/// Debug formatting of headers with credentials and signed URLs redacted.
+pub(crate) struct Headers<'a>(pub(crate) &'a HeaderMap);
+
+impl fmt::Debug for Headers<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.0 {
+            if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                map.entry(&name, &REDACTED);
+            } else {
+                match value.to_str().ok().and_then(|value| Url::parse(value).ok()) {
+                    Some(url) => map.entry(&name, &redact_url(&url).as_str()),
+                    None => map.entry(&name, &value),
+                };
+            }
+        }
+        map.finish()
+    }
+}
+
+impl Error {
+    /// Redacts URLs and occurrences of `token` contained in the error.
+    pub(crate) fn redacted(mut self, token: &str) -> Self {
+        match &mut self {
+            Self::Reqwest(err) => {
+                if let Some(url) = err.url_mut() {
+                    *url = redact_url(url);
+                }
+            }
+            _ => {
+                if let Some(err) = self.status_error_mut() {
+                    err.url = redact_url(&err.url);
+                    err.body = redact_secret(&err.body, token);
+                    if let Some(service_error) = &mut err.service_error {
+                        service_error.message = redact_secret(&service_error.message, token);
+                    }
+                }
+            }
+        }
+        self
+    }
+}
@@ -0,0 +1,109 @@
+//! Retrying failed requests.
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use crate::Error;
+
+/// Decides whether and when failed requests are retried.
+///
+/// Configured on the client using [`Cache::with_retry_policy`][crate::Cache::with_retry_policy].
+/// Requests with streamed bodies cannot be repeated and thus are never retried, independent of
+/// the policy.
+pub trait RetryPolicy: Send + Sync {
+    /// Returns the delay before retrying after `attempts` failed attempts, or `None` to give up.
+    ///
+    /// The `elapsed` time is measured from the start of the initial attempt and `error` is the
+    /// error of the latest attempt.
+    fn retry_delay(&self, attempts: u32, elapsed: Duration, error: &Error) -> Option<Duration>;
+}
+
+/// Policy that never retries, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry_delay(&self, _attempts: u32, _elapsed: Duration, _error: &Error) -> Option<Duration> {
+        None
+    }
+}
+
+/// Policy retrying failed requests with exponential backoff.
+///
+/// Requests are retried for [retryable][Error::is_retryable] errors. When
+/// the server asks to wait for a specific time using `Retry-After`, that time is used instead of
+/// the backoff delay. Other delays are randomized to spread out retries of concurrent jobs.
+///
+/// The default values give up after 5 attempts or 2 minutes.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackoff {
+    /// Maximal number of attempts, including the initial one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further retry.
+    pub initial_delay: Duration,
+    /// Upper bound for a single backoff delay.
+    pub max_delay: Duration,
+    /// Time after the initial attempt after which no further retries are started.
+    pub max_elapsed: Duration,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_elapsed: Duration::from_secs(120),
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// Returns a policy retrying up to `max_attempts` times without waiting in between.
+    ///
+    /// This is meant for tests against mock servers. Waiting for `Retry-After` is avoided by also
+    /// setting [`Cache::with_max_retry_after`][crate::Cache::with_max_retry_after] to zero, as
+    /// done by [`Cache::for_testing`][crate::Cache::for_testing].
+    pub fn without_delay(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..Self::default()
+        }
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn retry_delay(&self, attempts: u32, elapsed: Duration, error: &Error) -> Option<Duration> {
+        if attempts >= self.max_attempts || !error.is_retryable() {
+            return None;
+        }
+
+        let delay = match error.retry_after() {
+            Some(retry_after) => Duration::from_secs(retry_after),
+            None => {
+                let exponent = attempts.saturating_sub(1).min(31);
+                let backoff = self
+                    .initial_delay
+                    .saturating_mul(1 << exponent)
+                    .min(self.max_delay);
+                jitter(backoff)
+            }
+        };
+
+        if elapsed + delay > self.max_elapsed {
+            return None;
+        }
+        Some(delay)
+    }
+}
+
+/// Randomizes a delay to lie between half and all of the given delay.
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+    delay.mul_f64(0.5 + 0.5 * fraction)
+}
@@ -0,0 +1,75 @@
+//! Structured information about the scope of cache entries.
+use std::path::Path;
+
+/// The scope of a cache entry, i.e. the git ref of the workflow run which stored it.
+///
+/// Workflow runs can restore entries from their own scope, from the base branch of a pull
+/// request and from the default branch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Scope {
+    /// A branch, given by name without the `refs/heads/` prefix.
+    Branch(String),
+    /// A tag, given by name without the `refs/tags/` prefix.
+    Tag(String),
+    /// The merge ref of a pull request (`refs/pull/<number>/merge`).
+    PullRequest(u64),
+    /// Any other scope, given verbatim.
+    Other(String),
+}
+
+impl Scope {
+    /// Parses a scope as reported by the cache service.
+    pub fn parse(scope: &str) -> Self {
+        if let Some(branch) = scope.strip_prefix("refs/heads/") {
+            Self::Branch(branch.to_owned())
+        } else if let Some(tag) = scope.strip_prefix("refs/tags/") {
+            Self::Tag(tag.to_owned())
+        } else if let Some(number) = scope
+            .strip_prefix("refs/pull/")
+            .and_then(|rest| rest.strip_suffix("/merge"))
+            .and_then(|number| number.parse().ok())
+        {
+            Self::PullRequest(number)
+        } else {
+            Self::Other(scope.to_owned())
+        }
+    }
+
+    /// Returns the branch name if this is a branch scope.
+    pub fn branch(&self) -> Option<&str> {
+        match self {
+            Self::Branch(branch) => Some(branch),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this is the merge ref of a pull request.
+    pub fn is_pull_request(&self) -> bool {
+        matches!(self, Self::PullRequest(_))
+    }
+
+    /// Returns whether this is the branch with the given name.
+    pub fn is_branch(&self, name: &str) -> bool {
+        self.branch() == Some(name)
+    }
+
+    /// Returns whether this is the default branch of the repository.
+    ///
+    /// The default branch is read from the event payload of the current workflow run. Returns
+    /// `None` when that is not available.
+    pub fn is_default_branch(&self) -> Option<bool> {
+        Some(self.is_branch(&default_branch()?))
+    }
+}
+
+/// Reads the repository's default branch from the event payload at `GITHUB_EVENT_PATH`.
+pub(crate) fn default_branch() -> Option<String> {
+    let path = std::env::var_os("GITHUB_EVENT_PATH")?;
+    default_branch_from_event(Path::new(&path))
+}
+
+fn default_branch_from_event(path: &Path) -> Option<String> {
+    let event: serde_json::Value = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    Some(event["repository"]["default_branch"].as_str()?.to_owned())
+}
@@ -0,0 +1,549 @@
+//! Snapshots of directories and files, stored as compressed tar archives.
+//!
+//! Like the official cache action, archives are created and extracted by running `tar`, and
+//! `zstd` if it is installed. This preserves permissions and symlinks as far as the platform's
+//! `tar` does.
+use std::{
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+
+use crate::{digest, error, glob, key::CompressionMethod, CacheBackend, CacheHit, Error, Result};
+
+/// Returns Zstandard compression if the `zstd` program is available and gzip otherwise.
+pub fn detect_compression() -> CompressionMethod {
+    let zstd = Command::new("zstd")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match zstd {
+        Ok(status) if status.success() => CompressionMethod::Zstd,
+        _ => CompressionMethod::Gzip,
+    }
+}
+
+/// Returns the `tar` arguments selecting the compression, matching the official client.
+fn compression_args(compression: CompressionMethod, extract: bool) -> Vec<&'static str> {
+    let program = match (compression, extract) {
+        (CompressionMethod::Gzip, _) => return vec!["-z"],
+        (CompressionMethod::ZstdWithoutLong, false) => "zstd -T0",
+        (CompressionMethod::ZstdWithoutLong, true) => "zstd -d",
+        (CompressionMethod::Zstd, false) => "zstd -T0 --long=30",
+        (CompressionMethod::Zstd, true) => "zstd -d --long=30",
+    };
+    vec!["--use-compress-program", program]
+}
+
+/// A set of paths saved to and restored from a single cache entry.
+///
+/// Paths are stored relative to a root directory, the current directory by default, so a
+/// snapshot can be restored in a different location. The key space of the entries is derived
+/// from the paths and the compression, see [`key_space`][Self::key_space], so snapshots of
+/// different paths never restore each other's entries.
+///
+/// Restoring is atomic per path: the archive is extracted next to the paths and each path is
+/// replaced by its extracted version with a rename. Paths missing from the archive are left
+/// alone. Snapshots of glob patterns, see [`from_patterns`][Self::from_patterns], replace
+/// individual files instead.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    root: PathBuf,
+    selection: Selection,
+    compression: CompressionMethod,
+    cross_os: bool,
+}
+
+/// What a [`Snapshot`] archives.
+#[derive(Clone, Debug)]
+enum Selection {
+    Paths(Vec<PathBuf>),
+    Patterns(Vec<String>),
+}
+
+impl Snapshot {
+    /// Creates a snapshot of the given paths, relative to the current directory.
+    ///
+    /// The compression is [detected][detect_compression].
+    pub fn new(paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            root: PathBuf::from("."),
+            selection: Selection::Paths(paths.into_iter().map(Into::into).collect()),
+            compression: detect_compression(),
+            cross_os: false,
+        }
+    }
+
+    /// Creates a snapshot of the files matched by glob patterns, relative to the current
+    /// directory.
+    ///
+    /// Patterns follow the semantics of `hashFiles()`, see
+    /// [`hash_files_in`][crate::key::hash_files_in], and matched files have to be within the
+    /// root. Restoring extracts the archived files over existing ones, keeping files that are
+    /// not in the archive. The compression is [detected][detect_compression].
+    pub fn from_patterns(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            root: PathBuf::from("."),
+            selection: Selection::Patterns(patterns.into_iter().map(Into::into).collect()),
+            compression: detect_compression(),
+            cross_os: false,
+        }
+    }
+
+    /// Sets the directory the paths are relative to.
+    ///
+    /// Absolute paths have to be within this directory.
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Sets the compression of the archive.
+    pub fn with_compression(mut self, compression: CompressionMethod) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Shares entries between operating systems, like the official action's
+    /// `enableCrossOsArchive` option.
+    ///
+    /// Paths are recorded in the key space and the archive with `/` as separator, and entries
+    /// saved on Windows are no longer kept separate from other platforms. Only use this if the
+    /// archived content itself is platform independent.
+    pub fn cross_os(mut self) -> Self {
+        self.cross_os = true;
+        self
+    }
+
+    /// Returns the key space of this snapshot's entries.
+    ///
+    /// Like the version computed by the official cache action, it is a digest of the paths, or
+    /// patterns, and the compression method, and entries saved on Windows are kept separate
+    /// unless [`cross_os`][Self::cross_os] is set.
+    pub fn key_space(&self) -> Result<String> {
+        let mut input = String::from("snapshot");
+        match &self.selection {
+            Selection::Paths(_) => {
+                for path in self.relative_paths()? {
+                    input.push('\n');
+                    if self.cross_os {
+                        input.push_str(&portable_path(&path));
+                    } else {
+                        input.push_str(&path.to_string_lossy());
+                    }
+                }
+            }
+            Selection::Patterns(patterns) => {
+                input.push_str("\npatterns");
+                for pattern in patterns {
+                    input.push('\n');
+                    input.push_str(pattern);
+                }
+            }
+        }
+        input.push('\n');
+        input.push_str(self.compression.as_str());
+        if cfg!(windows) && !self.cross_os {
+            input.push_str("\nwindows-only");
+        }
+        Ok(digest::sha256_hex(input.as_bytes()))
+    }
+
+    /// Returns the literal paths of the snapshot, none for snapshots of patterns.
+    fn relative_paths(&self) -> Result<Vec<PathBuf>> {
+        let Selection::Paths(paths) = &self.selection else {
+            return Ok(vec![]);
+        };
+        paths
+            .iter()
+            .map(|path| relative_path(&self.root, path))
+            .collect()
+    }
+
+    /// Returns the existing paths to archive, relative to the root.
+    fn archived_paths(&self) -> Result<Vec<PathBuf>> {
+        match &self.selection {
+            Selection::Paths(_) => Ok(self
+                .relative_paths()?
+                .into_iter()
+                .filter(|path| self.root.join(path).symlink_metadata().is_ok())
+                .collect()),
+            Selection::Patterns(patterns) => {
+                let patterns: Vec<_> = patterns.iter().map(|p| glob::Pattern::new(p)).collect();
+                let root = std::path::absolute(&self.root)?;
+                glob::find_files(&root, &patterns)?
+                    .iter()
+                    .map(|path| relative_path(&root, path))
+                    .collect()
+            }
+        }
+    }
+
+    /// Archives the existing paths and stores the archive under `key`, returning its size.
+    ///
+    /// Paths that don't exist are skipped, if none exist [`Error::EmptySnapshot`] is returned.
+    pub async fn save(&self, cache: &dyn CacheBackend, key: &str) -> Result<u64> {
+        let key_space = self.key_space()?;
+        let root = self.root.clone();
+        let paths = {
+            let snapshot = self.clone();
+            tokio::task::spawn_blocking(move || snapshot.archived_paths())
+                .await
+                .map_err(std::io::Error::other)??
+        };
+        if paths.is_empty() {
+            return Err(Error::EmptySnapshot);
+        }
+
+        let archive = temp_archive_path();
+        let (compression, cross_os) = (self.compression, self.cross_os);
+        let result = async {
+            let (root, path) = (root.clone(), archive.clone());
+            tokio::task::spawn_blocking(move || {
+                create(&root, &paths, compression, cross_os, &path)
+            })
+            .await
+            .map_err(std::io::Error::other)??;
+            let size = tokio::fs::metadata(&archive).await?.len();
+            cache.put_file(&key_space, key, &archive).await?;
+            Ok(size)
+        }
+        .await;
+        let _ = tokio::fs::remove_file(&archive).await;
+        result
+    }
+
+    /// Restores the paths from the first entry matching `keys`.
+    ///
+    /// Returns `None` if no entry matches. See [`Cache::get_url`][crate::Cache::get_url] for how
+    /// entries are matched.
+    pub async fn restore(
+        &self,
+        cache: &dyn CacheBackend,
+        keys: &[&str],
+    ) -> Result<Option<CacheHit>> {
+        let key_space = self.key_space()?;
+        let root = self.root.clone();
+        let paths = match self.selection {
+            Selection::Paths(_) => Some(self.relative_paths()?),
+            Selection::Patterns(_) => None,
+        };
+        let compression = self.compression;
+
+        // Archives can be large, so they are downloaded to a file instead of into memory.
+        let archive = temp_archive_path();
+        let result = async {
+            let Some(hit) = cache.get_file(&key_space, keys, &archive).await? else {
+                return Ok(None);
+            };
+            let path = archive.clone();
+            tokio::task::spawn_blocking(move || {
+                restore(&root, paths.as_deref(), compression, &path)
+            })
+            .await
+            .map_err(std::io::Error::other)??;
+            Ok(Some(hit))
+        }
+        .await;
+        let _ = tokio::fs::remove_file(&archive).await;
+        result
+    }
+}
+
+/// Returns `path` relative to `root`, rejecting paths that could leave it.
+fn relative_path(root: &Path, path: &Path) -> Result<PathBuf> {
+    let invalid = || Error::InvalidSnapshotPath(path.to_owned());
+    let relative = if path.is_absolute() {
+        let root = std::path::absolute(root)?;
+        path.strip_prefix(&root).map_err(|_| invalid())?
+    } else {
+        path
+    };
+    let mut normalized = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return Err(invalid()),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(invalid());
+    }
+    Ok(normalized)
+}
+
+/// Returns a relative path with `/` as separator on every platform.
+fn portable_path(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
+
+/// Returns a unique path for an archive in the runner's temporary directory.
+fn temp_archive_path() -> PathBuf {
+    std::env::var_os("RUNNER_TEMP")
+        .map_or_else(std::env::temp_dir, PathBuf::from)
+        .join(format!("snapshot-{}.tar", unique_suffix()))
+}
+
+fn unique_suffix() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!(
+        "{}-{:x}-{}",
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Runs a `tar` command, passing `input` on stdin.
+fn run(mut command: Command, input: Option<Bytes>) -> Result<()> {
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Written from another thread, as tar may block writing to stderr until it is read.
+    let writer = child.stdin.take().zip(input).map(|(mut stdin, input)| {
+        std::thread::spawn(move || {
+            // A failed write means tar exited early, which it reports below.
+            let _ = stdin.write_all(&input);
+        })
+    });
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    error::command_output("tar", output)?;
+    Ok(())
+}
+
+fn create(
+    root: &Path,
+    paths: &[PathBuf],
+    compression: CompressionMethod,
+    cross_os: bool,
+    archive: &Path,
+) -> Result<()> {
+    // Paths are passed on stdin, as there may be too many files for the command line.
+    let mut list = vec![];
+    for path in paths {
+        if cross_os {
+            list.extend_from_slice(portable_path(path).as_bytes());
+        } else {
+            list.extend_from_slice(path.as_os_str().as_encoded_bytes());
+        }
+        list.push(0);
+    }
+    let mut command = Command::new("tar");
+    command
+        .args(["--posix", "-c"])
+        .args(compression_args(compression, false))
+        .arg("-f")
+        .arg(archive)
+        .arg("-C")
+        .arg(root)
+        .args(["--null", "-T", "-"]);
+    run(command, Some(list.into()))
+}
+
+/// Extracts an archive into `root`, replacing the given paths, or individual files if `None`.
+fn restore(
+    root: &Path,
+    paths: Option<&[PathBuf]>,
+    compression: CompressionMethod,
+    archive: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(root)?;
+    let Some(paths) = paths else {
+        return in_staging(root, |staging| {
+            extract(archive, compression, staging, None)?;
+            merge(staging, root)
+        });
+    };
+
+    let members = list(archive, compression)?;
+    for path in paths {
+        let name = portable_path(path);
+        if !members.iter().any(|member| is_within(member, &name)) {
+            continue;
+        }
+        // Each path is staged next to itself, as the paths can be on different file systems
+        // than the root, which might not even be writable.
+        let target = root.join(path);
+        let parent = target.parent().unwrap_or(root);
+        std::fs::create_dir_all(parent)?;
+        in_staging(parent, |staging| {
+            extract(archive, compression, staging, Some(&name))?;
+            let extracted = staging.join(path);
+            if extracted.symlink_metadata().is_err() {
+                return Ok(());
+            }
+            let previous = staging.join(format!(".previous-{}", unique_suffix()));
+            let replaced = target.symlink_metadata().is_ok();
+            if replaced {
+                std::fs::rename(&target, &previous)?;
+            }
+            if let Err(err) = std::fs::rename(&extracted, &target) {
+                if replaced {
+                    let _ = std::fs::rename(&previous, &target);
+                }
+                return Err(err.into());
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Runs `f` with a new staging directory within `dir`, removing it afterwards.
+fn in_staging(dir: &Path, f: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let staging = dir.join(format!(".snapshot-{}", unique_suffix()));
+    std::fs::create_dir(&staging)?;
+    let result = f(&staging);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+/// Extracts the member `name` and everything below it, or the whole archive, into `dir`.
+fn extract(
+    archive: &Path,
+    compression: CompressionMethod,
+    dir: &Path,
+    name: Option<&str>,
+) -> Result<()> {
+    let mut command = Command::new("tar");
+    command
+        .args(["-x", "-p"])
+        .args(compression_args(compression, true))
+        .arg("-f")
+        .arg(archive)
+        .arg("-C")
+        .arg(dir);
+    if let Some(name) = name {
+        command.arg("--").arg(name);
+    }
+    run(command, None)
+}
+
+/// Returns the names of the members of an archive.
+fn list(archive: &Path, compression: CompressionMethod) -> Result<Vec<String>> {
+    let output = Command::new("tar")
+        .arg("-t")
+        .args(compression_args(compression, true))
+        .arg("-f")
+        .arg(archive)
+        .stdin(Stdio::null())
+        .output()?;
+    let output = error::command_output("tar", output)?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Returns whether the archive member `member` is `name` or below it.
+fn is_within(member: &str, name: &str) -> bool {
+    let member = member.trim_end_matches('/');
+    member == name
+        || member
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Moves the contents of the extracted directory `source` into `target`, replacing files but
+/// keeping other contents of existing directories.
+fn merge(source: &Path, target: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = target.join(entry.file_name());
+        let existing = destination.symlink_metadata();
+        if entry.file_type()?.is_dir() && existing.as_ref().is_ok_and(|meta| meta.is_dir()) {
+            merge(&entry.path(), &destination)?;
+        } else {
+            // Renaming a directory onto a file fails, and onto a directory unless it is empty.
+            match existing {
+                Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&destination)?,
+                Ok(_) => std::fs::remove_file(&destination)?,
+                Err(_) => {}
+            }
+            std::fs::rename(entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryCache;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("snapshot-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn restores_saved_paths() {
+        let (source, target) = (temp_dir("paths-source"), temp_dir("paths-target"));
+        std::fs::create_dir_all(source.join("dir/sub")).unwrap();
+        std::fs::write(source.join("dir/sub/file"), "content").unwrap();
+        std::fs::write(source.join("top"), "top").unwrap();
+        std::fs::create_dir_all(target.join("dir")).unwrap();
+        std::fs::write(target.join("dir/stale"), "stale").unwrap();
+
+        let cache = InMemoryCache::new();
+        let snapshot = |root: &Path| {
+            Snapshot::new(["dir", "top", "missing"])
+                .with_root(root)
+                .with_compression(CompressionMethod::Gzip)
+        };
+        snapshot(&source).save(&cache, "key").await.unwrap();
+        let hit = snapshot(&target).restore(&cache, &["key"]).await.unwrap();
+        assert_eq!(hit.unwrap().key, "key");
+
+        assert_eq!(
+            std::fs::read(target.join("dir/sub/file")).unwrap(),
+            b"content"
+        );
+        assert_eq!(std::fs::read(target.join("top")).unwrap(), b"top");
+        assert!(!target.join("dir/stale").exists());
+    }
+
+    #[tokio::test]
+    async fn merges_directories_over_files() {
+        let (source, target) = (temp_dir("merge-source"), temp_dir("merge-target"));
+        std::fs::create_dir_all(source.join("dir")).unwrap();
+        std::fs::write(source.join("dir/file"), "content").unwrap();
+        std::fs::write(target.join("dir"), "file in the way").unwrap();
+
+        let cache = InMemoryCache::new();
+        let snapshot = |root: &Path| {
+            Snapshot::from_patterns(["dir/**"])
+                .with_root(root)
+                .with_compression(CompressionMethod::Gzip)
+        };
+        snapshot(&source).save(&cache, "key").await.unwrap();
+        snapshot(&target).restore(&cache, &["key"]).await.unwrap();
+        assert_eq!(std::fs::read(target.join("dir/file")).unwrap(), b"content");
+    }
+}
@@ -0,0 +1,227 @@
+//! Entries larger than the service accepts, split into parts tied together by a manifest.
+use std::{io, path::Path};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{backend::BoxFuture, digest, CacheBackend, CacheHit, Error, Result};
+
+/// Format version of manifests, changed for incompatible changes.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    size: u64,
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Part {
+    key: String,
+    size: u64,
+    sha256: String,
+}
+
+/// A [`CacheBackend`] splitting entries into parts of a maximal size.
+///
+/// Each entry is stored as a small manifest under the requested key, listing parts stored in a
+/// separate key space. Lookups only see manifests, so splitting is transparent to users of the
+/// backend, but entries stored without it can't be read through it and vice versa. Parts are
+/// uploaded before the manifest, so an entry is only found once it is complete, and are
+/// verified against their recorded digests when reassembled.
+///
+/// [`get_bytes`][CacheBackend::get_bytes] reassembles the whole entry in memory. To restore
+/// entries larger than the available memory, use [`get_to_file`][Self::get_to_file], which,
+/// like [`put_file`][CacheBackend::put_file], only buffers one part at a time.
+pub struct SplitCache<B> {
+    inner: B,
+    part_size: u64,
+}
+
+impl<B: CacheBackend> SplitCache<B> {
+    /// Wraps `inner`, splitting entries into parts of at most 1 GiB.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            part_size: 1 << 30,
+        }
+    }
+
+    /// Sets the maximal size of parts.
+    pub fn with_part_size(mut self, part_size: u64) -> Self {
+        self.part_size = part_size.max(1);
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn part_key_space(key_space: &str) -> String {
+        digest::sha256_hex(format!("{}\nparts", key_space).as_bytes())
+    }
+
+    async fn manifest(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+    ) -> Result<Option<(CacheHit, Manifest)>> {
+        let Some((hit, data)) = self.inner.get_bytes(key_space, keys).await? else {
+            return Ok(None);
+        };
+        let manifest: Manifest =
+            serde_json::from_slice(&data).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "unsupported version {}",
+                manifest.version
+            )));
+        }
+        Ok(Some((hit, manifest)))
+    }
+
+    async fn get_part(&self, key_space: &str, part: &Part) -> Result<Bytes> {
+        let part_key_space = Self::part_key_space(key_space);
+        match self.inner.get_bytes(&part_key_space, &[&part.key]).await? {
+            Some((hit, data))
+                if hit.key == part.key && digest::sha256_hex(&data) == part.sha256 =>
+            {
+                Ok(data)
+            }
+            _ => Err(Error::InvalidManifest(format!(
+                "missing or corrupted part {}",
+                part.key
+            ))),
+        }
+    }
+
+    async fn put_part(
+        &self,
+        key_space: &str,
+        key: &str,
+        index: usize,
+        data: Bytes,
+    ) -> Result<Part> {
+        let sha256 = digest::sha256_hex(&data);
+        // Named after their content, so parts left by an earlier attempt can be reused.
+        let part = Part {
+            key: format!("{}.{}.{}", key, index, &sha256[..16]),
+            size: data.len() as u64,
+            sha256,
+        };
+        let part_key_space = Self::part_key_space(key_space);
+        match self.inner.put_bytes(&part_key_space, &part.key, data).await {
+            Ok(()) | Err(Error::Conflict(_)) => Ok(part),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn put_manifest(&self, key_space: &str, key: &str, parts: Vec<Part>) -> Result<()> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            size: parts.iter().map(|part| part.size).sum(),
+            parts,
+        };
+        let data = serde_json::to_vec(&manifest).expect("manifests serialize");
+        self.inner.put_bytes(key_space, key, data.into()).await
+    }
+
+    /// Looks up a matching entry and writes its content to the file at `path`.
+    ///
+    /// Parts are written as they are downloaded, so only one part is held in memory.
+    pub async fn get_to_file(
+        &self,
+        key_space: &str,
+        keys: &[&str],
+        path: impl AsRef<Path>,
+    ) -> Result<Option<CacheHit>> {
+        let Some((hit, manifest)) = self.manifest(key_space, keys).await? else {
+            return Ok(None);
+        };
+        let mut file = tokio::fs::File::create(path).await?;
+        for part in &manifest.parts {
+            file.write_all(&self.get_part(key_space, part).await?)
+                .await?;
+        }
+        file.flush().await?;
+        Ok(Some(hit))
+    }
+}
+
+impl<B: CacheBackend> CacheBackend for SplitCache<B> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        self.inner.lookup(key_space, key_prefixes)
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let Some((hit, manifest)) = self.manifest(key_space, keys).await? else {
+                return Ok(None);
+            };
+            let mut data = BytesMut::with_capacity(manifest.size as usize);
+            for part in &manifest.parts {
+                data.extend_from_slice(&self.get_part(key_space, part).await?);
+            }
+            Ok(Some((hit, data.freeze())))
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut parts = vec![];
+            let mut offset = 0;
+            while offset < data.len() || parts.is_empty() {
+                let end = data
+                    .len()
+                    .min(offset.saturating_add(self.part_size as usize));
+                parts.push(
+                    self.put_part(key_space, key, parts.len(), data.slice(offset..end))
+                        .await?,
+                );
+                offset = end;
+            }
+            self.put_manifest(key_space, key, parts).await
+        })
+    }
+
+    fn put_file<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut file = tokio::fs::File::open(path).await?;
+            let size = file.metadata().await?.len();
+            let mut parts = vec![];
+            let mut offset = 0;
+            while offset < size || parts.is_empty() {
+                let len = self.part_size.min(size - offset);
+                let mut data = vec![0; usize::try_from(len).map_err(io::Error::other)?];
+                file.read_exact(&mut data).await?;
+                parts.push(
+                    self.put_part(key_space, key, parts.len(), data.into())
+                        .await?,
+                );
+                offset += len;
+            }
+            self.put_manifest(key_space, key, parts).await
+        })
+    }
+}
@@ -0,0 +1,105 @@
+//! Statistics about transfers.
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// Statistics about a completed upload or download.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct TransferStats {
+    /// Number of transferred content bytes.
+    pub bytes: u64,
+    /// Total duration of the operation, including all API requests.
+    pub elapsed: Duration,
+    /// Number of retried requests during the operation.
+    pub retries: u32,
+    /// Time spent in the individual steps of an upload, `None` for downloads.
+    pub phases: Option<PutPhases>,
+}
+
+/// Time spent in the individual steps of storing an entry.
+///
+/// Slow reserve and finalize steps point to the cache API, a slow upload to the blob store or to
+/// the code producing the uploaded content.
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct PutPhases {
+    /// Time spent reserving the entry.
+    pub reserve: Duration,
+    /// Time spent uploading the content, including producing streamed content.
+    pub upload: Duration,
+    /// Time spent committing the entry.
+    pub finalize: Duration,
+}
+
+impl TransferStats {
+    /// Returns the average throughput in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Formats the statistics like `1.2 GiB in 8.3 s (145.0 MiB/s)`.
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {:.1} s ({}/s)",
+            HumanBytes(self.bytes as f64),
+            self.elapsed.as_secs_f64(),
+            HumanBytes(self.throughput())
+        )
+    }
+}
+
+/// Formats a byte count using binary units.
+pub(crate) struct HumanBytes(pub(crate) f64);
+
+impl fmt::Display for HumanBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+        let mut value = self.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit + 1 < UNITS.len() {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{:.0} {}", value, UNITS[unit])
+        } else {
+            write!(f, "{:.1} {}", value, UNITS[unit])
+        }
+    }
+}
+
+/// Collects statistics while an operation is in progress.
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    attempts: AtomicU32,
+    retries: AtomicU32,
+}
+
+impl Tracker {
+    pub(crate) fn add_attempt(&self) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn retries(&self) -> u32 {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,71 @@
+//! Byte streams used for uploads.
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures_core::{Stream, TryStream};
+
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+type Inspector = Box<dyn FnMut(&Bytes) + Send + Sync>;
+
+/// Type erased stream of bytes, optionally observing each chunk passing through.
+pub(crate) struct ByteStream {
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, BoxError>> + Send + Sync>>,
+    inspectors: Vec<Inspector>,
+}
+
+impl ByteStream {
+    pub(crate) fn new<S>(stream: S) -> Self
+    where
+        S: TryStream + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        Bytes: From<S::Ok>,
+    {
+        Self {
+            inner: Box::pin(Erased(Box::pin(stream))),
+            inspectors: vec![],
+        }
+    }
+
+    /// Calls `inspector` for every chunk produced by the stream.
+    pub(crate) fn inspect(mut self, inspector: impl FnMut(&Bytes) + Send + Sync + 'static) -> Self {
+        self.inspectors.push(Box::new(inspector));
+        self
+    }
+}
+
+impl Stream for ByteStream {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let item = this.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &item {
+            for inspector in &mut this.inspectors {
+                inspector(chunk);
+            }
+        }
+        item
+    }
+}
+
+struct Erased<S>(Pin<Box<S>>);
+
+impl<S> Stream for Erased<S>
+where
+    S: TryStream,
+    S::Error: Into<BoxError>,
+    Bytes: From<S::Ok>,
+{
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .as_mut()
+            .try_poll_next(cx)
+            .map(|item| item.map(|item| item.map(Bytes::from).map_err(Into::into)))
+    }
+}
@@ -0,0 +1,210 @@
+//! Markdown report of the cache operations of a job.
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    sync::Mutex,
+};
+
+use crate::{management::RepositoryCacheUsage, stats::HumanBytes, CacheHit, Error, MatchKind};
+
+/// Collects the cache operations of a job and renders them as a markdown report.
+///
+/// A [`Cache`][crate::Cache] records its restores and saves into a summary set with
+/// [`Cache::with_summary`][crate::Cache::with_summary]. Other backends can record their
+/// operations explicitly. The report is meant to be appended to the job's step summary, see
+/// [`append_to_step_summary`][Self::append_to_step_summary].
+#[derive(Debug, Default)]
+pub struct JobSummary {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    rows: Vec<Row>,
+    usage: Option<RepositoryCacheUsage>,
+}
+
+#[derive(Debug)]
+struct Row {
+    save: bool,
+    key: String,
+    outcome: Outcome,
+    bytes: Option<u64>,
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Hit {
+        key: String,
+        scope: String,
+        exact: bool,
+    },
+    Miss,
+    Stored,
+    Skipped,
+    Failed(String),
+}
+
+impl JobSummary {
+    /// Creates an empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a restore of `key` that found the entry `hit`, optionally with the downloaded size.
+    pub fn record_hit(&self, key: &str, hit: &CacheHit, bytes: Option<u64>) {
+        self.push(
+            false,
+            key,
+            Outcome::Hit {
+                key: hit.key.clone(),
+                scope: hit.scope.clone(),
+                exact: hit.match_kind == MatchKind::Exact,
+            },
+            bytes,
+        );
+    }
+
+    /// Records a restore of `key` that found no entry.
+    pub fn record_miss(&self, key: &str) {
+        self.push(false, key, Outcome::Miss, None);
+    }
+
+    /// Records storing `bytes` under `key`.
+    pub fn record_stored(&self, key: &str, bytes: u64) {
+        self.push(true, key, Outcome::Stored, Some(bytes));
+    }
+
+    /// Records that storing `key` was skipped, e.g. because an identical entry exists.
+    pub fn record_skipped(&self, key: &str) {
+        self.push(true, key, Outcome::Skipped, None);
+    }
+
+    /// Records a failed restore or save of `key`.
+    pub fn record_error(&self, save: bool, key: &str, error: &Error) {
+        self.push(save, key, Outcome::Failed(error.to_string()), None);
+    }
+
+    /// Includes the repository's cache usage in the report, see
+    /// [`CacheManagement::usage`][crate::management::CacheManagement::usage].
+    pub fn set_usage(&self, usage: RepositoryCacheUsage) {
+        self.state.lock().unwrap().usage = Some(usage);
+    }
+
+    fn push(&self, save: bool, key: &str, outcome: Outcome, bytes: Option<u64>) {
+        self.state.lock().unwrap().rows.push(Row {
+            save,
+            key: key.to_owned(),
+            outcome,
+            bytes,
+        });
+    }
+
+    /// Returns whether nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.rows.is_empty() && state.usage.is_none()
+    }
+
+    /// Renders the report as markdown, a table of all operations followed by totals.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::from("### Cache\n\n");
+
+        if !state.rows.is_empty() {
+            out.push_str("| | Key | Result | Size |\n| --- | --- | --- | --- |\n");
+            for row in &state.rows {
+                let result = match &row.outcome {
+                    Outcome::Hit { exact: true, .. } => "exact hit".to_owned(),
+                    Outcome::Hit { key, scope, .. } => {
+                        format!("hit {} from {}", code(key), escape(scope))
+                    }
+                    Outcome::Miss => "miss".to_owned(),
+                    Outcome::Stored => "stored".to_owned(),
+                    Outcome::Skipped => "skipped, already cached".to_owned(),
+                    Outcome::Failed(error) => format!("failed: {}", escape(error)),
+                };
+                let size = row
+                    .bytes
+                    .map(|bytes| HumanBytes(bytes as f64).to_string())
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    if row.save { "save" } else { "restore" },
+                    code(&row.key),
+                    result,
+                    size
+                );
+            }
+            out.push('\n');
+
+            let restores = state.rows.iter().filter(|row| !row.save);
+            let (mut total, mut hits, mut exact) = (0, 0, 0);
+            for row in restores {
+                total += 1;
+                if let Outcome::Hit {
+                    exact: is_exact, ..
+                } = row.outcome
+                {
+                    hits += 1;
+                    exact += usize::from(is_exact);
+                }
+            }
+            let stored: u64 = state
+                .rows
+                .iter()
+                .filter(|row| matches!(row.outcome, Outcome::Stored))
+                .filter_map(|row| row.bytes)
+                .sum();
+            let _ = writeln!(
+                out,
+                "Restores: {} of {} hit ({} exact). Stored: {}.\n",
+                hits,
+                total,
+                exact,
+                HumanBytes(stored as f64)
+            );
+        }
+
+        if let Some(usage) = &state.usage {
+            let _ = writeln!(
+                out,
+                "Repository usage: {} in {} entries.\n",
+                HumanBytes(usage.active_caches_size_in_bytes as f64),
+                usage.active_caches_count
+            );
+        }
+        out
+    }
+
+    /// Appends the report to the file named by the `GITHUB_STEP_SUMMARY` environment variable.
+    ///
+    /// Returns `false` without writing anything when that variable is not set, i.e. outside of
+    /// a workflow run.
+    pub fn append_to_step_summary(&self) -> io::Result<bool> {
+        let path = match std::env::var_os("GITHUB_STEP_SUMMARY") {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        file.write_all(self.render().as_bytes())?;
+        Ok(true)
+    }
+}
+
+/// Escapes text for a table cell, keeping it on a single line.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+/// Formats a key as inline code within a table cell.
+fn code(key: &str) -> String {
+    // Backticks can't be escaped inside code spans, a longer delimiter is needed instead.
+    let delimiter = if key.contains('`') { "`` " } else { "`" };
+    let closing: String = delimiter.chars().rev().collect();
+    format!("{}{}{}", delimiter, escape(key), closing)
+}
@@ -0,0 +1,529 @@
+//! In-process mock of the cache service, available with the `testing` feature.
+//!
+//! [`MockServer`] speaks the same HTTP protocol as the cache service, so code using [`Cache`] can
+//! be tested end to end, including chunked uploads, without network access or runtime
+//! environment variables. Canned responses can be queued to exercise retries and rate limiting.
+//!
+//! [`RecordingTransport`] captures the traffic of a client to a fixture file, with secrets
+//! redacted, which [`ReplayTransport`] serves back, e.g. to test against traffic captured on an
+//! actual runner. [`FaultInjector`] injects failures like rate limiting, server errors, truncated
+//! bodies and slow requests into the traffic of a client. [`ManualClock`] lets retries and backoff
+//! run without actually waiting. [`RequestCapture`] captures the requests of operations for
+//! snapshot tests detecting unintended protocol changes. The [`conformance`] checks verify that
+//! other [`CacheBackend`] implementations behave like the cache service.
+//!
+//! [`CacheBackend`]: crate::CacheBackend
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{Cache, InMemoryCache, Result};
+
+mod clock;
+pub mod conformance;
+mod faults;
+mod fixtures;
+mod snapshot;
+
+pub use clock::ManualClock;
+pub use faults::{Fault, FaultInjector};
+pub use fixtures::{RecordingTransport, ReplayTransport};
+pub use snapshot::{CapturedRequest, RequestCapture};
+
+/// Runtime token expected by the mock server.
+const TOKEN: &str = "mock-runtime-token";
+
+/// A mock cache service listening on a local port.
+///
+/// The server is shut down when this is dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+/// A canned response returned instead of handling a request.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+/// A request received by the mock server.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RecordedRequest {
+    /// The request method.
+    pub method: String,
+    /// The request target, i.e. the path including the query.
+    pub target: String,
+    /// The request headers, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// The request body.
+    pub body: Bytes,
+}
+
+impl RecordedRequest {
+    /// Returns the value of the header `name` if present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+}
+
+struct State {
+    entries: InMemoryCache,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    injected: VecDeque<MockResponse>,
+    requests: Vec<RecordedRequest>,
+    reservations: HashMap<i64, Reservation>,
+    blobs: HashMap<i64, Bytes>,
+    next_id: i64,
+}
+
+struct Reservation {
+    key_space: String,
+    key: String,
+    chunks: Vec<(u64, Bytes)>,
+}
+
+impl MockResponse {
+    /// Creates an empty response with the given status.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("valid status code"),
+            headers: vec![],
+            body: Bytes::new(),
+        }
+    }
+
+    /// Creates a `429 Too Many Requests` response asking to retry after `seconds`.
+    pub fn rate_limited(seconds: u64) -> Self {
+        Self::status(429).header("retry-after", &seconds.to_string())
+    }
+
+    /// Adds a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    fn json(status: StatusCode, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+            body: value.to_string().into(),
+        }
+    }
+
+    fn service_error(status: StatusCode, type_key: &str, message: String) -> Self {
+        Self::json(status, json!({ "typeKey": type_key, "message": message }))
+    }
+}
+
+impl MockServer {
+    /// Starts a server with no entries.
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with(InMemoryCache::new()).await
+    }
+
+    /// Starts a server storing entries in the given cache.
+    ///
+    /// This determines the scopes of stored and restored entries and allows preparing entries.
+    pub async fn start_with(entries: InMemoryCache) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State {
+            entries,
+            inner: Mutex::default(),
+        });
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = serve(stream, addr, &state).await {
+                            tracing::debug!(%err, "mock server connection failed");
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// Returns the cache URL, as it would be found in `ACTIONS_CACHE_URL`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Returns the runtime token accepted by the server.
+    pub fn token(&self) -> &str {
+        TOKEN
+    }
+
+    /// Creates a client using this server.
+    pub fn client(&self, user_agent: &str) -> Result<Cache> {
+        Cache::with_endpoint(user_agent, &self.url(), TOKEN)
+    }
+
+    /// Returns the committed entries.
+    pub fn entries(&self) -> &InMemoryCache {
+        &self.state.entries
+    }
+
+    /// Queues a response that is returned for the next request instead of handling it.
+    ///
+    /// Queued responses are returned in order, one per request.
+    pub fn push_response(&self, response: MockResponse) {
+        self.state
+            .inner
+            .lock()
+            .unwrap()
+            .injected
+            .push_back(response);
+    }
+
+    /// Returns all requests received so far.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.inner.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(stream: TcpStream, addr: SocketAddr, state: &State) -> io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let mut parts = line.split(' ');
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "bad request line",
+                ))
+            }
+        };
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+        }
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        let mut body = vec![];
+        if let Some(len) = header("content-length").and_then(|len| len.parse().ok()) {
+            body.resize(len, 0);
+            reader.read_exact(&mut body).await?;
+        } else if header("transfer-encoding").is_some_and(|value| value.contains("chunked")) {
+            loop {
+                let mut size = String::new();
+                reader.read_line(&mut size).await?;
+                let size = size.trim().split(';').next().unwrap_or_default();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                if size == 0 {
+                    let mut trailer = String::new();
+                    while reader.read_line(&mut trailer).await? > 2 {
+                        trailer.clear();
+                    }
+                    break;
+                }
+                let start = body.len();
+                body.resize(start + size, 0);
+                reader.read_exact(&mut body[start..]).await?;
+                let mut crlf = [0; 2];
+                reader.read_exact(&mut crlf).await?;
+            }
+        }
+
+        let request = RecordedRequest {
+            method,
+            target,
+            headers,
+            body: body.into(),
+        };
+        let response = state.handle(request, addr);
+
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\ncontent-length: {}\r\n",
+            response.status.as_u16(),
+            response.status.canonical_reason().unwrap_or_default(),
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        write.write_all(head.as_bytes()).await?;
+        write.write_all(&response.body).await?;
+        write.flush().await?;
+    }
+}
+
+impl State {
+    fn handle(&self, request: RecordedRequest, addr: SocketAddr) -> MockResponse {
+        let mut inner = self.inner.lock().unwrap();
+        inner.requests.push(request.clone());
+        if let Some(response) = inner.injected.pop_front() {
+            return response;
+        }
+
+        let url = match Url::parse(&format!("http://{}{}", addr, request.target)) {
+            Ok(url) => url,
+            Err(_) => return MockResponse::status(400),
+        };
+        let path = url.path().to_owned();
+
+        if let Some(id) = path.strip_prefix("/_blob/") {
+            return match id.parse().ok().and_then(|id| inner.blobs.get(&id)) {
+                Some(data) if request.method == "GET" => {
+                    MockResponse::status(200).body(data.clone())
+                }
+                _ => MockResponse::status(404),
+            };
+        }
+
+        let path = match path.strip_prefix("/_apis/artifactcache/") {
+            Some(path) => path,
+            None => return MockResponse::status(404),
+        };
+        if request.header("authorization") != Some(&format!("Bearer {}", TOKEN)) {
+            return MockResponse::status(401);
+        }
+
+        match (&*request.method, path) {
+            ("GET", "cache") => self.lookup(&mut inner, &url, addr),
+            ("POST", "caches") => self.reserve(&mut inner, &request),
+            (method, path) => match path.strip_prefix("caches/").and_then(|id| id.parse().ok()) {
+                Some(id) => match method {
+                    "PATCH" => upload(&mut inner, id, &request),
+                    "POST" => self.commit(&mut inner, id, &request),
+                    "DELETE" => match inner.reservations.remove(&id) {
+                        Some(_) => MockResponse::status(204),
+                        None => MockResponse::status(404),
+                    },
+                    _ => MockResponse::status(405),
+                },
+                None => MockResponse::status(404),
+            },
+        }
+    }
+
+    fn lookup(&self, inner: &mut Inner, url: &Url, addr: SocketAddr) -> MockResponse {
+        let mut keys = String::new();
+        let mut key_space = String::new();
+        for (name, value) in url.query_pairs() {
+            match &*name {
+                "keys" => keys = value.into_owned(),
+                "version" => key_space = value.into_owned(),
+                _ => (),
+            }
+        }
+        let keys: Vec<&str> = keys.split(',').collect();
+
+        match self.entries.find(&key_space, &keys) {
+            Ok(Some((hit, data))) => {
+                let id = inner.next_id();
+                inner.blobs.insert(id, data);
+                MockResponse::json(
+                    StatusCode::OK,
+                    json!({
+                        "cacheKey": hit.key,
+                        "scope": hit.scope,
+                        "archiveLocation": format!("http://{}/_blob/{}", addr, id),
+                    }),
+                )
+            }
+            Ok(None) => MockResponse::status(204),
+            Err(err) => MockResponse::service_error(
+                StatusCode::BAD_REQUEST,
+                "ArgumentException",
+                err.to_string(),
+            ),
+        }
+    }
+
+    fn reserve(&self, inner: &mut Inner, request: &RecordedRequest) -> MockResponse {
+        #[derive(Deserialize)]
+        struct ReserveRequest {
+            key: String,
+            version: String,
+        }
+
+        let ReserveRequest { key, version } = match serde_json::from_slice(&request.body) {
+            Ok(request) => request,
+            Err(_) => return MockResponse::status(400),
+        };
+
+        let exists = matches!(
+            self.entries.find(&version, &[&key]),
+            Ok(Some((hit, _))) if hit.key == key && hit.scope == self.entries.scope()
+        );
+        let reserved = inner
+            .reservations
+            .values()
+            .any(|reservation| reservation.key == key && reservation.key_space == version);
+        if exists || reserved {
+            return MockResponse::service_error(
+                StatusCode::CONFLICT,
+                "ArtifactCacheItemAlreadyExistsException",
+                format!(
+                    "Cache already exists. Scope: {}, Key: {}",
+                    self.entries.scope(),
+                    key
+                ),
+            );
+        }
+
+        let id = inner.next_id();
+        inner.reservations.insert(
+            id,
+            Reservation {
+                key_space: version,
+                key,
+                chunks: vec![],
+            },
+        );
+        MockResponse::json(StatusCode::CREATED, json!({ "cacheId": id }))
+    }
+
+    fn commit(&self, inner: &mut Inner, id: i64, request: &RecordedRequest) -> MockResponse {
+        #[derive(Deserialize)]
+        struct FinalizeRequest {
+            size: u64,
+        }
+
+        let size = match serde_json::from_slice::<FinalizeRequest>(&request.body) {
+            Ok(request) => request.size,
+            Err(_) => return MockResponse::status(400),
+        };
+        let mut reservation = match inner.reservations.remove(&id) {
+            Some(reservation) => reservation,
+            None => return MockResponse::status(404),
+        };
+
+        reservation.chunks.sort_by_key(|(offset, _)| *offset);
+        let mut data = Vec::with_capacity(size as usize);
+        for (offset, chunk) in &reservation.chunks {
+            if *offset != data.len() as u64 {
+                return MockResponse::service_error(
+                    StatusCode::BAD_REQUEST,
+                    "ArgumentException",
+                    "uploaded chunks are not contiguous".to_owned(),
+                );
+            }
+            data.extend_from_slice(chunk);
+        }
+        if data.len() as u64 != size {
+            return MockResponse::service_error(
+                StatusCode::BAD_REQUEST,
+                "ArgumentException",
+                format!("expected {} bytes, but {} were uploaded", size, data.len()),
+            );
+        }
+
+        let scope = self.entries.scope().to_owned();
+        match self.entries.insert(
+            &scope,
+            &reservation.key_space,
+            &reservation.key,
+            data.into(),
+        ) {
+            Ok(()) => MockResponse::status(204),
+            Err(err) => MockResponse::service_error(
+                StatusCode::CONFLICT,
+                "ArtifactCacheItemAlreadyExistsException",
+                err.to_string(),
+            ),
+        }
+    }
+}
+
+fn upload(inner: &mut Inner, id: i64, request: &RecordedRequest) -> MockResponse {
+    let range = request
+        .header("content-range")
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.strip_suffix("/*"))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()?)));
+    let (start, end) = match range {
+        Some(range) => range,
+        None => return MockResponse::status(400),
+    };
+    if end < start || end - start + 1 != request.body.len() as u64 {
+        return MockResponse::status(400);
+    }
+    match inner.reservations.get_mut(&id) {
+        Some(reservation) => {
+            reservation.chunks.push((start, request.body.clone()));
+            MockResponse::status(204)
+        }
+        None => MockResponse::status(404),
+    }
+}
+
+impl Inner {
+    fn next_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
@@ -0,0 +1,79 @@
+//! A manually advanced clock.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{backend::BoxFuture, Clock};
+
+/// A [`Clock`] that only advances when told to, or when sleeping.
+///
+/// Sleeping returns immediately after advancing the clock by the requested duration, so retries
+/// and backoff run instantly while observing the same elapsed times as with a real clock. The
+/// total time slept is available using [`slept`][Self::slept].
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    system_start: SystemTime,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    elapsed: Duration,
+    slept: Duration,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock starting at the current time.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    /// Creates a clock whose wall-clock time starts at `system_start`.
+    pub fn starting_at(system_start: SystemTime) -> Self {
+        Self {
+            start: Instant::now(),
+            system_start,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Advances the clock.
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().elapsed += duration;
+    }
+
+    /// Returns the time the clock advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+
+    /// Returns the total time requested by calls to [`Clock::sleep`].
+    pub fn slept(&self) -> Duration {
+        self.state.lock().unwrap().slept
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += duration;
+        state.slept += duration;
+        Box::pin(async {})
+    }
+}
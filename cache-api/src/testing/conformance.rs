@@ -0,0 +1,173 @@
+//! Conformance checks for [`CacheBackend`] implementations.
+//!
+//! Each check exercises one aspect of the semantics of the cache service against a backend and
+//! panics with a descriptive message if the backend deviates, so they can be called directly
+//! from tests. [`run_all`] runs all checks.
+//!
+//! The checks store entries using fixed keys within the given key space. As entries cannot be
+//! overwritten, every run needs an empty key space, e.g. one containing a unique run id when
+//! testing against the actual service.
+use bytes::Bytes;
+
+use crate::{CacheBackend, MatchKind};
+
+/// Runs all conformance checks.
+pub async fn run_all(backend: &dyn CacheBackend, key_space: &str) {
+    miss(backend, key_space).await;
+    exact_match(backend, key_space).await;
+    prefix_match_precedence(backend, key_space).await;
+    key_order_precedence(backend, key_space).await;
+    key_space_isolation(backend, key_space).await;
+    conflict(backend, key_space).await;
+    empty_entry(backend, key_space).await;
+}
+
+async fn put(backend: &dyn CacheBackend, key_space: &str, key: &str, data: &'static [u8]) {
+    if let Err(err) = backend
+        .put_bytes(key_space, key, Bytes::from_static(data))
+        .await
+    {
+        panic!("storing {:?} failed: {}", key, err);
+    }
+}
+
+async fn get(
+    backend: &dyn CacheBackend,
+    key_space: &str,
+    keys: &[&str],
+) -> Option<(crate::CacheHit, Bytes)> {
+    match backend.get_bytes(key_space, keys).await {
+        Ok(result) => result,
+        Err(err) => panic!("looking up {:?} failed: {}", keys, err),
+    }
+}
+
+/// Checks that lookups without matching entries find nothing.
+pub async fn miss(backend: &dyn CacheBackend, key_space: &str) {
+    let found = get(backend, key_space, &["miss-", "miss"]).await;
+    assert!(
+        found.is_none(),
+        "lookup in an empty key space found {:?}",
+        found.map(|(hit, _)| hit.key)
+    );
+}
+
+/// Checks that an entry is found by its key, reported as exact match, with its content.
+pub async fn exact_match(backend: &dyn CacheBackend, key_space: &str) {
+    put(backend, key_space, "exact-a", b"exact").await;
+
+    let (hit, data) = get(backend, key_space, &["exact-a"])
+        .await
+        .expect("stored entry not found by its key");
+    assert_eq!(hit.key, "exact-a");
+    assert_eq!(
+        hit.match_kind,
+        MatchKind::Exact,
+        "match not reported as exact"
+    );
+    assert_eq!(&data[..], b"exact", "content differs from stored content");
+}
+
+/// Checks prefix matches: an exact match wins, otherwise the most recent matching entry.
+pub async fn prefix_match_precedence(backend: &dyn CacheBackend, key_space: &str) {
+    put(backend, key_space, "prefix-1", b"first").await;
+    put(backend, key_space, "prefix-2", b"second").await;
+
+    let (hit, data) = get(backend, key_space, &["prefix-3", "prefix-"])
+        .await
+        .expect("entry not found by prefix");
+    assert_eq!(
+        hit.key, "prefix-2",
+        "prefix match did not find newest entry"
+    );
+    assert_eq!(
+        hit.match_kind,
+        MatchKind::Prefix,
+        "prefix match reported as exact"
+    );
+    assert_eq!(&data[..], b"second");
+
+    let (hit, _) = get(backend, key_space, &["prefix-1", "prefix-"])
+        .await
+        .expect("entry not found by key");
+    assert_eq!(hit.key, "prefix-1", "exact match did not take precedence");
+    assert_eq!(hit.match_kind, MatchKind::Exact);
+
+    let (hit, _) = get(backend, key_space, &["prefix-"])
+        .await
+        .expect("entry not found by prefix");
+    assert_eq!(
+        hit.match_kind,
+        MatchKind::Prefix,
+        "a prefix of a key was reported as exact match"
+    );
+}
+
+/// Checks that keys are tried in order, even if a later key matches a more recent entry.
+pub async fn key_order_precedence(backend: &dyn CacheBackend, key_space: &str) {
+    put(backend, key_space, "order-a-1", b"a").await;
+    put(backend, key_space, "order-b-1", b"b").await;
+
+    let (hit, _) = get(backend, key_space, &["order-c", "order-a-", "order-b-"])
+        .await
+        .expect("entry not found by prefix");
+    assert_eq!(hit.key, "order-a-1", "keys were not tried in order");
+}
+
+/// Checks that entries are only found within their key space.
+pub async fn key_space_isolation(backend: &dyn CacheBackend, key_space: &str) {
+    put(backend, key_space, "isolated", b"isolated").await;
+
+    let other = format!("{}-other", key_space);
+    let found = get(backend, &other, &["isolated"]).await;
+    assert!(found.is_none(), "entry found using a different key space");
+}
+
+/// Checks that an existing entry is not overwritten.
+pub async fn conflict(backend: &dyn CacheBackend, key_space: &str) {
+    put(backend, key_space, "conflict", b"original").await;
+
+    let result = backend
+        .put_bytes(key_space, "conflict", Bytes::from_static(b"replaced"))
+        .await;
+    assert!(result.is_err(), "storing an existing key succeeded");
+
+    let (_, data) = get(backend, key_space, &["conflict"])
+        .await
+        .expect("entry lost after conflicting store");
+    assert_eq!(&data[..], b"original", "existing entry was overwritten");
+}
+
+/// Checks that an empty entry is either stored and restored as empty, or rejected entirely.
+pub async fn empty_entry(backend: &dyn CacheBackend, key_space: &str) {
+    let stored = backend
+        .put_bytes(key_space, "empty", Bytes::new())
+        .await
+        .is_ok();
+
+    let found = get(backend, key_space, &["empty"]).await;
+    match (stored, found) {
+        (true, Some((_, data))) => assert!(data.is_empty(), "empty entry restored with content"),
+        (true, None) => panic!("stored empty entry not found"),
+        (false, Some((hit, _))) => panic!("rejected empty entry found as {:?}", hit.key),
+        (false, None) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MockServer, InMemoryCache};
+
+    #[tokio::test]
+    async fn in_memory_cache_conforms() {
+        run_all(&InMemoryCache::new(), "conformance").await;
+    }
+
+    #[tokio::test]
+    async fn mock_server_conforms() {
+        let server = MockServer::start().await.unwrap();
+        let cache = server.client("conformance-test").unwrap();
+        run_all(&cache, "conformance").await;
+    }
+}
@@ -0,0 +1,218 @@
+//! Injection of failures into HTTP exchanges.
+use std::{
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use reqwest::{Body, Client, Request, Response};
+
+use crate::{backend::BoxFuture, Result, Transport};
+
+/// A failure injected by a [`FaultInjector`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Responds with `429 Too Many Requests` and a `Retry-After` header of the given seconds.
+    RateLimit {
+        /// The requested wait time in seconds.
+        retry_after: u64,
+    },
+    /// Responds with the given status, e.g. 500 or 503.
+    Status(u16),
+    /// Forwards the request, but fails reading the response body after half of it.
+    TruncatedBody,
+    /// Forwards the request after waiting, e.g. to simulate slow chunk uploads.
+    Delay(Duration),
+}
+
+struct Rule {
+    method: Option<String>,
+    path_contains: Option<String>,
+    skip: usize,
+    remaining: usize,
+    fault: Fault,
+}
+
+impl Rule {
+    fn matches(&self, request: &Request) -> bool {
+        self.method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(request.method().as_str()))
+            && self
+                .path_contains
+                .as_ref()
+                .is_none_or(|path| request.url().path().contains(&**path))
+    }
+}
+
+/// A [`Transport`] injecting failures into the requests of a client.
+///
+/// Rules are checked in the order they were added. The first rule matching a request that still
+/// has injections left applies its fault. Requests without a matching rule are forwarded
+/// unchanged to the wrapped transport, a plain [`reqwest::Client`] by default.
+///
+/// This works both against the real service and a [`MockServer`][super::MockServer].
+pub struct FaultInjector {
+    inner: Box<dyn Transport>,
+    rules: Mutex<Vec<Rule>>,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultInjector {
+    /// Creates an injector forwarding requests using a default client.
+    pub fn new() -> Self {
+        Self::wrap(Client::new())
+    }
+
+    /// Creates an injector forwarding requests using `inner`.
+    pub fn wrap(inner: impl Transport + 'static) -> Self {
+        Self {
+            inner: Box::new(inner),
+            rules: Mutex::default(),
+        }
+    }
+
+    /// Injects `fault` into the next `times` requests.
+    pub fn inject(self, fault: Fault, times: usize) -> Self {
+        self.rule(None, None, 0, fault, times)
+    }
+
+    /// Injects `fault` into `times` requests with the given method and a path containing
+    /// `path_contains`, after letting `skip` such requests pass.
+    ///
+    /// For example, `inject_matching("PATCH", "/caches/", 1, Fault::Status(500), 2)` lets the
+    /// first chunk upload succeed and fails the following two.
+    pub fn inject_matching(
+        self,
+        method: &str,
+        path_contains: &str,
+        skip: usize,
+        fault: Fault,
+        times: usize,
+    ) -> Self {
+        self.rule(
+            Some(method.to_owned()),
+            Some(path_contains.to_owned()),
+            skip,
+            fault,
+            times,
+        )
+    }
+
+    fn rule(
+        self,
+        method: Option<String>,
+        path_contains: Option<String>,
+        skip: usize,
+        fault: Fault,
+        times: usize,
+    ) -> Self {
+        self.rules.lock().unwrap().push(Rule {
+            method,
+            path_contains,
+            skip,
+            remaining: times,
+            fault,
+        });
+        self
+    }
+
+    /// Returns the number of faults not injected yet.
+    pub fn remaining(&self) -> usize {
+        self.rules
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|rule| rule.remaining)
+            .sum()
+    }
+
+    fn next_fault(&self, request: &Request) -> Option<Fault> {
+        let mut rules = self.rules.lock().unwrap();
+        let rule = rules
+            .iter_mut()
+            .find(|rule| rule.remaining > 0 && rule.matches(request))?;
+        if rule.skip > 0 {
+            rule.skip -= 1;
+            return None;
+        }
+        rule.remaining -= 1;
+        Some(rule.fault.clone())
+    }
+
+    async fn run(&self, request: Request) -> Result<Response> {
+        let fault = self.next_fault(&request);
+        tracing::debug!(?fault, url = %request.url().path(), "fault injection");
+        match fault {
+            None => self.inner.execute(request).await,
+            Some(Fault::RateLimit { retry_after }) => Ok(respond(
+                http::Response::builder()
+                    .status(429)
+                    .header(reqwest::header::RETRY_AFTER, retry_after),
+                Body::from(""),
+            )),
+            Some(Fault::Status(status)) => Ok(respond(
+                http::Response::builder().status(status),
+                Body::from(""),
+            )),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.execute(request).await
+            }
+            Some(Fault::TruncatedBody) => {
+                let response = self.inner.execute(request).await?;
+                let mut builder = http::Response::builder().status(response.status());
+                for (name, value) in response.headers() {
+                    builder = builder.header(name, value);
+                }
+                let mut body = response.bytes().await?;
+                let half = body.split_to(body.len() / 2);
+                Ok(respond(
+                    builder,
+                    Body::wrap_stream(Truncated { chunk: Some(half) }),
+                ))
+            }
+        }
+    }
+}
+
+impl Transport for FaultInjector {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        Box::pin(self.run(request))
+    }
+}
+
+fn respond(builder: http::response::Builder, body: Body) -> Response {
+    builder
+        .body(body)
+        .expect("injected responses are valid")
+        .into()
+}
+
+/// A body stream yielding a single chunk followed by an error.
+struct Truncated {
+    chunk: Option<Bytes>,
+}
+
+impl Stream for Truncated {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(Some(match self.chunk.take() {
+            Some(chunk) => Ok(chunk),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "injected truncated body",
+            )),
+        }))
+    }
+}
@@ -0,0 +1,284 @@
+//! Recording and replaying of HTTP exchanges.
+use std::{
+    collections::VecDeque,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, Request, Response, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BoxFuture,
+    digest::hex,
+    redact::{redact_url, REDACTED},
+    Error, Result, Transport,
+};
+
+/// Headers whose values are replaced in fixtures.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Contents of a fixture file.
+#[derive(Serialize, Deserialize, Default)]
+struct Fixture {
+    exchanges: Vec<Exchange>,
+}
+
+/// A single request and its response.
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    url: String,
+    request_headers: Vec<(String, String)>,
+    /// `None` for streamed request bodies, which are not recorded.
+    request_body: Option<FixtureBody>,
+    status: u16,
+    response_headers: Vec<(String, String)>,
+    response_body: FixtureBody,
+}
+
+/// A message body, stored as text where possible to keep fixtures readable.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FixtureBody {
+    Text(String),
+    Hex(String),
+}
+
+impl FixtureBody {
+    fn new(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(text) => Self::Text(redact_text(text)),
+            Err(_) => Self::Hex(hex(data)),
+        }
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Text(text) => Ok(text.as_bytes().to_vec()),
+            Self::Hex(hex) => (0..hex.len())
+                .step_by(2)
+                .map(|i| {
+                    hex.get(i..i + 2)
+                        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                        .ok_or_else(|| invalid_data("invalid hex body in fixture"))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Redacts URLs within a JSON body, like the signed `archiveLocation` of lookups.
+fn redact_text(text: &str) -> String {
+    fn redact_value(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(string) => {
+                if let Ok(url) = Url::parse(string) {
+                    *string = redact_url(&url).into();
+                }
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(redact_value),
+            serde_json::Value::Object(map) => map.values_mut().for_each(redact_value),
+            _ => (),
+        }
+    }
+
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => text.to_owned(),
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_owned()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.as_str().to_owned(), value)
+        })
+        .collect()
+}
+
+/// The part of a URL that must match during replay.
+fn match_target(url: &Url) -> String {
+    let url = redact_url(url);
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A [`Transport`] recording all exchanges to a fixture file for a [`ReplayTransport`].
+///
+/// Requests are sent using the wrapped transport, a plain [`reqwest::Client`] by default. The
+/// fixture file is rewritten after each exchange. `Authorization` and cookie headers are
+/// replaced and signatures and credentials are removed from URLs, including URLs within JSON
+/// bodies. Streamed request bodies are not recorded.
+pub struct RecordingTransport {
+    path: PathBuf,
+    inner: Box<dyn Transport>,
+    fixture: Mutex<Fixture>,
+}
+
+impl RecordingTransport {
+    /// Records exchanges to the file at `path`, sending requests using a default client.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::wrap(path, Client::new())
+    }
+
+    /// Records exchanges to the file at `path`, sending requests using `inner`.
+    pub fn wrap(path: impl Into<PathBuf>, inner: impl Transport + 'static) -> Self {
+        Self {
+            path: path.into(),
+            inner: Box::new(inner),
+            fixture: Mutex::default(),
+        }
+    }
+
+    async fn record(&self, request: Request) -> Result<Response> {
+        let method = request.method().to_string();
+        let url = redact_url(request.url()).to_string();
+        let request_headers = redact_headers(request.headers());
+        let request_body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(FixtureBody::new);
+
+        let response = self.inner.execute(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        let exchange = Exchange {
+            method,
+            url,
+            request_headers,
+            request_body,
+            status: status.as_u16(),
+            response_headers: redact_headers(&headers),
+            response_body: FixtureBody::new(&body),
+        };
+
+        {
+            let mut fixture = self.fixture.lock().unwrap();
+            fixture.exchanges.push(exchange);
+            let json = serde_json::to_vec_pretty(&*fixture).expect("fixtures are serializable");
+            std::fs::write(&self.path, json)?;
+        }
+
+        Ok(build_response(status.as_u16(), headers, body.to_vec())?)
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        Box::pin(self.record(request))
+    }
+}
+
+/// A [`Transport`] answering requests with the exchanges of a fixture file.
+///
+/// Requests must arrive in the recorded order and match the recorded method, path and query,
+/// compared after redaction. Hosts are ignored, so the client may use any cache URL. A request
+/// that does not match fails with an [`Error::Io`] error of kind
+/// [`InvalidData`][io::ErrorKind::InvalidData].
+pub struct ReplayTransport {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl ReplayTransport {
+    /// Loads the fixture file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let fixture: Fixture = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Self {
+            exchanges: Mutex::new(fixture.exchanges.into()),
+        })
+    }
+
+    /// Returns the number of recorded exchanges not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().len()
+    }
+
+    fn replay(&self, request: &Request) -> Result<Response> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let target = match_target(request.url());
+        let matches = exchanges.front().is_some_and(|exchange| {
+            exchange.method == request.method().as_str()
+                && Url::parse(&exchange.url).is_ok_and(|url| match_target(&url) == target)
+        });
+        if !matches {
+            return Err(Error::Io(invalid_data(match exchanges.front() {
+                Some(exchange) => format!(
+                    "unexpected request {} {}, expected {} {}",
+                    request.method(),
+                    target,
+                    exchange.method,
+                    exchange.url
+                ),
+                None => format!("unexpected request {} {}", request.method(), target),
+            })));
+        }
+
+        let exchange = exchanges.pop_front().unwrap();
+        let mut headers = HeaderMap::new();
+        for (name, value) in &exchange.response_headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| invalid_data("invalid header name in fixture"))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| invalid_data("invalid header value in fixture"))?;
+            headers.append(name, value);
+        }
+        Ok(build_response(
+            exchange.status,
+            headers,
+            exchange.response_body.to_bytes()?,
+        )?)
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        let result = self.replay(&request);
+        Box::pin(async move { result })
+    }
+}
+
+/// Builds a response from recorded parts.
+pub(crate) fn build_response(
+    status: u16,
+    headers: HeaderMap,
+    body: Vec<u8>,
+) -> io::Result<Response> {
+    let mut builder = http::Response::builder().status(status);
+    for (name, value) in &headers {
+        // The body is already decoded and its length may differ.
+        if name != reqwest::header::CONTENT_ENCODING && name != reqwest::header::TRANSFER_ENCODING {
+            builder = builder.header(name, value);
+        }
+    }
+    let response = builder
+        .body(body)
+        .map_err(|err| invalid_data(err.to_string()))?;
+    Ok(response.into())
+}
@@ -0,0 +1,133 @@
+//! Capturing of requests for snapshot tests.
+use std::{fmt, sync::Mutex};
+
+use bytes::Bytes;
+use reqwest::{Client, Request, Response};
+
+use crate::{backend::BoxFuture, redact::redact_url, Result, Transport};
+
+/// Headers left out of captured requests, as they hold credentials or vary between runs.
+const IGNORED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "host"];
+
+/// A request as captured by [`RequestCapture`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CapturedRequest {
+    /// The request method.
+    pub method: String,
+    /// The path and query of the request, with signatures redacted.
+    pub target: String,
+    /// The request headers, except for credentials, in the order they were set.
+    pub headers: Vec<(String, String)>,
+    /// The request body, `None` for streamed bodies.
+    pub body: Option<Bytes>,
+}
+
+/// Formats the request in a stable, HTTP-like text form suitable for snapshots.
+///
+/// Bodies are included if they are UTF-8, otherwise only their length is.
+impl fmt::Display for CapturedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", self.method, self.target)?;
+        for (name, value) in &self.headers {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        match &self.body {
+            None => writeln!(f, "\n<streamed body>"),
+            Some(body) if body.is_empty() => Ok(()),
+            Some(body) => match std::str::from_utf8(body) {
+                Ok(text) => writeln!(f, "\n{}", text),
+                Err(_) => writeln!(f, "\n<{} bytes of binary data>", body.len()),
+            },
+        }
+    }
+}
+
+/// A [`Transport`] capturing requests before forwarding them, for snapshot tests.
+///
+/// Requests are forwarded using the wrapped transport, a plain [`reqwest::Client`] by default,
+/// usually pointed at a [`MockServer`][super::MockServer]. Hosts are left out of captured
+/// requests, so snapshots do not depend on the port of the mock server. To inspect the captured
+/// requests while a client uses this transport, pass it wrapped in an `Arc`.
+pub struct RequestCapture {
+    inner: Box<dyn Transport>,
+    requests: Mutex<Vec<CapturedRequest>>,
+}
+
+impl Default for RequestCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestCapture {
+    /// Captures requests and forwards them using a default client.
+    pub fn new() -> Self {
+        Self::wrap(Client::new())
+    }
+
+    /// Captures requests and forwards them using `inner`.
+    pub fn wrap(inner: impl Transport + 'static) -> Self {
+        Self {
+            inner: Box::new(inner),
+            requests: Mutex::default(),
+        }
+    }
+
+    /// Returns the requests captured so far.
+    pub fn requests(&self) -> Vec<CapturedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the captured requests and forgets them, e.g. to capture the next operation.
+    pub fn take(&self) -> Vec<CapturedRequest> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+
+    /// Returns all captured requests formatted for a snapshot, separated by blank lines.
+    pub fn snapshot(&self) -> String {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn capture(&self, request: &Request) {
+        let url = redact_url(request.url());
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        let headers = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| !IGNORED_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_owned(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let body = match request.body() {
+            None => Some(Bytes::new()),
+            Some(body) => body.as_bytes().map(Bytes::copy_from_slice),
+        };
+        self.requests.lock().unwrap().push(CapturedRequest {
+            method: request.method().to_string(),
+            target,
+            headers,
+            body,
+        });
+    }
+}
+
+impl Transport for RequestCapture {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        self.capture(&request);
+        self.inner.execute(request)
+    }
+}
@@ -0,0 +1,30 @@
+//! Pluggable execution of HTTP requests.
+use reqwest::{Client, Request, Response};
+
+use crate::{backend::BoxFuture, Result};
+
+/// Executes the HTTP requests of a [`Cache`][crate::Cache].
+///
+/// By default, requests are sent using the client's own [`reqwest::Client`]. A custom transport
+/// set using [`Cache::with_transport`][crate::Cache::with_transport] can observe, modify or
+/// answer requests, e.g. for recording or replaying traffic in tests. Retries, error statuses
+/// and everything else are still handled by the cache client.
+///
+/// Transports are also implemented for `Arc<T>`, so a transport can be inspected while a client
+/// uses it.
+pub trait Transport: Send + Sync {
+    /// Executes a request and returns the response, regardless of its status.
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>>;
+}
+
+impl Transport for Client {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        Box::pin(async move { Ok(Client::execute(self, request).await?) })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+    fn execute(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+        (**self).execute(request)
+    }
+}
@@ -0,0 +1,193 @@
+//! Expiring entries before the cache service evicts them.
+//!
+//! GitHub only evicts entries that were not accessed for 7 days, so an entry restored by every
+//! run never expires. Restoring build state that old, or from an outdated toolchain, is a
+//! common source of broken builds. Entries stored through a [`TtlCache`] carry the time they
+//! were stored, so restores can treat old entries as misses.
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    backend::BoxFuture, digest, CacheBackend, CacheHit, Clock, MatchKind, Result, SystemClock,
+};
+
+/// Identifies the format of the header of entries, changed for incompatible changes.
+const MAGIC: &[u8; 4] = b"RAT1";
+
+const HEADER_LEN: usize = MAGIC.len() + 16;
+
+/// A [`CacheBackend`] treating entries older than their time to live or a maximal age as
+/// misses.
+///
+/// Entries are stored with a header holding the time they were stored and their time to live,
+/// if set using [`with_ttl`][Self::with_ttl]. Gets skip matches that expired or are older than
+/// the maximal age set using [`with_max_age`][Self::with_max_age], and continue with the next
+/// key. Lookups need to get each match to check its age.
+///
+/// As entries can't be replaced, an expired entry keeps its key occupied. Keys of entries
+/// expected to expire should change over time, e.g. by including the week they were stored
+/// in, with restore keys falling back to older ones.
+///
+/// Entries are stored in key spaces derived from the given ones, so entries without a header
+/// are not found.
+pub struct TtlCache<B> {
+    inner: B,
+    ttl: Option<Duration>,
+    max_age: Option<Duration>,
+    clock: Arc<dyn Clock>,
+}
+
+/// The header of an entry stored by a [`TtlCache`].
+struct Header {
+    stored_at: SystemTime,
+    ttl: Option<Duration>,
+}
+
+impl<B: CacheBackend> TtlCache<B> {
+    /// Wraps `inner`, storing entries without a time to live and restoring entries of any age.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            ttl: None,
+            max_age: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the time to live of stored entries, after which restores treat them as misses.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Treats entries stored longer ago than `max_age` as misses, regardless of their time to
+    /// live.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Uses the given clock for the time entries are stored at and for their age.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn key_space(key_space: &str) -> String {
+        digest::sha256_hex(format!("{}\nttl", key_space).as_bytes())
+    }
+
+    /// Returns whether an entry with the given header should still be restored.
+    fn is_fresh(&self, header: &Header) -> bool {
+        let age = self
+            .clock
+            .system_now()
+            .duration_since(header.stored_at)
+            .unwrap_or_default();
+        header.ttl.is_none_or(|ttl| age <= ttl) && self.max_age.is_none_or(|max| age <= max)
+    }
+}
+
+impl Header {
+    fn encode(&self, data: &[u8]) -> Bytes {
+        let stored_at = self
+            .stored_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut entry = BytesMut::with_capacity(HEADER_LEN + data.len());
+        entry.put_slice(MAGIC);
+        entry.put_u64_le(stored_at.as_secs());
+        entry.put_u64_le(self.ttl.map_or(0, |ttl| ttl.as_secs().max(1)));
+        entry.put_slice(data);
+        entry.freeze()
+    }
+
+    fn decode(mut entry: Bytes) -> Result<(Self, Bytes)> {
+        if entry.len() < HEADER_LEN || !entry.starts_with(MAGIC) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "entry has no valid expiry header",
+            )
+            .into());
+        }
+        let header = entry.split_to(HEADER_LEN);
+        let field = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let ttl = field(MAGIC.len() + 8);
+        Ok((
+            Self {
+                stored_at: SystemTime::UNIX_EPOCH + Duration::from_secs(field(MAGIC.len())),
+                ttl: (ttl != 0).then(|| Duration::from_secs(ttl)),
+            },
+            entry,
+        ))
+    }
+}
+
+impl<B: CacheBackend> CacheBackend for TtlCache<B> {
+    fn lookup<'a>(
+        &'a self,
+        key_space: &'a str,
+        key_prefixes: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<CacheHit>>> {
+        Box::pin(async move {
+            Ok(self
+                .get_bytes(key_space, key_prefixes)
+                .await?
+                .map(|(hit, _)| hit))
+        })
+    }
+
+    fn get_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        keys: &'a [&'a str],
+    ) -> BoxFuture<'a, Result<Option<(CacheHit, Bytes)>>> {
+        Box::pin(async move {
+            let inner_key_space = Self::key_space(key_space);
+            for key in keys {
+                let Some((mut hit, entry)) = self.inner.get_bytes(&inner_key_space, &[key]).await?
+                else {
+                    continue;
+                };
+                let (header, data) = Header::decode(entry)?;
+                if !self.is_fresh(&header) {
+                    tracing::debug!(key = hit.key, "skipping expired entry");
+                    continue;
+                }
+                hit.match_kind = if keys.first() == Some(&&*hit.key) {
+                    MatchKind::Exact
+                } else {
+                    MatchKind::Prefix
+                };
+                return Ok(Some((hit, data)));
+            }
+            Ok(None)
+        })
+    }
+
+    fn put_bytes<'a>(
+        &'a self,
+        key_space: &'a str,
+        key: &'a str,
+        data: Bytes,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let header = Header {
+                stored_at: self.clock.system_now(),
+                ttl: self.ttl,
+            };
+            self.inner
+                .put_bytes(&Self::key_space(key_space), key, header.encode(&data))
+                .await
+        })
+    }
+}
@@ -0,0 +1,224 @@
+//! Command line parsing.
+use std::{ffi::OsString, fmt, path::PathBuf};
+
+/// Key space used unless `--key-space` is given.
+pub const DEFAULT_KEY_SPACE: &str =
+    "9796546c64ab15ab7468b479f3b3c20d5840af05ac0f999ad7a089512d01572e";
+
+pub const USAGE: &str = "\
+Usage: gha-cache [OPTIONS] <COMMAND>
+
+Commands:
+  get <KEY> [RESTORE_KEY]...     Write the content of a matching entry to stdout
+  put <KEY> [FILE]               Store the content of FILE, or stdin, as an entry
+  exists <KEY> [RESTORE_KEY]...  Print the key of a matching entry
+  url <KEY> [RESTORE_KEY]...     Print the download URL of a matching entry
+  save --path <PATH>... --key <KEY>
+                                 Archive the given paths and store them as an entry
+  restore --path <PATH>... --key <KEY> [--restore-key <RESTORE_KEY>]...
+                                 Restore the given paths from a matching entry
+
+Options:
+  -s, --key-space <KEY_SPACE>      Key space (version) of the entries
+  -o, --output <FILE>              Write the content of `get` to FILE instead of stdout
+  -p, --path <PATH>                Path or glob pattern to save or restore
+  -k, --key <KEY>                  Key to save or restore
+  -r, --restore-key <RESTORE_KEY>  Key prefix to restore from when KEY has no entry
+  -h, --help                       Print this help
+  -V, --version                    Print the version
+
+Restore keys are key prefixes tried in order when no entry has exactly the given key.
+
+Like the `path` input of actions/cache, paths may span several lines, support glob patterns,
+exclude files with a leading `!` and expand a leading `~`. The key space of `save` and
+`restore` is derived from the paths, so restoring requires the same paths used for saving.
+When run as a step, `restore` sets the `cache-hit` and `cache-matched-key` outputs.
+
+The cache service's URL and token are read from ACTIONS_CACHE_URL and ACTIONS_RUNTIME_TOKEN.
+These are only set for actions, `run` steps need to have them exported by an action first.
+
+Exit status:
+  0  The entry was found or stored
+  1  No entry matched, an entry with the key already exists, or none of the paths exist
+  2  Invalid arguments
+  3  Any other error";
+
+const COMMANDS: &[&str] = &["get", "put", "exists", "url", "save", "restore"];
+
+/// The parsed command line.
+#[derive(Debug)]
+pub enum Args {
+    Help,
+    Version,
+    Run { key_space: String, command: Command },
+}
+
+/// A subcommand with its arguments.
+#[derive(Debug)]
+pub enum Command {
+    Get {
+        keys: Vec<String>,
+        output: Option<PathBuf>,
+    },
+    Put {
+        key: String,
+        input: Option<PathBuf>,
+    },
+    Exists {
+        keys: Vec<String>,
+    },
+    Url {
+        keys: Vec<String>,
+    },
+    Save {
+        paths: Vec<String>,
+        key: String,
+    },
+    Restore {
+        paths: Vec<String>,
+        keys: Vec<String>,
+    },
+}
+
+/// Invalid command line arguments.
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+fn usage_error(message: impl Into<String>) -> UsageError {
+    UsageError(message.into())
+}
+
+impl Args {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self, UsageError> {
+        let mut args = args.into_iter();
+        let mut key_space = None;
+        let mut output = None;
+        let mut paths = vec![];
+        let mut key = None;
+        let mut restore_keys = vec![];
+        let mut positional = vec![];
+
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            let arg = arg
+                .into_string()
+                .map_err(|arg| usage_error(format!("invalid argument {:?}", arg)))?;
+            if options_done || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg);
+                continue;
+            }
+            if arg == "--" {
+                options_done = true;
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+                _ => (&*arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next()?.into_string().ok())
+                    .ok_or_else(|| usage_error(format!("missing value for {}", name)))
+            };
+            match name {
+                "-h" | "--help" => return Ok(Self::Help),
+                "-V" | "--version" => return Ok(Self::Version),
+                "-s" | "--key-space" => key_space = Some(value()?),
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                "-p" | "--path" => paths.push(value()?),
+                "-k" | "--key" => key = Some(value()?),
+                "-r" | "--restore-key" => restore_keys.push(value()?),
+                _ => return Err(usage_error(format!("unknown option {}", name))),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let command = positional
+            .next()
+            .ok_or_else(|| usage_error("missing command"))?;
+        if !COMMANDS.contains(&&*command) {
+            return Err(usage_error(format!("unknown command {}", command)));
+        }
+        let mut keys: Vec<String> = positional.collect();
+
+        if let "save" | "restore" = &*command {
+            if let Some(arg) = keys.first() {
+                return Err(usage_error(format!("unexpected argument {}", arg)));
+            }
+            if key_space.is_some() || output.is_some() {
+                return Err(usage_error(format!(
+                    "--key-space and --output are not supported by {}",
+                    command
+                )));
+            }
+            if paths.is_empty() {
+                return Err(usage_error(format!("missing --path for {}", command)));
+            }
+            let key = key.ok_or_else(|| usage_error(format!("missing --key for {}", command)))?;
+            let command = if command == "save" {
+                if !restore_keys.is_empty() {
+                    return Err(usage_error("--restore-key is only supported by restore"));
+                }
+                Command::Save { paths, key }
+            } else {
+                Command::Restore {
+                    paths,
+                    keys: std::iter::once(key).chain(restore_keys).collect(),
+                }
+            };
+            // Unused, the snapshot's key space is derived from the paths.
+            return Ok(Self::Run {
+                key_space: DEFAULT_KEY_SPACE.to_owned(),
+                command,
+            });
+        }
+
+        if !paths.is_empty() || key.is_some() || !restore_keys.is_empty() {
+            return Err(usage_error(format!(
+                "--path, --key and --restore-key are not supported by {}",
+                command
+            )));
+        }
+        if keys.is_empty() {
+            return Err(usage_error(format!("missing key for {}", command)));
+        }
+
+        let command = match &*command {
+            "get" => Command::Get {
+                keys,
+                output: output.take(),
+            },
+            "put" => {
+                if keys.len() > 2 {
+                    return Err(usage_error("put takes a key and at most one file"));
+                }
+                let input = keys.get(1).filter(|input| *input != "-").map(PathBuf::from);
+                Command::Put {
+                    key: keys.remove(0),
+                    input,
+                }
+            }
+            "exists" => Command::Exists { keys },
+            "url" => Command::Url { keys },
+            _ => unreachable!(),
+        };
+        if output.is_some() {
+            return Err(usage_error("--output is only supported by get"));
+        }
+
+        Ok(Self::Run {
+            key_space: key_space.unwrap_or_else(|| DEFAULT_KEY_SPACE.to_owned()),
+            command,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+//! Command line client for the GitHub Actions Cache API.
+mod args;
+mod paths;
+
+use std::process::ExitCode;
+
+use args::{Args, Command, USAGE};
+use rust_actions_cache_api::{Cache, Error, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing_subscriber::EnvFilter;
+
+const EXIT_NOT_FOUND: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_ERROR: u8 = 3;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    let (key_space, command) = match Args::parse(std::env::args_os().skip(1)) {
+        Ok(Args::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Args::Version) => {
+            println!("gha-cache {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Ok(Args::Run { key_space, command }) => (key_space, command),
+        Err(err) => {
+            eprintln!("gha-cache: {}\nRun `gha-cache --help` for usage.", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match run(&key_space, command).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_NOT_FOUND),
+        Err(err) => {
+            eprintln!("gha-cache: {}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Runs a command, returning whether the entry was found or stored.
+async fn run(key_space: &str, command: Command) -> Result<bool> {
+    let cache = Cache::new(concat!("gha-cache/", env!("CARGO_PKG_VERSION")))?;
+
+    match command {
+        Command::Get { keys, output } => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let Some((_, data)) = cache.get_bytes(key_space, &keys).await? else {
+                eprintln!("gha-cache: no matching entry");
+                return Ok(false);
+            };
+            match output {
+                Some(output) => tokio::fs::write(output, &data).await?,
+                None => {
+                    let mut stdout = tokio::io::stdout();
+                    stdout.write_all(&data).await?;
+                    stdout.flush().await?;
+                }
+            }
+        }
+        Command::Put { key, input } => {
+            let result = match input {
+                Some(input) => cache.put_file(key_space, &key, &input).await,
+                None => {
+                    let mut data = vec![];
+                    tokio::io::stdin().read_to_end(&mut data).await?;
+                    cache.put_bytes(key_space, &key, data.into()).await
+                }
+            };
+            match result {
+                Ok(_) => {}
+                Err(Error::Conflict(_)) => {
+                    eprintln!("gha-cache: an entry with key {:?} already exists", key);
+                    return Ok(false);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Command::Exists { keys } => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let Some((hit, _)) = cache.get_url(key_space, &keys).await? else {
+                return Ok(false);
+            };
+            println!("{}", hit.key);
+        }
+        Command::Url { keys } => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let Some((_, url)) = cache.get_url(key_space, &keys).await? else {
+                return Ok(false);
+            };
+            println!("{}", url);
+        }
+        Command::Save { paths, key } => match paths::snapshot(&paths)?.save(&cache, &key).await {
+            Ok(size) => eprintln!("gha-cache: saved {} bytes as {:?}", size, key),
+            Err(Error::Conflict(_)) => {
+                eprintln!("gha-cache: an entry with key {:?} already exists", key);
+                return Ok(false);
+            }
+            Err(Error::EmptySnapshot) => {
+                eprintln!("gha-cache: none of the paths exist, not saving");
+                return Ok(false);
+            }
+            Err(err) => return Err(err),
+        },
+        Command::Restore { paths, keys } => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let hit = paths::snapshot(&paths)?.restore(&cache, &keys).await?;
+            if std::env::var_os("GITHUB_OUTPUT").is_some() {
+                let exact = hit.as_ref().is_some_and(|hit| hit.is_exact());
+                rust_actions::files::set_output("cache-hit", &exact.to_string())?;
+                let matched = hit.as_ref().map_or("", |hit| &*hit.key);
+                rust_actions::files::set_output("cache-matched-key", matched)?;
+            }
+            let Some(hit) = hit else {
+                eprintln!("gha-cache: no matching entry");
+                return Ok(false);
+            };
+            println!("{}", hit.key);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,98 @@
+//! Turning the `--path` arguments of `save` and `restore` into a snapshot.
+use std::path::{Component, Path, PathBuf};
+
+use rust_actions_cache_api::{snapshot::Snapshot, Result};
+
+/// Returns a snapshot of the given paths or glob patterns.
+///
+/// Like the `path` input of the official cache action, each argument may contain several
+/// patterns on separate lines, and patterns starting with `!` exclude files. A leading `~` is
+/// expanded to the home directory. All paths are archived relative to their closest common
+/// ancestor directory, so paths outside the current directory, like Cargo's registry, can be
+/// part of the same entry. Glob patterns without a common directory other than `/` are rejected,
+/// as restoring them would need to write to `/`.
+pub fn snapshot(args: &[String]) -> Result<Snapshot> {
+    let current_dir = std::env::current_dir()?;
+    let mut patterns = vec![];
+    for line in args.iter().flat_map(|arg| arg.lines()) {
+        let line = line.trim();
+        let pattern = line.trim_start_matches('!');
+        if pattern.is_empty() {
+            continue;
+        }
+        let negated = (line.len() - pattern.len()) % 2 == 1;
+        patterns.push((negated, normalize(&current_dir.join(expand_home(pattern)))));
+    }
+
+    let mut root: Option<PathBuf> = None;
+    for (_, pattern) in patterns.iter().filter(|(negated, _)| !negated) {
+        let literal: PathBuf = pattern
+            .components()
+            .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+            .collect();
+        let parent = literal.parent().unwrap_or(&literal);
+        root = Some(match root {
+            None => parent.to_owned(),
+            Some(root) => root
+                .components()
+                .zip(parent.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    let root = root.unwrap_or(current_dir);
+
+    let literal = patterns
+        .iter()
+        .all(|(negated, pattern)| !negated && !is_glob(&pattern.to_string_lossy()));
+    let snapshot = if literal {
+        Snapshot::new(patterns.into_iter().map(|(_, pattern)| pattern))
+    } else if root.parent().is_none() {
+        // Restoring extracts the matching files next to the root, which must therefore be
+        // writable. Literal paths are each restored next to themselves instead.
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "the glob patterns have no common directory other than {}, use separate \
+                 entries for patterns in unrelated directories",
+                root.display()
+            ),
+        )
+        .into());
+    } else {
+        Snapshot::from_patterns(patterns.into_iter().map(|(negated, pattern)| {
+            let pattern = pattern.strip_prefix(&root).unwrap_or(&pattern);
+            format!("{}{}", if negated { "!" } else { "" }, pattern.display())
+        }))
+    };
+    Ok(snapshot.with_root(root))
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Removes `.` and `..` components without accessing the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}